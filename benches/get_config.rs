@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! Measures `ServerConfigProvider::get_config`, the per-accept read, from
+//! several threads while the config rotates every millisecond.
+//!
+//! Run with `cargo bench --bench get_config`.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rustls::{
+    ServerConfig,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use rustls_config_stream::{ServerConfigStreamBuilder, ServerConfigStreamError};
+use rustls_spiffe::ServerConfigProvider;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+const THREADS: usize = 8;
+const DURATION: Duration = Duration::from_secs(2);
+const ROTATION_INTERVAL: Duration = Duration::from_millis(1);
+
+type Item = Result<Arc<ServerConfig>, ServerConfigStreamError>;
+
+#[derive(Debug)]
+struct NoCert;
+
+impl ResolvesServerCert for NoCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        None
+    }
+}
+
+fn config() -> Arc<ServerConfig> {
+    Arc::new(
+        ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(NoCert)),
+    )
+}
+
+struct Builder(Option<mpsc::Receiver<Item>>);
+
+impl ServerConfigStreamBuilder for Builder {
+    type ConfigStream = ReceiverStream<Item>;
+
+    async fn build(&mut self) -> Result<Self::ConfigStream, ServerConfigStreamError> {
+        self.0
+            .take()
+            .map(ReceiverStream::new)
+            .ok_or(ServerConfigStreamError::EmptyStream)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(Ok(config())).await.expect("send initial config");
+    let provider = ServerConfigProvider::start(Builder(Some(rx)))
+        .await
+        .expect("start provider");
+
+    let rotate = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(ROTATION_INTERVAL).await;
+            if tx.send(Ok(config())).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let provider = provider.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut reads = 0_u64;
+                while !stop.load(Ordering::Relaxed) {
+                    std::hint::black_box(provider.get_config());
+                    reads += 1;
+                }
+                reads
+            })
+        })
+        .collect();
+
+    let start = Instant::now();
+    tokio::time::sleep(DURATION).await;
+    stop.store(true, Ordering::Relaxed);
+    let reads: u64 = readers
+        .into_iter()
+        .map(|reader| reader.join().expect("reader thread"))
+        .sum();
+    let elapsed = start.elapsed();
+    rotate.abort();
+
+    #[allow(clippy::cast_precision_loss)]
+    let per_second = reads as f64 / elapsed.as_secs_f64();
+    println!(
+        "get_config: {reads} reads on {THREADS} threads in {elapsed:?} ({per_second:.0}/s, {:.1} ns/read/thread)",
+        elapsed.as_secs_f64() * 1e9 * THREADS as f64 / reads as f64
+    );
+}
@@ -0,0 +1,263 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    future::{self, Future},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{HeaderValue, Request, Response, StatusCode, header::CONTENT_TYPE};
+use spiffe::SpiffeId;
+use tower::{Layer, Service};
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::PeerIdentity;
+
+type Authorizer = Arc<dyn Fn(&SpiffeId) -> bool + Send + Sync>;
+
+/// A [`Layer`] rejecting requests unless the SPIFFE ID of the peer is
+/// authorized for the request path.
+///
+/// Each route is a path prefix with an authorizer; the longest prefix
+/// matching the path decides, and requests matching no route are rejected.
+/// For gRPC, prefix routes with the service, e.g. `/example.Greeter/`.
+///
+/// Prefixes match the raw request path, without normalization: a request
+/// for `/public/../admin/` is decided by the `/public/` route. Normalize
+/// paths before this layer if the inner service resolves them.
+/// Rejected HTTP requests get `403 Forbidden`, and gRPC requests the
+/// `PERMISSION_DENIED` status.
+///
+/// The peer is read from the [`PeerIdentity`] inserted by a
+/// [`PeerIdentityLayer`](crate::PeerIdentityLayer) or, with the `axum` or
+/// `tonic` feature, from the `PeerInfo` of a connection accepted by
+/// `TlsIncoming`.
+///
+/// ```rust
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use rustls_spiffe::SpiffeAuthorizationLayer;
+/// use spiffe::TrustDomain;
+///
+/// let trust_domain = TrustDomain::new("example.org")?;
+/// let authorization = SpiffeAuthorizationLayer::new()
+///     .with_route_ids("/admin/", vec!["spiffe://example.org/operator".try_into()?])
+///     .with_route("/", move |id| *id.trust_domain() == trust_domain);
+/// // Router::new().route(...).layer(authorization)
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct SpiffeAuthorizationLayer {
+    routes: Arc<Vec<(String, Authorizer)>>,
+}
+
+impl SpiffeAuthorizationLayer {
+    /// Reject every request until routes are added.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow requests whose path starts with `prefix` from peers `authorize`
+    /// returns `true` for, unless a longer prefix matches.
+    #[must_use]
+    pub fn with_route(
+        mut self,
+        prefix: impl Into<String>,
+        authorize: impl Fn(&SpiffeId) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.routes).push((prefix.into(), Arc::new(authorize)));
+        self
+    }
+
+    /// Allow requests whose path starts with `prefix` from any peer in
+    /// `ids`; a shorthand for [`with_route`](Self::with_route).
+    #[must_use]
+    pub fn with_route_ids(self, prefix: impl Into<String>, ids: Vec<SpiffeId>) -> Self {
+        self.with_route(prefix, move |id| ids.contains(id))
+    }
+
+    fn authorizes(&self, path: &str, spiffe_id: Option<&SpiffeId>) -> bool {
+        let Some(spiffe_id) = spiffe_id else {
+            return false;
+        };
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .is_some_and(|(_, authorize)| authorize(spiffe_id))
+    }
+}
+
+impl<S> Layer<S> for SpiffeAuthorizationLayer {
+    type Service = SpiffeAuthorization<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SpiffeAuthorization {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service of a [`SpiffeAuthorizationLayer`].
+#[derive(Clone)]
+pub struct SpiffeAuthorization<S> {
+    inner: S,
+    layer: SpiffeAuthorizationLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SpiffeAuthorization<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let spiffe_id = peer_spiffe_id(&request);
+        if self
+            .layer
+            .authorizes(request.uri().path(), spiffe_id.as_ref())
+        {
+            return Box::pin(self.inner.call(request));
+        }
+
+        #[cfg(feature = "tracing")]
+        debug!(
+            name: "spiffe_authorization",
+            path = request.uri().path(),
+            spiffe_id = ?spiffe_id,
+            "rejecting unauthorized request"
+        );
+
+        Box::pin(future::ready(Ok(permission_denied(&request))))
+    }
+}
+
+fn peer_spiffe_id<B>(request: &Request<B>) -> Option<SpiffeId> {
+    let extensions = request.extensions();
+    if let Some(identity) = extensions.get::<PeerIdentity>() {
+        return match identity {
+            PeerIdentity::Authenticated(spiffe_id) => Some(spiffe_id.clone()),
+            _ => None,
+        };
+    }
+    #[cfg(feature = "tonic")]
+    if let Some(info) = extensions.get::<crate::PeerInfo>() {
+        return info.spiffe_id.clone();
+    }
+    #[cfg(feature = "axum")]
+    if let Some(axum::extract::ConnectInfo(info)) =
+        extensions.get::<axum::extract::ConnectInfo<crate::PeerInfo>>()
+    {
+        return info.spiffe_id.clone();
+    }
+    None
+}
+
+/// A `403 Forbidden` response, or a `PERMISSION_DENIED` status for gRPC.
+fn permission_denied<ReqBody, ResBody: Default>(request: &Request<ReqBody>) -> Response<ResBody> {
+    let grpc = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/grpc"));
+    let mut response = Response::new(ResBody::default());
+    if grpc {
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        headers.insert("grpc-status", HeaderValue::from_static("7"));
+        headers.insert(
+            "grpc-message",
+            HeaderValue::from_static("peer SPIFFE ID is not authorized"),
+        );
+    } else {
+        *response.status_mut() = StatusCode::FORBIDDEN;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderValue, Request, Response, StatusCode, header::CONTENT_TYPE};
+    use spiffe::SpiffeId;
+
+    use super::{SpiffeAuthorizationLayer, peer_spiffe_id, permission_denied};
+    use crate::{PeerIdentity, SourceError};
+
+    fn layer() -> Result<SpiffeAuthorizationLayer, SourceError> {
+        Ok(SpiffeAuthorizationLayer::new()
+            .with_route_ids(
+                "/admin/",
+                vec![SpiffeId::new("spiffe://example.org/operator")?],
+            )
+            .with_route("/", |id| id.trust_domain().to_string() == "example.org"))
+    }
+
+    #[test]
+    fn the_longest_prefix_decides() -> Result<(), SourceError> {
+        let layer = layer()?;
+        let operator = SpiffeId::new("spiffe://example.org/operator")?;
+        let workload = SpiffeId::new("spiffe://example.org/workload")?;
+
+        assert!(layer.authorizes("/admin/users", Some(&operator)));
+        assert!(!layer.authorizes("/admin/users", Some(&workload)));
+        assert!(layer.authorizes("/status", Some(&workload)));
+        Ok(())
+    }
+
+    #[test]
+    fn paths_matching_no_route_are_rejected() -> Result<(), SourceError> {
+        let layer = SpiffeAuthorizationLayer::new().with_route("/public/", |_| true);
+        let workload = SpiffeId::new("spiffe://example.org/workload")?;
+
+        assert!(layer.authorizes("/public/index.html", Some(&workload)));
+        assert!(layer.authorizes("/public/../private/", Some(&workload)));
+        assert!(!layer.authorizes("/private/", Some(&workload)));
+        assert!(!SpiffeAuthorizationLayer::new().authorizes("/", Some(&workload)));
+        Ok(())
+    }
+
+    #[test]
+    fn peers_without_a_spiffe_id_are_rejected() {
+        let layer = SpiffeAuthorizationLayer::new().with_route("/", |_| true);
+        let mut request = Request::new(());
+        assert_eq!(peer_spiffe_id(&request), None);
+        request.extensions_mut().insert(PeerIdentity::Unidentified);
+        assert_eq!(peer_spiffe_id(&request), None);
+
+        assert!(!layer.authorizes("/", peer_spiffe_id(&request).as_ref()));
+    }
+
+    #[test]
+    fn grpc_requests_get_permission_denied() -> Result<(), SourceError> {
+        let request = Request::builder()
+            .header(CONTENT_TYPE, "application/grpc+proto")
+            .body(())?;
+
+        let response: Response<()> = permission_denied(&request);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("grpc-status"),
+            Some(&HeaderValue::from_static("7"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn http_requests_get_forbidden() {
+        let response: Response<()> = permission_denied(&Request::new(()));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers().get("grpc-status"), None);
+    }
+}
@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::time::SystemTime;
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, connect_info::Connected},
+    http::{StatusCode, request::Parts},
+    serve::IncomingStream,
+};
+use spiffe::{SpiffeId, TrustDomain};
+
+use crate::{PeerInfo, TlsIncoming, extract_not_after};
+
+impl Connected<IncomingStream<'_, TlsIncoming>> for PeerInfo {
+    fn connect_info(stream: IncomingStream<'_, TlsIncoming>) -> Self {
+        stream.io().peer_info().clone()
+    }
+}
+
+/// An axum extractor for the X509-SVID the peer presented when its
+/// connection was accepted by [`TlsIncoming`].
+///
+/// The router must be served with its [`PeerInfo`] as connect info.
+/// Requests from peers without an SVID are rejected with
+/// `403 Forbidden`.
+///
+/// ```rust
+/// # async fn example(incoming: rustls_spiffe::TlsIncoming) -> std::io::Result<()> {
+/// use axum::{Router, routing::get};
+/// use rustls_spiffe::{PeerInfo, SpiffePeer};
+///
+/// async fn whoami(peer: SpiffePeer) -> String {
+///     peer.spiffe_id.to_string()
+/// }
+///
+/// let router = Router::new().route("/whoami", get(whoami));
+/// axum::serve(
+///     incoming,
+///     router.into_make_service_with_connect_info::<PeerInfo>(),
+/// )
+/// .await
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SpiffePeer {
+    /// The SPIFFE ID of the peer.
+    pub spiffe_id: SpiffeId,
+    /// The trust domain of the peer.
+    pub trust_domain: TrustDomain,
+    /// When the peer's X509-SVID expires.
+    pub expires_at: SystemTime,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SpiffePeer {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(info)) = parts.extensions.get::<ConnectInfo<PeerInfo>>() else {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "the router is not served with PeerInfo connect info",
+            ));
+        };
+        let leaf = info.certs.as_deref().and_then(<[_]>::first);
+        let (Some(spiffe_id), Some(expires_at)) = (info.spiffe_id.clone(), extract_not_after(leaf))
+        else {
+            return Err((StatusCode::FORBIDDEN, "the peer presented no X509-SVID"));
+        };
+        Ok(Self {
+            trust_domain: spiffe_id.trust_domain().clone(),
+            spiffe_id,
+            expires_at,
+        })
+    }
+}
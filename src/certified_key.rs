@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::sync::Arc;
+
+use rustls::{
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    sign::CertifiedKey,
+};
+use spiffe::{X509Bundle, X509BundleSet, X509Svid};
+#[cfg(feature = "tracing")]
+use tracing::debug;
+use x509_parser::prelude::GeneralName;
+
+/// The certificate chain of `svid`, leaf first.
+pub fn cert_chain(svid: &X509Svid) -> Vec<CertificateDer<'static>> {
+    svid.cert_chain()
+        .iter()
+        .map(|c| CertificateDer::from(c.content().to_owned()))
+        .collect()
+}
+
+/// The private key of `svid`, which is always PKCS#8: [`X509Svid`] rejects
+/// other encodings, so sources convert them with `der::pkcs8_private_key` first.
+pub fn private_key(svid: &X509Svid) -> PrivateKeyDer<'static> {
+    PrivateKeyDer::from(PrivatePkcs8KeyDer::from(
+        svid.private_key().content().to_owned(),
+    ))
+}
+
+/// Build a [`CertifiedKey`] for `svid`, loading the private key with
+/// `provider`.
+///
+/// With `bundles`, intermediates missing from the SVID chain are completed
+/// from the bundle of the SVID's trust domain.
+pub fn certified_key(
+    svid: &X509Svid,
+    bundles: Option<&X509BundleSet>,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, rustls::Error> {
+    let mut chain = cert_chain(svid);
+    if let Some(bundle) = bundles.and_then(|b| b.get_bundle(svid.spiffe_id().trust_domain())) {
+        complete_chain(&mut chain, bundle);
+    }
+    CertifiedKey::from_der(chain, private_key(svid), provider)
+}
+
+/// A callback returning the DER OCSP response to staple for an SVID.
+pub type OcspResponder = Arc<dyn Fn(&X509Svid) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Staple the OCSP response `ocsp` returns for `svid` to `key`, if any.
+pub fn staple(
+    mut key: CertifiedKey,
+    svid: &X509Svid,
+    ocsp: Option<&OcspResponder>,
+) -> CertifiedKey {
+    key.ocsp = ocsp.and_then(|ocsp| ocsp(svid));
+    key
+}
+
+/// Append intermediates from `bundle` to `chain` until its last certificate is
+/// issued by a self-signed authority, or no issuer can be found.
+fn complete_chain(chain: &mut Vec<CertificateDer<'static>>, bundle: &X509Bundle) {
+    let authorities: Vec<_> = bundle
+        .authorities()
+        .iter()
+        .filter_map(|a| {
+            x509_parser::parse_x509_certificate(a.content())
+                .ok()
+                .map(|(_, cert)| (a.content(), cert))
+        })
+        .collect();
+    #[cfg(feature = "tracing")]
+    let len = chain.len();
+    while let Some(last) = chain.last() {
+        let Ok((_, cert)) = x509_parser::parse_x509_certificate(last) else {
+            break;
+        };
+        if cert.subject().as_raw() == cert.issuer().as_raw() {
+            break;
+        }
+        let Some((der, issuer)) = authorities
+            .iter()
+            .find(|(_, authority)| authority.subject().as_raw() == cert.issuer().as_raw())
+        else {
+            break;
+        };
+        // peers already hold the root, and a repeated issuer means a loop
+        if issuer.subject().as_raw() == issuer.issuer().as_raw()
+            || chain.iter().any(|c| c.as_ref() == *der)
+        {
+            break;
+        }
+        chain.push(CertificateDer::from(der.to_vec()));
+    }
+
+    #[cfg(feature = "tracing")]
+    if chain.len() > len {
+        debug!(
+            added = chain.len() - len,
+            "completed SVID chain from bundle"
+        );
+    }
+}
+
+/// The DNS subject alternative names of the leaf certificate of `svid`.
+pub fn dns_names(svid: &X509Svid) -> Vec<String> {
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(svid.leaf().content()) else {
+        return Vec::new();
+    };
+    let Ok(Some(san)) = cert.subject_alternative_name() else {
+        return Vec::new();
+    };
+    san.value
+        .general_names
+        .iter()
+        .filter_map(|gn| match gn {
+            GeneralName::DNSName(name) => Some((*name).to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use rustls::pki_types::CertificateDer;
+
+    use super::{cert_chain, complete_chain};
+    use crate::{
+        SourceError,
+        test_certs::{Ca, DAY},
+    };
+
+    const ID: &str = "spiffe://example.org/workload";
+
+    #[test]
+    fn completes_intermediates_up_to_the_root() -> Result<(), SourceError> {
+        let issuing = Ca::root("root")?
+            .intermediate("outer")?
+            .intermediate("inner")?;
+        let full = cert_chain(&issuing.svid(ID, DAY)?);
+        let mut chain = vec![full.first().ok_or("empty chain")?.clone()];
+
+        complete_chain(&mut chain, &issuing.bundle("example.org", true)?);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain, full);
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_complete_chains_alone() -> Result<(), SourceError> {
+        let issuing = Ca::root("root")?.intermediate("intermediate")?;
+        let full = cert_chain(&issuing.svid(ID, DAY)?);
+        let mut chain = full.clone();
+
+        complete_chain(&mut chain, &issuing.bundle("example.org", true)?);
+        assert_eq!(chain, full);
+        Ok(())
+    }
+
+    #[test]
+    fn stops_without_a_known_issuer() -> Result<(), SourceError> {
+        let issuing = Ca::root("root")?.intermediate("intermediate")?;
+        let leaf: CertificateDer<'static> = cert_chain(&issuing.svid(ID, DAY)?)
+            .into_iter()
+            .next()
+            .ok_or("empty chain")?;
+        let mut chain = vec![leaf.clone()];
+
+        complete_chain(&mut chain, &issuing.bundle("example.org", false)?);
+        assert_eq!(chain, vec![leaf]);
+        Ok(())
+    }
+}
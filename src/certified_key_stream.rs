@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use rustls::{ServerConfig, crypto::CryptoProvider, sign::CertifiedKey};
+use spiffe::{SpiffeId, X509Context};
+use tokio_stream::Stream;
+
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::{
+    SpiffeConfigError,
+    certified_key::certified_key,
+    svid_selector::SvidSelector,
+    svid_source::{SvidSource, WorkloadApiSource, X509ContextStream},
+};
+
+/// Builder for a [`SpiffeCertifiedKeyStream`].
+pub struct SpiffeCertifiedKeyStreamBuilder {
+    svid: SvidSelector,
+    provider: Option<Arc<CryptoProvider>>,
+    source: Arc<dyn SvidSource>,
+}
+
+impl Default for SpiffeCertifiedKeyStreamBuilder {
+    fn default() -> Self {
+        Self {
+            svid: SvidSelector::Default,
+            provider: None,
+            source: Arc::new(WorkloadApiSource::new()),
+        }
+    }
+}
+
+impl SpiffeCertifiedKeyStreamBuilder {
+    /// Use the SVID with the given SPIFFE ID instead of the default SVID.
+    ///
+    /// If an update no longer contains an SVID with this SPIFFE ID, the stream
+    /// yields [`SpiffeConfigError::SvidNotFound`].
+    #[must_use]
+    pub fn with_svid_id(mut self, spiffe_id: SpiffeId) -> Self {
+        self.svid = SvidSelector::SpiffeId(spiffe_id);
+        self
+    }
+
+    /// Load private keys with `provider` instead of the process-default
+    /// [`CryptoProvider`].
+    #[must_use]
+    pub fn with_crypto_provider(mut self, provider: Arc<CryptoProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Read SVIDs from `source` instead of the SPIFFE Workload API.
+    #[must_use]
+    pub fn with_svid_source(mut self, source: impl SvidSource) -> Self {
+        self.source = Arc::new(source);
+        self
+    }
+
+    /// Connect to the SVID source and start streaming certified keys.
+    ///
+    /// # Errors
+    /// [`SpiffeConfigError::Source`] if the SVID source cannot be connected.
+    pub async fn build(&self) -> Result<SpiffeCertifiedKeyStream, SpiffeConfigError> {
+        let inner = self
+            .source
+            .connect()
+            .await
+            .map_err(SpiffeConfigError::Source)?;
+        Ok(SpiffeCertifiedKeyStream {
+            inner,
+            svid: self.svid.clone(),
+            provider: self
+                .provider
+                .clone()
+                .unwrap_or_else(|| ServerConfig::builder().crypto_provider().clone()),
+        })
+    }
+}
+
+/// A stream that yields a [`CertifiedKey`] for the workload SVID on every
+/// SPIFFE Workload API update.
+///
+/// This is the building block for users who assemble their own
+/// [`ServerConfig`] or [`rustls::ClientConfig`], for example with a custom
+/// certificate resolver, but want this crate to handle the Workload API,
+/// key parsing and rotation.
+///
+/// # Behavior
+///
+/// * SVID source errors are yielded as [`SpiffeConfigError::Source`].
+/// * An update without the selected SVID yields
+///   [`SpiffeConfigError::MissingSvid`] or [`SpiffeConfigError::SvidNotFound`].
+/// * An SVID whose key cannot be loaded yields [`SpiffeConfigError::Rustls`].
+pub struct SpiffeCertifiedKeyStream {
+    inner: X509ContextStream,
+    svid: SvidSelector,
+    provider: Arc<CryptoProvider>,
+}
+
+impl SpiffeCertifiedKeyStream {
+    /// Create a builder for a [`SpiffeCertifiedKeyStream`] using the default
+    /// SVID.
+    #[must_use]
+    pub fn builder() -> SpiffeCertifiedKeyStreamBuilder {
+        SpiffeCertifiedKeyStreamBuilder::default()
+    }
+
+    fn build_certified_key(
+        &self,
+        x509_context: &X509Context,
+    ) -> Result<Arc<CertifiedKey>, SpiffeConfigError> {
+        let svid = self.svid.select(x509_context).ok_or_else(|| {
+            self.svid
+                .not_found()
+                .unwrap_or(SpiffeConfigError::MissingSvid)
+        })?;
+
+        #[cfg(feature = "tracing")]
+        debug!(workload_identity = %svid.spiffe_id());
+
+        Ok(Arc::new(certified_key(svid, None, &self.provider)?))
+    }
+}
+
+impl Stream for SpiffeCertifiedKeyStream {
+    type Item = Result<Arc<CertifiedKey>, SpiffeConfigError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(SpiffeConfigError::Source(err)))),
+            Poll::Ready(Some(Ok(x509_context))) => {
+                Poll::Ready(Some(self.build_certified_key(&x509_context)))
+            }
+        }
+    }
+}
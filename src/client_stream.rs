@@ -1,43 +1,949 @@
 // SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
 
+#[cfg(feature = "disk-cache")]
+use std::path::PathBuf;
 use std::{
+    collections::HashMap,
+    ops::ControlFlow,
     pin::Pin,
     sync::Arc,
-    task::{Context, Poll},
+    task::{Context, Poll, ready},
+    time::Duration,
 };
 
+#[cfg(any(feature = "brotli", feature = "zlib"))]
+use rustls::compress;
 use rustls::{
-    ClientConfig,
-    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    ClientConfig, ConfigBuilder, SupportedCipherSuite, SupportedProtocolVersion, WantsVerifier,
+    client::{
+        ClientSessionStore, ResolvesClientCert, Resumption, WebPkiServerVerifier,
+        danger::ServerCertVerifier,
+    },
+    crypto::{CryptoProvider, SupportedKxGroup},
+    pki_types::{CertificateDer, ServerName, TrustAnchor},
+    sign::SingleCertAndKey,
 };
+#[cfg(feature = "key-log")]
+use rustls::{KeyLog, KeyLogFile};
 use rustls_config_stream::{ClientConfigStreamBuilder, ClientConfigStreamError};
-use spiffe::{TrustDomain, WorkloadApiClient, X509Context, error::GrpcClientError};
-use tokio_stream::Stream;
+use spiffe::{SpiffeId, TrustDomain, WorkloadApiClient, X509Context, X509Source};
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+};
+use tokio_stream::{Stream, wrappers::WatchStream};
 
 pub use rustls_config_stream::ClientConfigProvider;
 
 #[cfg(feature = "tracing")]
 use tracing::debug;
 
-use crate::TrustDomainStore;
+#[cfg(feature = "disk-cache")]
+use crate::DiskCacheSource;
+use crate::{
+    FallbackSource, RefreshHandle, RetryPolicy, SharedX509Source, ShutdownHandle, SourceError,
+    SpiffeConfigError, TrustDomainHandle, TrustDomainStore,
+    certified_key::certified_key,
+    destination_verifier::DestinationVerifier,
+    expiry_watch_source::{ExpiryWatchSource, WatchedSvids},
+    holdback::Holdback,
+    reconnect_source::ReconnectSource,
+    rotation_event::RotationEvent,
+    self_test,
+    stream_health::{
+        HealthChannels, HealthReporter, HealthState, Rejecting, Stale, StalePolicy, StreamHealth,
+        report_build_error,
+    },
+    svid_selector::SvidSelector,
+    svid_source::{
+        LayeredSource, SvidSource, WorkloadApiSource, X509ContextStream,
+        connect_with_initial_fetch, connect_within,
+    },
+    trust_domain_store::RootStoreOptions,
+    workload_identity::{RotationHook, RotationInfo, WorkloadIdentity},
+};
 
 /// Builder for a [`SpiffeClientConfigStream`] that provides [`rustls::ClientConfig`]
 /// objects built w/ trust bundles and workload X509-SVID from SPIFFE.
 ///
 /// The builder controls which SPIFFE trust bundles are included in the
 /// internal [`rustls::RootCertStore`] used to build the [`ClientConfig`]
+#[allow(clippy::struct_excessive_bools)]
 pub struct SpiffeClientConfigStreamBuilder {
-    trust_domains: Vec<TrustDomain>,
-    client: Option<WorkloadApiClient>,
+    trust_domains: TrustDomainHandle,
+    root_store_options: RootStoreOptions,
+    destinations: HashMap<ServerName<'static>, TrustDomain>,
+    svid: SvidSelector,
+    /// The SVIDs watched by sources wrapped with `with_expiry_reconnect`.
+    watched_svids: watch::Sender<WatchedSvids>,
+    contexts: watch::Sender<Option<Arc<X509Context>>>,
+    configs: watch::Sender<Option<Arc<ClientConfig>>>,
+    complete_chains: bool,
+    extra_roots: Arc<[CertificateDer<'static>]>,
+    extra_trust_anchors: Arc<[TrustAnchor<'static>]>,
+    identities: watch::Sender<Option<WorkloadIdentity>>,
+    rotation_hooks: Vec<RotationHook>,
+    config_mappers: Vec<ConfigMapper>,
+    crypto_provider: Option<Arc<CryptoProvider>>,
+    protocol_versions: Option<Vec<&'static SupportedProtocolVersion>>,
+    kx_groups: Option<Vec<&'static dyn SupportedKxGroup>>,
+    cipher_suites: Option<Vec<SupportedCipherSuite>>,
+    resumption: Option<Resumption>,
+    rotation_events: broadcast::Sender<RotationEvent>,
+    source: LayeredSource,
+    refresh: RefreshHandle,
+    shutdown: ShutdownHandle,
+    initial_fetch: bool,
+    startup_timeout: Option<Duration>,
+    started: bool,
+    health: HealthChannels,
+    keep_last_good: bool,
+    max_staleness: Option<(Duration, StalePolicy)>,
+    debounce: Option<Duration>,
+    adoption_delay: Option<(Duration, Duration)>,
+    self_test: bool,
 }
 
 impl SpiffeClientConfigStreamBuilder {
     /// Create a builder that can create [`SpiffeClientConfigStream`] objects
     /// with the provided SPIFFE trust domains.
-    const fn new(trust_domains: Vec<TrustDomain>) -> Self {
+    fn new(trust_domains: Vec<TrustDomain>) -> Self {
         Self {
-            trust_domains,
-            client: None,
+            trust_domains: TrustDomainHandle::new(trust_domains),
+            contexts: watch::Sender::new(None),
+            configs: watch::Sender::new(None),
+            identities: watch::Sender::new(None),
+            rotation_hooks: Vec::new(),
+            config_mappers: Vec::new(),
+            crypto_provider: None,
+            protocol_versions: None,
+            kx_groups: None,
+            cipher_suites: None,
+            resumption: None,
+            rotation_events: broadcast::Sender::new(16),
+            complete_chains: false,
+            extra_roots: Arc::new([]),
+            extra_trust_anchors: Arc::new([]),
+            root_store_options: RootStoreOptions::default(),
+            destinations: HashMap::new(),
+            svid: SvidSelector::Default,
+            watched_svids: watch::Sender::new(WatchedSvids::Selected(SvidSelector::Default)),
+            source: LayeredSource::new(WorkloadApiSource::new()),
+            refresh: RefreshHandle::new(),
+            shutdown: ShutdownHandle::new(),
+            initial_fetch: false,
+            startup_timeout: None,
+            started: false,
+            health: HealthChannels::new(),
+            keep_last_good: false,
+            max_staleness: None,
+            debounce: None,
+            adoption_delay: None,
+            self_test: false,
+        }
+    }
+
+    /// Also trust the trust domain of the workload's own SVID.
+    ///
+    /// The trust domain is taken from the SPIFFE ID of the SVID used for each
+    /// config, so it follows the workload if its identity changes.
+    #[must_use]
+    pub const fn with_local_trust_domain(mut self, trust_local: bool) -> Self {
+        self.root_store_options.trust_local_domain = trust_local;
+        self
+    }
+
+    /// Verify servers reached as `server_name` only against the bundle of
+    /// `trust_domain`.
+    ///
+    /// This lets a single provider talk to services in several federated trust
+    /// domains without trusting every domain for every connection. Server names
+    /// without a mapping are verified against all configured trust domains. If
+    /// the mapped trust domain has no bundle, connections to `server_name` are
+    /// rejected.
+    #[must_use]
+    pub fn with_destination_trust_domain(
+        mut self,
+        server_name: ServerName<'static>,
+        trust_domain: TrustDomain,
+    ) -> Self {
+        self.destinations.insert(server_name, trust_domain);
+        self
+    }
+
+    /// Use the SVID with the given SPIFFE ID instead of the default SVID.
+    ///
+    /// For workloads registered with several identities. If an update no longer
+    /// contains an SVID with this SPIFFE ID, the stream yields a
+    /// [`ClientConfigStreamError::StreamError`] wrapping
+    /// [`SpiffeConfigError::SvidNotFound`](crate::SpiffeConfigError::SvidNotFound).
+    #[must_use]
+    pub fn with_svid_id(mut self, spiffe_id: SpiffeId) -> Self {
+        self.svid = SvidSelector::SpiffeId(spiffe_id);
+        self
+    }
+
+    /// Returns a [`TrustDomainHandle`] for changing the trust domains of streams
+    /// built by this builder after the provider has started.
+    #[must_use]
+    pub fn trust_domain_handle(&self) -> TrustDomainHandle {
+        self.trust_domains.clone()
+    }
+
+    /// Returns a [`RefreshHandle`] for forcing streams built by this builder
+    /// to fetch a new update from the SVID source and rebuild their config.
+    #[must_use]
+    pub fn refresh_handle(&self) -> RefreshHandle {
+        self.refresh.clone()
+    }
+
+    /// Returns a [`ShutdownHandle`] for closing streams built by this builder
+    /// and their SVID source connections.
+    #[must_use]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Returns a receiver for the raw [`X509Context`] updates feeding streams
+    /// built by this builder.
+    ///
+    /// Holds `None` until the first update arrives. This exposes the SVIDs and
+    /// bundles behind each config, e.g. for audit logging, without opening a
+    /// second Workload API stream.
+    #[must_use]
+    pub fn context_updates(&self) -> watch::Receiver<Option<Arc<X509Context>>> {
+        self.contexts.subscribe()
+    }
+
+    /// Returns a receiver for the configs yielded by streams of this builder,
+    /// the same ones the provider stores.
+    ///
+    /// Holds `None` until the first config is built, so other tasks can
+    /// `changed().await` on rotations instead of polling `get_config()`.
+    #[must_use]
+    pub fn config_updates(&self) -> watch::Receiver<Option<Arc<ClientConfig>>> {
+        self.configs.subscribe()
+    }
+
+    /// Returns a receiver for the [`WorkloadIdentity`] of the most recent
+    /// config built by streams of this builder.
+    ///
+    /// Holds `None` until the first config is built, and only changes when the
+    /// SVID does, so it can back health endpoints and logs without parsing
+    /// certificates out of the rustls config.
+    #[must_use]
+    pub fn identity_updates(&self) -> watch::Receiver<Option<WorkloadIdentity>> {
+        self.identities.subscribe()
+    }
+
+    /// Returns a receiver for a [`RotationEvent`] describing each Workload API
+    /// update that changed the SVID selected with
+    /// [`with_svid_id`](Self::with_svid_id), or else the default SVID, or the
+    /// bundles of the configured trust domains.
+    ///
+    /// Updates received by several streams of this builder are only
+    /// described once. A receiver lagging more than 16 events behind loses
+    /// the oldest.
+    #[must_use]
+    pub fn rotation_events(&self) -> broadcast::Receiver<RotationEvent> {
+        self.rotation_events.subscribe()
+    }
+
+    /// Call `hook` whenever a stream built by this builder adopts a config
+    /// with a new identity, e.g. to reset connection pools on rotation.
+    ///
+    /// The hook runs on the task polling the stream, so it should return
+    /// quickly. Hooks run in registration order.
+    #[must_use]
+    pub fn on_rotation(mut self, hook: impl Fn(&RotationInfo) + Send + Sync + 'static) -> Self {
+        self.rotation_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Apply `map` to every [`ClientConfig`] built by streams of this
+    /// builder, e.g. to set ALPN protocols, resumption or a key log.
+    ///
+    /// Mappers run in registration order on each rebuilt config, before it is
+    /// yielded. [`RotatingClientConfig`](crate::RotatingClientConfig) applies
+    /// them once to its long-lived config.
+    #[must_use]
+    pub fn map_config(mut self, map: impl Fn(&mut ClientConfig) + Send + Sync + 'static) -> Self {
+        self.config_mappers.push(Arc::new(map));
+        self
+    }
+
+    /// Offer `protocols`, most preferred first, in ALPN on every built
+    /// config, e.g. `vec![b"h2".to_vec(), b"http/1.1".to_vec()]`.
+    ///
+    /// Replaces the protocols set by an earlier call or mapper.
+    #[must_use]
+    pub fn with_alpn_protocols(self, protocols: Vec<Vec<u8>>) -> Self {
+        self.map_config(move |config| config.alpn_protocols.clone_from(&protocols))
+    }
+
+    /// Offer HTTP/1.1 only in ALPN; see
+    /// [`with_alpn_protocols`](Self::with_alpn_protocols).
+    #[must_use]
+    pub fn with_http1(self) -> Self {
+        self.with_alpn_protocols(vec![b"http/1.1".to_vec()])
+    }
+
+    /// Offer HTTP/2, falling back to HTTP/1.1, in ALPN; see
+    /// [`with_alpn_protocols`](Self::with_alpn_protocols).
+    #[must_use]
+    pub fn with_http2(self) -> Self {
+        self.with_alpn_protocols(vec![b"h2".to_vec(), b"http/1.1".to_vec()])
+    }
+
+    /// Offer HTTP/2 only in ALPN, as gRPC requires, e.g. for tonic; see
+    /// [`with_alpn_protocols`](Self::with_alpn_protocols).
+    #[must_use]
+    pub fn with_grpc(self) -> Self {
+        self.with_alpn_protocols(vec![b"h2".to_vec()])
+    }
+
+    /// Send TLS records carrying at most `size` bytes, the record header
+    /// included, on every built config, e.g. for constrained peers needing
+    /// small records. Records are up to 16 KiB by default.
+    ///
+    /// `size` must be between 32 and 16389, or creating connections fails
+    /// with [`rustls::Error::BadMaxFragmentSize`]. The limit on buffered
+    /// outgoing data belongs to the connection rather than the config; set it
+    /// with `set_buffer_limit` on each `rustls::ClientConnection`.
+    #[must_use]
+    pub fn with_max_fragment_size(self, size: usize) -> Self {
+        self.map_config(move |config| config.max_fragment_size = Some(size))
+    }
+
+    /// Compress certificates sent, and accept compressed certificates from
+    /// peers, with the algorithms of the `brotli` and `zlib` features, the
+    /// default when either is enabled, or disable compression.
+    ///
+    /// SPIFFE chains with intermediates compress well, saving handshake
+    /// bytes when the peer supports compression too.
+    #[cfg(any(feature = "brotli", feature = "zlib"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "brotli", feature = "zlib"))))]
+    #[must_use]
+    pub fn with_cert_compression(self, enable: bool) -> Self {
+        self.map_config(move |config| {
+            if enable {
+                config.cert_compressors = compress::default_cert_compressors().to_vec();
+                config.cert_decompressors = compress::default_cert_decompressors().to_vec();
+            } else {
+                config.cert_compressors.clear();
+                config.cert_decompressors.clear();
+            }
+        })
+    }
+
+    /// Log the TLS secrets of every connection using a built config to
+    /// `key_log`, so captures can be decrypted, e.g. by Wireshark.
+    ///
+    /// Anyone with the logged secrets can decrypt the traffic; only enable
+    /// the `key-log` feature in debugging builds.
+    #[cfg(feature = "key-log")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "key-log")))]
+    #[must_use]
+    pub fn with_key_log(self, key_log: Arc<dyn KeyLog>) -> Self {
+        self.map_config(move |config| config.key_log = key_log.clone())
+    }
+
+    /// Log TLS secrets to the file named by the `SSLKEYLOGFILE` environment
+    /// variable, if set; see [`with_key_log`](Self::with_key_log).
+    #[cfg(feature = "key-log")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "key-log")))]
+    #[must_use]
+    pub fn with_key_log_file(self) -> Self {
+        self.with_key_log(Arc::new(KeyLogFile::new()))
+    }
+
+    /// Send TLS 1.3 early data (0-RTT) when resuming a session on every
+    /// built config, if the server accepts it. Disabled by default.
+    ///
+    /// Early data can be replayed by an attacker, so only send idempotent
+    /// requests in it. It needs resumption, so has no effect with resumption
+    /// disabled.
+    #[must_use]
+    pub fn with_early_data(self, enable: bool) -> Self {
+        self.map_config(move |config| config.enable_early_data = enable)
+    }
+
+    /// Build configs, and load private keys, with `provider` instead of the
+    /// process-default [`CryptoProvider`].
+    ///
+    /// For binaries linking several TLS stacks, where installing a process
+    /// default is not an option.
+    #[must_use]
+    pub fn with_crypto_provider(mut self, provider: Arc<CryptoProvider>) -> Self {
+        self.crypto_provider = Some(provider);
+        self
+    }
+
+    /// Enable only `versions` on every built config, e.g.
+    /// `&[&rustls::version::TLS13]` to refuse TLS 1.2 peers, instead of both
+    /// TLS 1.2 and 1.3.
+    ///
+    /// Building the stream fails if the crypto provider supports none of
+    /// `versions`.
+    #[must_use]
+    pub fn with_protocol_versions(
+        mut self,
+        versions: &[&'static SupportedProtocolVersion],
+    ) -> Self {
+        self.protocol_versions = Some(versions.to_vec());
+        self
+    }
+
+    /// Offer only the cipher `suites` of the crypto provider, most preferred
+    /// first, e.g. to satisfy a hardening baseline.
+    ///
+    /// Building the stream fails if no suite supports the protocol versions.
+    #[must_use]
+    pub fn with_cipher_suites(mut self, suites: Vec<SupportedCipherSuite>) -> Self {
+        self.cipher_suites = Some(suites);
+        self
+    }
+
+    /// Offer only the key exchange `groups` of the crypto provider, most
+    /// preferred first, e.g. only the hybrid post-quantum
+    /// `rustls::crypto::aws_lc_rs::kx_group::X25519MLKEM768` to force it.
+    ///
+    /// Building the stream fails if `groups` is empty.
+    #[must_use]
+    pub fn with_kx_groups(mut self, groups: Vec<&'static dyn SupportedKxGroup>) -> Self {
+        self.kx_groups = Some(groups);
+        self
+    }
+
+    /// Resume sessions on configs built by this builder, the default, or
+    /// disable resumption so every connection does a full handshake and
+    /// authenticates with the SVIDs current at that time.
+    ///
+    /// Replaces the store set by an earlier
+    /// [`with_session_store`](Self::with_session_store).
+    #[must_use]
+    pub fn with_resumption(mut self, resumption: bool) -> Self {
+        self.resumption = (!resumption).then(Resumption::disabled);
+        self
+    }
+
+    /// Store sessions for resumption in `store` instead of the in-memory
+    /// store of rustls. Every built config uses the same store.
+    #[must_use]
+    pub fn with_session_store(mut self, store: Arc<dyn ClientSessionStore>) -> Self {
+        self.resumption = Some(Resumption::store(store));
+        self
+    }
+
+    /// Keep up to `sessions` sessions for resumption in one in-memory store
+    /// shared by every built config, instead of a store of 256 per config.
+    ///
+    /// Replaces the store set by an earlier
+    /// [`with_session_store`](Self::with_session_store).
+    #[must_use]
+    pub fn with_session_cache_size(mut self, sessions: usize) -> Self {
+        self.resumption = Some(Resumption::in_memory_sessions(sessions));
+        self
+    }
+
+    pub(crate) fn config_builder(
+        &self,
+    ) -> Result<ConfigBuilder<ClientConfig, WantsVerifier>, ClientConfigStreamError> {
+        let mut provider = self
+            .crypto_provider
+            .clone()
+            .unwrap_or_else(|| ClientConfig::builder().crypto_provider().clone());
+        if let Some(kx_groups) = &self.kx_groups {
+            provider = Arc::new(CryptoProvider {
+                kx_groups: kx_groups.clone(),
+                ..CryptoProvider::clone(&provider)
+            });
+        }
+        if let Some(cipher_suites) = &self.cipher_suites {
+            provider = Arc::new(CryptoProvider {
+                cipher_suites: cipher_suites.clone(),
+                ..CryptoProvider::clone(&provider)
+            });
+        }
+        let versions = self
+            .protocol_versions
+            .as_deref()
+            .unwrap_or(rustls::DEFAULT_VERSIONS);
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(versions)
+            .map_err(|e| ClientConfigStreamError::StreamBuilderError(e.into()))?;
+        #[cfg(feature = "fips")]
+        if !builder.crypto_provider().fips() {
+            return Err(ClientConfigStreamError::StreamBuilderError(
+                SpiffeConfigError::NotFips.into(),
+            ));
+        }
+        Ok(builder)
+    }
+
+    /// Apply the resumption setting and mappers to `config`.
+    pub(crate) fn customize(&self, config: &mut ClientConfig) {
+        if let Some(resumption) = &self.resumption {
+            config.resumption = resumption.clone();
+        }
+        for map in &self.config_mappers {
+            map(config);
+        }
+    }
+
+    /// Returns a receiver for the [`StreamHealth`] of the streams of this
+    /// builder, updated after every Workload API update.
+    #[must_use]
+    pub fn health_updates(&self) -> watch::Receiver<StreamHealth> {
+        self.health.subscribe()
+    }
+
+    /// Returns a receiver for the [`HealthState`] of the streams of this
+    /// builder, which only changes on transitions, e.g. to alert on
+    /// [`HealthState::Failed`] without polling.
+    #[must_use]
+    pub fn health_state_updates(&self) -> watch::Receiver<HealthState> {
+        self.health.subscribe_state()
+    }
+
+    /// Returns a future resolving with the error, and its sources, on which
+    /// the SVID source of this builder's streams gave up, so the application
+    /// can exit or restart instead of serving an ever older config.
+    ///
+    /// Sources only give up once the retries of a [`RetryPolicy`] with
+    /// [`with_max_retries`](RetryPolicy::with_max_retries), set with
+    /// [`with_auto_reconnect`](Self::with_auto_reconnect), are exhausted; the
+    /// provider's own task rebuilds failed streams forever. The error is also
+    /// kept, as text, in [`StreamHealth::fatal`] until a config is built
+    /// again.
+    ///
+    /// The error is a [`SpiffeConfigError::RetriesExhausted`], shared between
+    /// all callers; the future resolves with `None` if the builder and its
+    /// streams are dropped first.
+    pub fn fatal_error(
+        &self,
+    ) -> impl Future<Output = Option<Arc<SpiffeConfigError>>> + Send + 'static {
+        let mut fatal = self.health.subscribe_fatal();
+        async move {
+            fatal
+                .wait_for(Option::is_some)
+                .await
+                .ok()
+                .and_then(|fatal| fatal.clone())
+        }
+    }
+
+    /// Returns a future resolving once a stream built by this builder has
+    /// built its first config, to gate listener startup and readiness probes
+    /// on.
+    ///
+    /// The future does not borrow the builder, so it can be taken before the
+    /// builder is handed to [`ClientConfigProvider::start`].
+    ///
+    /// # Errors
+    /// [`SpiffeConfigError::NotReady`](crate::SpiffeConfigError::NotReady) if
+    /// no config was built within `timeout`.
+    pub fn wait_ready(
+        &self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), SpiffeConfigError>> + Send + 'static {
+        let mut health = self.health.subscribe();
+        async move {
+            let ready = health.wait_for(|health| health.last_good.is_some());
+            match tokio::time::timeout(timeout, ready).await {
+                Ok(Ok(_)) => Ok(()),
+                _ => Err(SpiffeConfigError::NotReady(timeout)),
+            }
+        }
+    }
+
+    /// Keep errors of individual updates, such as a failed Workload API
+    /// stream item or an update without roots, from the provider.
+    ///
+    /// The stream then keeps waiting for the next update, so the provider
+    /// keeps serving the last good config; the errors are only reported
+    /// through [`health_updates`](Self::health_updates), whose
+    /// [`StreamHealth::staleness`] tells how old that config is. The end of
+    /// the Workload API stream is still passed on, so the provider rebuilds
+    /// the stream.
+    #[must_use]
+    pub const fn with_keep_last_good(mut self, keep_last_good: bool) -> Self {
+        self.keep_last_good = keep_last_good;
+        self
+    }
+
+    /// Coalesce bursts of Workload API updates, such as during federation
+    /// sync: the first update of a burst is held for `window`, and only the
+    /// latest update received by then is built into a config.
+    ///
+    /// Updates are delayed by up to `window`; refreshes and trust domain
+    /// changes are not.
+    #[must_use]
+    pub const fn with_debounce(mut self, window: Duration) -> Self {
+        self.debounce = Some(window);
+        self
+    }
+
+    /// Test every freshly built config with an in-memory handshake against
+    /// a peer trusting the bundle of the SVID's trust domain, presenting the
+    /// config's certificate chain.
+    ///
+    /// A config failing the handshake, e.g. because of a broken intermediate,
+    /// is not yielded: the stream yields a
+    /// [`SpiffeConfigError::SelfTest`](crate::SpiffeConfigError::SelfTest)
+    /// error wrapped in [`ClientConfigStreamError::StreamError`] instead, so
+    /// the provider keeps the previous config.
+    #[must_use]
+    pub const fn with_self_test(mut self, self_test: bool) -> Self {
+        self.self_test = self_test;
+        self
+    }
+
+    /// Delay adopting an update that rotates the selected SVID by `delay`, so
+    /// that peers with skewed clocks or late bundle updates do not reject
+    /// the new certificate yet.
+    ///
+    /// The previous config stays in use meanwhile, unless its SVID expires
+    /// within `min_remaining`: then the update is adopted at that point, or
+    /// at once if that point has passed. Updates that only change bundles
+    /// are not delayed.
+    #[must_use]
+    pub const fn with_adoption_delay(mut self, delay: Duration, min_remaining: Duration) -> Self {
+        self.adoption_delay = Some((delay, min_remaining));
+        self
+    }
+
+    /// Treat the stream as wedged once no config was built for `max_age`,
+    /// e.g. 1.5 times the SVID lifetime, and act as `policy` says.
+    ///
+    /// The stream then yields a
+    /// [`SpiffeConfigError::Stale`](crate::SpiffeConfigError::Stale) error
+    /// wrapped in [`ClientConfigStreamError::StreamError`], which marks the
+    /// provider unhealthy and makes it rebuild the stream; with
+    /// [`StalePolicy::Reject`] it first yields a config that fails every
+    /// handshake. The provider marks itself healthy again as soon as the stream
+    /// is rebuilt, while [`StreamHealth::stale`] stays set until a config is
+    /// built.
+    #[must_use]
+    pub const fn with_max_staleness(mut self, max_age: Duration, policy: StalePolicy) -> Self {
+        self.max_staleness = Some((max_age, policy));
+        self
+    }
+
+    /// Require every configured trust domain to be present in each Workload API
+    /// update.
+    ///
+    /// When enabled, an update whose bundle set is missing any configured trust
+    /// domain yields a [`SpiffeConfigError::MissingBundle`] error instead of a
+    /// config built from the remaining trust domains. When disabled (the default),
+    /// missing trust domains are skipped and logged.
+    ///
+    /// [`SpiffeConfigError::MissingBundle`]: crate::SpiffeConfigError::MissingBundle
+    #[must_use]
+    pub const fn with_strict_trust_domains(mut self, strict: bool) -> Self {
+        self.root_store_options.strict = strict;
+        self
+    }
+
+    /// Skip bundle authorities whose `notAfter` has passed when building the
+    /// root store.
+    ///
+    /// Disabled by default. Skipped authorities are counted in the `expired`
+    /// field of the root store debug event.
+    #[must_use]
+    pub const fn with_skip_expired_authorities(mut self, skip: bool) -> Self {
+        self.root_store_options.authorities.skip_expired = skip;
+        self
+    }
+
+    /// Only add bundle authorities that carry a basicConstraints extension with
+    /// `CA=true` to the root store.
+    ///
+    /// Disabled by default. Each rejected authority is logged, so a leaf
+    /// certificate imported into a bundle by mistake cannot silently become a
+    /// trust anchor.
+    #[must_use]
+    pub const fn with_require_ca_authorities(mut self, require: bool) -> Self {
+        self.root_store_options.authorities.require_ca = require;
+        self
+    }
+
+    /// Trust `roots` in addition to the SPIFFE trust bundles.
+    ///
+    /// For migrations where peers with certificates from a legacy CA must keep
+    /// working next to SPIFFE peers. The roots are added to every config and
+    /// do not rotate; unparsable certificates are ignored.
+    ///
+    /// To also trust the OS trust store, load it once at startup and pass it
+    /// here, e.g. `rustls_native_certs::load_native_certs().certs`; it is then
+    /// merged into every rotated root store. This stands in for a
+    /// `native-roots` feature, which the crate does not have.
+    #[must_use]
+    pub fn with_extra_roots(mut self, roots: Vec<CertificateDer<'static>>) -> Self {
+        self.extra_roots = roots.into();
+        self
+    }
+
+    /// Trust `anchors` in addition to the SPIFFE trust bundles.
+    ///
+    /// This is how to trust a public root program next to the mesh, so one
+    /// provider serves both in-mesh mTLS and public internet calls, e.g. with
+    /// `webpki_roots::TLS_SERVER_ROOTS.to_vec()`. Combine with
+    /// [`with_destination_trust_domain`](Self::with_destination_trust_domain)
+    /// to keep mesh destinations from being verified against public roots.
+    ///
+    /// This method stands in for a `webpki-roots` feature, which the crate
+    /// does not have: add `webpki-roots` to your own dependencies and pass
+    /// its anchors here.
+    #[must_use]
+    pub fn with_extra_trust_anchors(mut self, anchors: Vec<TrustAnchor<'static>>) -> Self {
+        self.extra_trust_anchors = anchors.into();
+        self
+    }
+
+    /// Complete the SVID certificate chain with intermediates from the trust
+    /// bundle of the SVID's trust domain.
+    ///
+    /// Disabled by default. For agents that return only the leaf certificate:
+    /// intermediates are appended until the chain reaches a certificate issued
+    /// by a self-signed bundle authority, so peers without the intermediates
+    /// can still verify the workload.
+    #[must_use]
+    pub const fn with_chain_completion(mut self, complete: bool) -> Self {
+        self.complete_chains = complete;
+        self
+    }
+
+    /// Fetch the current SVID and bundles once before streaming updates.
+    ///
+    /// Disabled by default. The first config is built from the fetched update
+    /// as soon as it arrives, while the update stream is opened in the
+    /// background, which shortens provider startup.
+    #[must_use]
+    pub const fn with_initial_fetch(mut self, initial_fetch: bool) -> Self {
+        self.initial_fetch = initial_fetch;
+        self
+    }
+
+    /// Fail the first build when no SVID update arrives within `timeout`.
+    ///
+    /// Without a timeout, [`ClientConfigProvider::start`] waits forever for an
+    /// agent that is not running. With one, it returns a
+    /// [`ClientConfigStreamError::StreamBuilderError`] wrapping
+    /// [`SpiffeConfigError::StartupTimeout`], which names the SVID source in
+    /// use, e.g. the Workload API socket. Rebuilds after the first successful
+    /// build are not limited.
+    #[must_use]
+    pub const fn with_startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = Some(timeout);
+        self
+    }
+
+    /// Read SVIDs and bundles from `source` instead of the SPIFFE Workload API.
+    ///
+    /// Replaces the source chosen by this or another `with_*` source setter,
+    /// but keeps the wrappers such as
+    /// [`with_auto_reconnect`](Self::with_auto_reconnect) around it.
+    #[must_use]
+    pub fn with_svid_source(mut self, source: impl SvidSource) -> Self {
+        self.source.set_base(source);
+        self
+    }
+
+    /// Connect to the Workload API at `endpoint`, e.g.
+    /// `unix:///run/spire/sockets/agent.sock` or `tcp://127.0.0.1:8081`,
+    /// instead of the socket named by `SPIFFE_ENDPOINT_SOCKET`.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.source
+            .set_base(WorkloadApiSource::from_endpoint(endpoint));
+        self
+    }
+
+    /// Connect to the first reachable Workload API of `endpoints`, tried in
+    /// order, e.g. a primary agent followed by a hot standby.
+    ///
+    /// When the stream fails, the provider rebuilds it with backoff and tries
+    /// the endpoints again from the first. See
+    /// [`WorkloadApiSource::from_endpoints`].
+    #[must_use]
+    pub fn with_endpoints(
+        mut self,
+        endpoints: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.source
+            .set_base(WorkloadApiSource::from_endpoints(endpoints));
+        self
+    }
+
+    /// Stream from an already-configured [`WorkloadApiClient`].
+    ///
+    /// The client is cloned for each stream, so one client can be shared
+    /// between the client and server builders.
+    #[must_use]
+    pub fn with_client(mut self, client: WorkloadApiClient) -> Self {
+        self.source.set_base(WorkloadApiSource::from_client(client));
+        self
+    }
+
+    /// Read SVIDs and bundles from a shared [`X509Source`] instead of opening
+    /// a Workload API stream.
+    ///
+    /// The SVID is the one picked by the source. Bundles are looked up for the
+    /// configured trust domains, the SVID's own trust domain and the
+    /// destination trust domains configured so far. See [`SharedX509Source`].
+    #[must_use]
+    pub fn with_x509_source(mut self, source: Arc<X509Source>) -> Self {
+        self.source.set_base(SharedX509Source::new(
+            source,
+            self.trust_domains.subscribe(),
+            self.destinations.values().cloned().collect(),
+        ));
+        self
+    }
+
+    /// Serve `fallback` until the SVID source delivers its first update.
+    ///
+    /// Wraps the SVID source, whichever setter configures it and whether
+    /// before or after this call, and the wrappers added before this one.
+    /// With `stale_after`, the fallback is served again
+    /// when the source has been down for that long. See [`FallbackSource`].
+    #[must_use]
+    pub fn with_static_fallback(
+        mut self,
+        fallback: X509Context,
+        stale_after: Option<Duration>,
+    ) -> Self {
+        self.source.push(Box::new(move |source| {
+            Arc::new(
+                FallbackSource::from_arc(source, fallback.clone()).with_stale_after(stale_after),
+            )
+        }));
+        self
+    }
+
+    /// Connect to the SVID source according to `policy`, and reconnect when
+    /// its stream fails or ends instead of failing the config stream.
+    ///
+    /// Wraps the SVID source, whichever setter configures it and whether
+    /// before or after this call, and the wrappers added before this one.
+    /// The provider then keeps its stream, and stays healthy, across agent
+    /// restarts while the last config stays in use. Once the policy's retries
+    /// are exhausted, building or polling the stream fails with
+    /// [`SpiffeConfigError::RetriesExhausted`].
+    #[must_use]
+    pub fn with_auto_reconnect(mut self, policy: RetryPolicy) -> Self {
+        self.source.push(Box::new(move |source| {
+            Arc::new(ReconnectSource::new(source, policy))
+        }));
+        self
+    }
+
+    /// Rebuild the SVID source stream when an SVID gets within `threshold` of
+    /// its expiry and no renewal has arrived, logging a warning.
+    ///
+    /// Wraps the SVID source and the wrappers added before this one: called
+    /// before [`with_auto_reconnect`](Self::with_auto_reconnect), the
+    /// source is reconnected inside the stream; otherwise the stream ends and
+    /// the provider rebuilds it. Each expiring SVID forces one rebuild at most.
+    ///
+    /// Only the SVIDs configs are built from are watched: the one selected
+    /// with `with_svid_id`, or the default SVID.
+    #[must_use]
+    pub fn with_expiry_reconnect(mut self, threshold: Duration) -> Self {
+        let watched_svids = self.watched_svids.clone();
+        self.source.push(Box::new(move |source| {
+            Arc::new(ExpiryWatchSource::new(
+                source,
+                threshold,
+                watched_svids.subscribe(),
+            ))
+        }));
+        self
+    }
+
+    /// Persist every update to the file at `path` and serve the last persisted
+    /// update if the SVID source is unavailable when the stream is built.
+    ///
+    /// Wraps the SVID source, whichever setter configures it and whether
+    /// before or after this call, and the wrappers added before this one.
+    /// Bundles of the builder's trust domains are cached
+    /// along with the SVIDs. See [`DiskCacheSource`].
+    #[cfg(feature = "disk-cache")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "disk-cache")))]
+    #[must_use]
+    pub fn with_disk_cache(mut self, path: impl Into<PathBuf>, max_age: Option<Duration>) -> Self {
+        let (path, trust_domains) = (path.into(), self.trust_domains.clone());
+        self.source.push(Box::new(move |source| {
+            let mut cache = DiskCacheSource::from_arc(source, path.clone())
+                .with_trust_domain_handle(trust_domains.clone());
+            if let Some(max_age) = max_age {
+                cache = cache.with_max_age(max_age);
+            }
+            Arc::new(cache)
+        }));
+        self
+    }
+}
+
+impl SpiffeClientConfigStreamBuilder {
+    /// Fetch the current SVID and bundles once and build a single
+    /// [`ClientConfig`], without a provider or rotation.
+    ///
+    /// For short-lived tools and jobs that want a SPIFFE-derived config but
+    /// finish before the SVID rotates. Nothing is published: the builder's
+    /// update watches, rotation hooks and rotation events are left untouched.
+    ///
+    /// # Errors
+    /// - [`ClientConfigStreamError::StreamBuilderError`] if the fetch fails.
+    /// - Any error building the config, as yielded by the stream.
+    pub async fn fetch_client_config(&self) -> Result<Arc<ClientConfig>, ClientConfigStreamError> {
+        let x509_context = self
+            .source
+            .get()
+            .fetch()
+            .await
+            .map_err(ClientConfigStreamError::StreamBuilderError)?;
+        self.stream_from(Box::pin(tokio_stream::empty()), self.config_builder()?)
+            .build_client_config(&x509_context)
+    }
+
+    fn stream_from(
+        &self,
+        inner: X509ContextStream,
+        config_builder: ConfigBuilder<ClientConfig, WantsVerifier>,
+    ) -> SpiffeClientConfigStream {
+        SpiffeClientConfigStream {
+            config_builder,
+            resumption: self.resumption.clone(),
+            trust_domains: self.trust_domains.subscribe(),
+            trust_domain_updates: WatchStream::from_changes(self.trust_domains.subscribe()),
+            contexts: self.contexts.clone(),
+            configs: self.configs.clone(),
+            complete_chains: self.complete_chains,
+            extra_roots: self.extra_roots.clone(),
+            extra_trust_anchors: self.extra_trust_anchors.clone(),
+            identities: self.identities.clone(),
+            rotation_hooks: self.rotation_hooks.clone().into(),
+            config_mappers: self.config_mappers.clone().into(),
+            rotation_events: self.rotation_events.clone(),
+            last_context: None,
+            source: self.source.get().clone(),
+            refreshes: WatchStream::from_changes(self.refresh.subscribe()),
+            refresh: None,
+            holdback: Holdback::new(self.debounce, self.adoption_delay, self.svid.clone()),
+            self_test: self.self_test,
+            shutdown: WatchStream::new(self.shutdown.subscribe()),
+            shut_down: false,
+            health: HealthReporter::new(
+                self.health.clone(),
+                self.keep_last_good,
+                self.max_staleness,
+            ),
+            root_store_options: self.root_store_options,
+            svid: self.svid.clone(),
+            destinations: self.destinations.clone(),
+            inner,
         }
     }
 }
@@ -46,22 +952,34 @@ impl ClientConfigStreamBuilder for SpiffeClientConfigStreamBuilder {
     type ConfigStream = SpiffeClientConfigStream;
 
     async fn build(&mut self) -> Result<Self::ConfigStream, ClientConfigStreamError> {
-        let client = if let Some(client) = &mut self.client {
-            client
-        } else {
-            &mut WorkloadApiClient::default()
-                .await
-                .map_err(|e| ClientConfigStreamError::StreamBuilderError(e.into()))?
+        if self.shutdown.is_shut_down() {
+            // the provider has no terminal error: it rebuilds an ended stream,
+            // retrying failed builds forever with a backoff capped at 10s, so
+            // parking the build is the only way to stop its task polling
+            return std::future::pending().await;
+        }
+        self.watched_svids
+            .send_replace(WatchedSvids::Selected(self.svid.clone()));
+        let config_builder = self.config_builder()?;
+        let connect = async {
+            if self.initial_fetch {
+                connect_with_initial_fetch(self.source.get().clone()).await
+            } else {
+                self.source.get().connect().await
+            }
         };
-        Ok(SpiffeClientConfigStream {
-            trust_domains: self.trust_domains.clone(),
-            inner: Pin::from(Box::from(
-                client
-                    .stream_x509_contexts()
-                    .await
-                    .map_err(|e| ClientConfigStreamError::StreamError(e.into()))?,
-            )),
-        })
+        let inner = match self.startup_timeout {
+            Some(timeout) if !self.started => {
+                connect_within(connect, timeout, self.source.get().describe()).await
+            }
+            _ => connect.await,
+        }
+        .map_err(|err| {
+            report_build_error(&self.health, &err);
+            ClientConfigStreamError::StreamBuilderError(err)
+        })?;
+        self.started = true;
+        Ok(self.stream_from(inner, config_builder))
     }
 }
 
@@ -69,26 +987,68 @@ impl ClientConfigStreamBuilder for SpiffeClientConfigStreamBuilder {
 /// SPIFFE Workload API X509-SVID and Trust Bundles.
 ///
 /// Each yielded config:
-/// * Uses the workload's default SVID (certificate chain + private key).
+/// * Uses the workload's default SVID (certificate chain + private key), or
+///   the SVID selected with `with_svid_id` on the builder.
 /// * Requires (and verifies) server certificates whose trust anchors come from
 ///   the configured SPIFFE trust domains.
 ///
 /// # Behavior
 ///
-/// * If the Workload API stream returns an error, this stream yields
-///   a [`ClientConfigStreamError::StreamError`] wrapping the original
-///   [`GrpcClientError`].
+/// * If the SVID source returns an error, this stream yields a
+///   [`ClientConfigStreamError::StreamError`] wrapping the original error, a
+///   [`GrpcClientError`](spiffe::error::GrpcClientError) for the Workload API.
 /// * If an update lacks roots/SVID or the verifier cannot be built, the error
 ///   is returned on the stream as a [`ClientConfigStreamError`]
+/// * With strict trust domains enabled, an update missing a configured trust
+///   domain's bundle yields a [`ClientConfigStreamError::StreamError`] wrapping
+///   [`crate::SpiffeConfigError::MissingBundle`].
+/// * An update identical to the previous one is skipped instead of yielding
+///   an equivalent config.
 pub struct SpiffeClientConfigStream {
-    inner:
-        Pin<Box<dyn Stream<Item = Result<X509Context, GrpcClientError>> + Send + Sync + 'static>>,
-    trust_domains: Vec<TrustDomain>,
+    inner: X509ContextStream,
+    health: HealthReporter,
+    trust_domains: watch::Receiver<Vec<TrustDomain>>,
+    trust_domain_updates: WatchStream<Vec<TrustDomain>>,
+    last_context: Option<Arc<X509Context>>,
+    source: Arc<dyn SvidSource>,
+    refreshes: WatchStream<()>,
+    refresh: Option<JoinHandle<Result<X509Context, SourceError>>>,
+    holdback: Option<Holdback>,
+    self_test: bool,
+    shutdown: WatchStream<bool>,
+    shut_down: bool,
+    root_store_options: RootStoreOptions,
+    destinations: HashMap<ServerName<'static>, TrustDomain>,
+    svid: SvidSelector,
+    contexts: watch::Sender<Option<Arc<X509Context>>>,
+    configs: watch::Sender<Option<Arc<ClientConfig>>>,
+    complete_chains: bool,
+    extra_roots: Arc<[CertificateDer<'static>]>,
+    extra_trust_anchors: Arc<[TrustAnchor<'static>]>,
+    identities: watch::Sender<Option<WorkloadIdentity>>,
+    rotation_hooks: Arc<[RotationHook]>,
+    config_mappers: Arc<[ConfigMapper]>,
+    config_builder: ConfigBuilder<ClientConfig, WantsVerifier>,
+    /// Replaces the resumption of rustls on built configs, if configured.
+    resumption: Option<Resumption>,
+    rotation_events: broadcast::Sender<RotationEvent>,
 }
 
 impl TrustDomainStore for SpiffeClientConfigStream {
-    fn get_trust_domains(&self) -> &Vec<TrustDomain> {
-        &self.trust_domains
+    fn get_trust_domains(&self) -> Vec<TrustDomain> {
+        self.trust_domains.borrow().clone()
+    }
+
+    fn root_store_options(&self) -> RootStoreOptions {
+        self.root_store_options
+    }
+
+    fn extra_roots(&self) -> &[CertificateDer<'static>] {
+        &self.extra_roots
+    }
+
+    fn extra_trust_anchors(&self) -> &[TrustAnchor<'static>] {
+        &self.extra_trust_anchors
     }
 }
 
@@ -96,55 +1056,357 @@ impl SpiffeClientConfigStream {
     /// Create a builder that can create [`SpiffeClientConfigStream`] objects
     /// with the provided SPIFFE trust domains.
     #[must_use]
-    pub const fn builder(trust_domains: Vec<TrustDomain>) -> SpiffeClientConfigStreamBuilder {
+    pub fn builder(trust_domains: Vec<TrustDomain>) -> SpiffeClientConfigStreamBuilder {
         SpiffeClientConfigStreamBuilder::new(trust_domains)
     }
 
-    fn build_client_config(
+    /// Create a builder that trusts only the trust domain of the workload's own
+    /// SVID, as reported by the Workload API.
+    ///
+    /// This is equivalent to `builder(vec![]).with_local_trust_domain(true)`.
+    #[must_use]
+    pub fn builder_local() -> SpiffeClientConfigStreamBuilder {
+        SpiffeClientConfigStreamBuilder::new(Vec::new()).with_local_trust_domain(true)
+    }
+
+    fn build_client_parts(
         &self,
         x509_context: &X509Context,
-    ) -> Result<Arc<ClientConfig>, ClientConfigStreamError> {
-        let roots = self.build_root_store(x509_context.bundle_set());
+    ) -> Result<ClientParts, ClientConfigStreamError> {
+        let svid = self.svid.select(x509_context).ok_or_else(|| {
+            self.svid
+                .not_found()
+                .map_or(ClientConfigStreamError::MissingCertifiedKey, |e| {
+                    ClientConfigStreamError::StreamError(e.into())
+                })
+        })?;
+        let roots = self
+            .build_root_store(
+                x509_context.bundle_set(),
+                Some(svid.spiffe_id().trust_domain()),
+            )
+            .map_err(|e| ClientConfigStreamError::StreamError(e.into()))?;
         if roots.is_empty() {
             return Err(ClientConfigStreamError::MissingRoots);
         }
-        let svid = x509_context
-            .default_svid()
-            .ok_or(ClientConfigStreamError::MissingCertifiedKey)?;
 
         #[cfg(feature = "tracing")]
         debug!(workload_identity = %svid.spiffe_id());
 
-        let config = ClientConfig::builder()
-            .with_root_certificates(roots)
-            .with_client_auth_cert(
-                svid.cert_chain()
-                    .iter()
-                    .map(|c| CertificateDer::from(c.content().to_owned()))
-                    .collect(),
-                PrivateKeyDer::from(PrivatePkcs8KeyDer::from(
-                    svid.private_key().content().to_owned(),
-                )),
+        let provider = self.config_builder.crypto_provider().clone();
+        let verifier: Arc<dyn ServerCertVerifier> = if self.destinations.is_empty() {
+            WebPkiServerVerifier::builder_with_provider(roots, provider.clone()).build()?
+        } else {
+            Arc::new(DestinationVerifier::new(
+                roots,
+                &self.destinations,
+                x509_context.bundle_set(),
+                self.root_store_options,
+                &provider,
+            )?)
+        };
+        let bundles = self.complete_chains.then(|| x509_context.bundle_set());
+        let resolver = Arc::new(SingleCertAndKey::from(certified_key(
+            svid, bundles, &provider,
+        )?));
+        if self.self_test {
+            self_test::client(resolver.clone(), svid, x509_context.bundle_set(), &provider)
+                .map_err(|e| ClientConfigStreamError::StreamError(e.into()))?;
+        }
+        Ok(ClientParts { verifier, resolver })
+    }
+
+    /// Publish the identity of the SVID selected from `x509_context` and run
+    /// the rotation hooks if it changed.
+    fn publish_identity(&self, x509_context: &X509Context) {
+        let Some(svid) = self.svid.select(x509_context) else {
+            return;
+        };
+        let identity = WorkloadIdentity::from_svid(svid);
+        let mut previous = None;
+        let rotated = self.identities.send_if_modified(|current| {
+            if *current == identity {
+                return false;
+            }
+            previous = std::mem::replace(current, identity.clone());
+            true
+        });
+        if rotated && let Some(identity) = identity {
+            let info = RotationInfo { identity, previous };
+            for hook in self.rotation_hooks.iter() {
+                hook(&info);
+            }
+        }
+    }
+
+    fn build_client_config(
+        &self,
+        x509_context: &X509Context,
+    ) -> Result<Arc<ClientConfig>, ClientConfigStreamError> {
+        Ok(self.client_config(self.build_client_parts(x509_context)?))
+    }
+
+    fn client_config(&self, parts: ClientParts) -> Arc<ClientConfig> {
+        let mut config = self
+            .config_builder
+            .clone()
+            .dangerous()
+            .with_custom_certificate_verifier(parts.verifier)
+            .with_client_cert_resolver(parts.resolver);
+        if let Some(resumption) = &self.resumption {
+            config.resumption = resumption.clone();
+        }
+        for map in self.config_mappers.iter() {
+            map(&mut config);
+        }
+        Arc::from(config)
+    }
+
+    /// Polls for the next Workload API update, or for a trust domain change
+    /// that requires rebuilding from the last update.
+    fn poll_update(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Arc<X509Context>, ClientConfigStreamError>>> {
+        // a trust domain change rebuilds from the last known context
+        while let Poll::Ready(Some(_)) = Pin::new(&mut self.trust_domain_updates).poll_next(cx) {
+            if let Some(x509_context) = &self.last_context {
+                #[cfg(feature = "tracing")]
+                debug!("trust domains changed, rebuilding client config");
+
+                return Poll::Ready(Some(Ok(x509_context.clone())));
+            }
+        }
+        // a refresh fetches a new context from the source
+        while Pin::new(&mut self.refreshes).poll_next(cx) == Poll::Ready(Some(())) {
+            let source = self.source.clone();
+            if let Some(refresh) = self
+                .refresh
+                .replace(tokio::spawn(async move { source.fetch().await }))
+            {
+                refresh.abort();
+            }
+        }
+        if let Some(refresh) = &mut self.refresh
+            && let Poll::Ready(fetched) = Pin::new(refresh).poll(cx)
+        {
+            self.refresh = None;
+
+            #[cfg(feature = "tracing")]
+            debug!("refresh requested, rebuilding client config");
+
+            return Poll::Ready(Some(match fetched {
+                Ok(Ok(x509_context)) => Ok(self.record(x509_context)),
+                Ok(Err(err)) => Err(ClientConfigStreamError::StreamError(err)),
+                Err(err) => Err(ClientConfigStreamError::StreamError(err.into())),
+            }));
+        }
+        loop {
+            let x509_context = match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(x509_context))) => match &mut self.holdback {
+                    Some(holdback) => {
+                        holdback.push(x509_context, self.last_context.as_deref());
+                        continue;
+                    }
+                    None => x509_context,
+                },
+                Poll::Ready(Some(Err(err))) => {
+                    self.health.report_fatal(&err);
+                    return Poll::Ready(Some(Err(ClientConfigStreamError::StreamError(err))));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => match &mut self.holdback {
+                    Some(holdback) => ready!(holdback.poll_release(cx)),
+                    None => return Poll::Pending,
+                },
+            };
+            // an update identical to the last one would only churn consumers
+            if self.last_context.as_deref() == Some(&x509_context) {
+                #[cfg(feature = "tracing")]
+                debug!("skipping unchanged Workload API update");
+
+                continue;
+            }
+            return Poll::Ready(Some(Ok(self.record(x509_context))));
+        }
+    }
+
+    /// Polls for a shutdown, closing the stream once it is requested.
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.shut_down {
+            return true;
+        }
+        while let Poll::Ready(Some(shutdown)) = Pin::new(&mut self.shutdown).poll_next(cx) {
+            self.shut_down |= shutdown;
+        }
+        if self.shut_down {
+            #[cfg(feature = "tracing")]
+            debug!("shutting down client config stream");
+
+            self.inner = Box::pin(tokio_stream::pending());
+            self.last_context = None;
+            self.holdback = None;
+            if let Some(refresh) = self.refresh.take() {
+                refresh.abort();
+            }
+            self.contexts.send_replace(None);
+            self.configs.send_replace(None);
+            self.identities.send_replace(None);
+        }
+        self.shut_down
+    }
+
+    /// Keeps `x509_context` as the last update and publishes it.
+    fn record(&mut self, x509_context: X509Context) -> Arc<X509Context> {
+        let x509_context = Arc::new(x509_context);
+        self.last_context = Some(x509_context.clone());
+        let previous = self.contexts.send_replace(Some(x509_context.clone()));
+        if self.rotation_events.receiver_count() > 0
+            && let Some(event) = RotationEvent::diff(
+                previous.as_deref(),
+                &x509_context,
+                &self.svid,
+                &self.get_trust_domains(),
             )
-            .map_err(ClientConfigStreamError::RustlsError)?;
-        Ok(Arc::from(config))
+        {
+            let _ = self.rotation_events.send(event);
+        }
+        x509_context
     }
+
+    /// Polls for the next set of [`ClientParts`], built the same way as the
+    /// configs yielded by the [`Stream`] implementation.
+    pub(crate) fn poll_parts(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ClientParts, ClientConfigStreamError>>> {
+        if self.poll_shutdown(cx) {
+            return Poll::Ready(None);
+        }
+        loop {
+            let Poll::Ready(update) = self.poll_update(cx) else {
+                return self.health.poll_stale(cx).map(|stale| {
+                    Some(match stale {
+                        Stale::Reject => Ok(rejecting_parts(self.config_builder.crypto_provider())),
+                        Stale::Fail(err) => Err(ClientConfigStreamError::StreamError(err.into())),
+                    })
+                });
+            };
+            let parts = update.map(|res| {
+                res.and_then(|ctx| {
+                    let parts = self.build_client_parts(&ctx)?;
+                    self.publish_identity(&ctx);
+                    Ok(parts)
+                })
+            });
+            if let ControlFlow::Break(parts) = self.health.observe(parts) {
+                return Poll::Ready(parts);
+            }
+        }
+    }
+}
+
+/// The server certificate verifier and client certificate resolver built
+/// from one Workload API update.
+pub struct ClientParts {
+    pub verifier: Arc<dyn ServerCertVerifier>,
+    pub resolver: Arc<dyn ResolvesClientCert>,
 }
 
 impl Stream for SpiffeClientConfigStream {
     type Item = Result<Arc<ClientConfig>, ClientConfigStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.inner.as_mut().poll_next(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(Err(err))) => {
-                Poll::Ready(Some(Err(ClientConfigStreamError::StreamError(err.into()))))
-            }
-            Poll::Ready(Some(Ok(x509_context))) => match self.build_client_config(&x509_context) {
-                Ok(config) => Poll::Ready(Some(Ok(config))),
-                Err(err) => Poll::Ready(Some(Err(err))),
-            },
+        let item =
+            ready!(self.poll_parts(cx)).map(|res| res.map(|parts| self.client_config(parts)));
+        if let Some(Ok(config)) = &item {
+            self.configs.send_replace(Some(config.clone()));
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// A mapper registered with `map_config`.
+type ConfigMapper = Arc<dyn Fn(&mut ClientConfig) + Send + Sync>;
+
+/// Parts failing every handshake, for [`StalePolicy::Reject`].
+fn rejecting_parts(provider: &Arc<CryptoProvider>) -> ClientParts {
+    ClientParts {
+        verifier: Arc::new(Rejecting(provider.clone())),
+        resolver: Arc::new(Rejecting(provider.clone())),
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use rustls_config_stream::ClientConfigStreamBuilder;
+    use spiffe::{TrustDomain, X509Context};
+    use tokio_stream::StreamExt;
+
+    use crate::{
+        SourceError, SpiffeClientConfigStream,
+        svid_source::{ConnectFuture, SvidSource},
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    /// A source that cannot be connected to.
+    struct Unavailable;
+
+    impl SvidSource for Unavailable {
+        fn connect(&self) -> ConnectFuture<'_> {
+            Box::pin(async { Err("unavailable".into()) })
         }
     }
+
+    fn update(ca: &Ca) -> Result<X509Context, SourceError> {
+        Ok(context(
+            vec![ca.svid("spiffe://example.org/workload", DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        ))
+    }
+
+    #[tokio::test]
+    async fn fetches_publish_nothing() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let (source, tx) = ChannelSource::new(update(&ca)?);
+        let rotations = Arc::new(AtomicUsize::new(0));
+        let counter = rotations.clone();
+        let mut builder = SpiffeClientConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source)
+            .on_rotation(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        let identities = builder.identity_updates();
+        let contexts = builder.context_updates();
+
+        builder.fetch_client_config().await?;
+        assert_eq!(rotations.load(Ordering::Relaxed), 0);
+        assert!(identities.borrow().is_none());
+        assert!(contexts.borrow().is_none());
+        assert!(!identities.has_changed()?);
+
+        tx.send(Ok(update(&ca)?)).await?;
+        let mut stream = builder.build().await?;
+        stream.next().await.ok_or("stream ended")??;
+        assert_eq!(rotations.load(Ordering::Relaxed), 1);
+        assert!(identities.borrow().is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn source_setters_keep_earlier_wrappers() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let config = SpiffeClientConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_static_fallback(update(&ca)?, None)
+            .with_svid_source(Unavailable)
+            .fetch_client_config()
+            .await;
+        assert!(config.is_ok());
+        Ok(())
+    }
 }
@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::sync::watch;
+#[cfg(feature = "tracing")]
+use tracing::info;
+
+use crate::WorkloadIdentity;
+
+/// Tracks accepted connections so that connections accepted before an SVID
+/// rotation can be closed a grace period after it.
+///
+/// Register each connection after its handshake and close it, with
+/// `close_notify`, once its [`ConnectionGuard`] is drained:
+///
+/// ```rust,no_run
+/// # async fn example(
+/// #     registry: rustls_spiffe::ConnectionRegistry,
+/// #     mut tls: tokio_rustls::server::TlsStream<tokio::net::TcpStream>,
+/// # ) {
+/// use tokio::io::AsyncWriteExt;
+///
+/// let mut guard = registry.register();
+/// tokio::select! {
+///     () = async { /* serve the connection */ } => {}
+///     () = guard.drained() => {
+///         // sends close_notify
+///         let _ = tls.shutdown().await;
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConnectionRegistry {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Incremented on every rotation and drain.
+    epoch: AtomicU64,
+    /// Connections registered in an epoch before this one are drained.
+    drained: watch::Sender<u64>,
+    active: AtomicUsize,
+}
+
+impl ConnectionRegistry {
+    /// Drain connections `grace` after each rotation received on
+    /// `identities`, e.g. from a builder's `identity_updates`.
+    ///
+    /// Must be called within a Tokio runtime.
+    #[must_use]
+    pub fn new(identities: watch::Receiver<Option<WorkloadIdentity>>, grace: Duration) -> Self {
+        let inner = Arc::new(Inner {
+            epoch: AtomicU64::new(0),
+            drained: watch::Sender::new(0),
+            active: AtomicUsize::new(0),
+        });
+        tokio::spawn(watch_rotations(Arc::downgrade(&inner), identities, grace));
+        Self { inner }
+    }
+
+    /// Track a connection accepted under the current SVID.
+    #[must_use]
+    pub fn register(&self) -> ConnectionGuard {
+        self.inner.active.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            inner: self.inner.clone(),
+            epoch: self.inner.epoch.load(Ordering::Relaxed),
+            drained: self.inner.drained.subscribe(),
+        }
+    }
+
+    /// Drain every connection registered so far, now.
+    pub fn drain_all(&self) {
+        self.inner.drain_now();
+    }
+
+    /// Returns the number of registered connections whose guards have not
+    /// been dropped.
+    #[must_use]
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::Relaxed)
+    }
+}
+
+impl Inner {
+    /// Start a new epoch, returning it.
+    fn next_epoch(&self) -> u64 {
+        self.epoch.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn drain_before(&self, epoch: u64) {
+        self.drained.send_if_modified(|drained| {
+            if *drained >= epoch {
+                return false;
+            }
+            *drained = epoch;
+            true
+        });
+    }
+
+    fn drain_now(&self) {
+        self.drain_before(self.next_epoch());
+    }
+}
+
+async fn watch_rotations(
+    inner: Weak<Inner>,
+    mut identities: watch::Receiver<Option<WorkloadIdentity>>,
+    grace: Duration,
+) {
+    let mut current = identities.borrow_and_update().clone();
+    while identities.changed().await.is_ok() {
+        let identity = identities.borrow_and_update().clone();
+        let rotated = current.is_some() && identity.is_some() && identity != current;
+        if identity.is_some() {
+            current = identity;
+        }
+        if !rotated {
+            continue;
+        }
+        let Some(registry) = inner.upgrade() else {
+            return;
+        };
+        let epoch = registry.next_epoch();
+        drop(registry);
+
+        #[cfg(feature = "tracing")]
+        info!(name: "connection_registry", grace_secs = grace.as_secs(), "SVID rotated, draining older connections after grace period");
+
+        let inner = inner.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            if let Some(registry) = inner.upgrade() {
+                registry.drain_before(epoch);
+            }
+        });
+    }
+}
+
+/// A connection registered with a [`ConnectionRegistry`]; dropping it
+/// unregisters the connection.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    inner: Arc<Inner>,
+    epoch: u64,
+    drained: watch::Receiver<u64>,
+}
+
+impl ConnectionGuard {
+    /// Resolves once the connection should be closed.
+    pub async fn drained(&mut self) {
+        let epoch = self.epoch;
+        if self
+            .drained
+            .wait_for(|drained| *drained > epoch)
+            .await
+            .is_err()
+        {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Returns whether the connection should be closed.
+    #[must_use]
+    pub fn is_drained(&self) -> bool {
+        *self.drained.borrow() > self.epoch
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.inner.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use spiffe::{SpiffeId, SpiffeIdError};
+    use tokio::sync::watch;
+
+    use super::ConnectionRegistry;
+    use crate::WorkloadIdentity;
+
+    const GRACE: Duration = Duration::from_secs(30);
+
+    fn identity(serial: u8) -> Result<Option<WorkloadIdentity>, SpiffeIdError> {
+        Ok(Some(WorkloadIdentity {
+            spiffe_id: SpiffeId::new("spiffe://example.org/workload")?,
+            serial: vec![serial],
+            not_before: SystemTime::UNIX_EPOCH,
+            not_after: SystemTime::UNIX_EPOCH,
+        }))
+    }
+
+    /// Let the rotation watcher and its grace timers run.
+    async fn settle() {
+        for _ in 0..4 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drains_older_connections_after_the_grace_period() -> Result<(), SpiffeIdError> {
+        let (identities, rx) = watch::channel(identity(1)?);
+        let registry = ConnectionRegistry::new(rx, GRACE);
+        let old = registry.register();
+        settle().await;
+
+        identities.send_replace(identity(2)?);
+        settle().await;
+        let new = registry.register();
+        tokio::time::advance(GRACE / 2).await;
+        settle().await;
+        assert!(!old.is_drained());
+
+        tokio::time::advance(GRACE / 2).await;
+        settle().await;
+        assert!(old.is_drained());
+        assert!(!new.is_drained());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_first_and_missing_identities() -> Result<(), SpiffeIdError> {
+        let (identities, rx) = watch::channel(None);
+        let registry = ConnectionRegistry::new(rx, GRACE);
+        let guard = registry.register();
+        settle().await;
+
+        identities.send_replace(identity(1)?);
+        settle().await;
+        identities.send_replace(None);
+        settle().await;
+        identities.send_replace(identity(1)?);
+        settle().await;
+        tokio::time::advance(GRACE).await;
+        settle().await;
+        assert!(!guard.is_drained());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drain_all_drains_registered_connections() {
+        let (_identities, rx) = watch::channel(None);
+        let registry = ConnectionRegistry::new(rx, GRACE);
+        let mut guard = registry.register();
+        assert_eq!(registry.active(), 1);
+
+        registry.drain_all();
+        guard.drained().await;
+        assert!(!registry.register().is_drained());
+        drop(guard);
+        assert_eq!(registry.active(), 0);
+    }
+}
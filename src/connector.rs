@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    Uri,
+    rt::{Read, ReadBufCursor, Write},
+};
+#[cfg(feature = "hyper")]
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::ServerName;
+use spiffe::SpiffeId;
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsConnector, client::TlsStream};
+use tower::Service;
+
+use crate::{ClientConfigProvider, ConnectionRegistry, extract_spiffe_id};
+
+type Authorizer = Arc<dyn Fn(&SpiffeId) -> bool + Send + Sync>;
+
+/// A connector dialing TLS with the current config of a
+/// [`ClientConfigProvider`].
+///
+/// Use it for tonic channels with `Endpoint::connect_with_connector` or,
+/// with the `hyper` feature, as the HTTPS connector of a `hyper_util`
+/// client, which then needs no rebuilding on rotation.
+///
+/// Every new connection handshakes with the SVID and bundles current at
+/// that time. Established connections keep the SVID they were created with;
+/// with [`with_connection_registry`](Self::with_connection_registry) they are
+/// closed a grace period after a rotation, so the channel reconnects with the
+/// new SVID. Every URI is dialed with TLS, on port 443 unless it names one.
+/// For tonic the provider's builder should offer HTTP/2 in ALPN, e.g. with
+/// `with_grpc`.
+///
+/// ```rust
+/// # async fn example(provider: std::sync::Arc<rustls_spiffe::ClientConfigProvider>) -> Result<(), Box<dyn std::error::Error>> {
+/// use rustls_spiffe::SpiffeConnector;
+///
+/// let connector = SpiffeConnector::new(provider)
+///     .with_server_ids(vec!["spiffe://example.org/backend".try_into()?]);
+/// let channel = tonic::transport::Endpoint::from_static("https://backend.example.org:8443")
+///     .connect_with_connector_lazy(connector);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SpiffeConnector {
+    provider: Arc<ClientConfigProvider>,
+    server_name: Option<ServerName<'static>>,
+    authorize: Option<Authorizer>,
+    registry: Option<ConnectionRegistry>,
+}
+
+impl SpiffeConnector {
+    /// Dial with the configs of `provider`, verifying servers against the
+    /// host of each URI.
+    #[must_use]
+    pub const fn new(provider: Arc<ClientConfigProvider>) -> Self {
+        Self {
+            provider,
+            server_name: None,
+            authorize: None,
+            registry: None,
+        }
+    }
+
+    /// Send `server_name` in SNI and verify the server certificate against
+    /// it, instead of the host of the URI.
+    #[must_use]
+    pub fn with_server_name(mut self, server_name: ServerName<'static>) -> Self {
+        self.server_name = Some(server_name);
+        self
+    }
+
+    /// Only accept servers whose SPIFFE ID `authorize` returns `true` for,
+    /// failing the connection otherwise.
+    ///
+    /// The SPIFFE ID is checked after the certificate has been verified.
+    #[must_use]
+    pub fn with_server_authorizer(
+        mut self,
+        authorize: impl Fn(&SpiffeId) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.authorize = Some(Arc::new(authorize));
+        self
+    }
+
+    /// Only accept servers presenting one of `ids`; see
+    /// [`with_server_authorizer`](Self::with_server_authorizer).
+    #[must_use]
+    pub fn with_server_ids(self, ids: Vec<SpiffeId>) -> Self {
+        self.with_server_authorizer(move |id| ids.contains(id))
+    }
+
+    /// Register connections with `registry` and close them once drained,
+    /// e.g. a grace period after each rotation of the provider's SVID.
+    #[must_use]
+    pub fn with_connection_registry(mut self, registry: ConnectionRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    async fn connect(self, uri: Uri) -> io::Result<SpiffeConnection> {
+        let host = uri
+            .host()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URI has no host"))?;
+        let server_name = match self.server_name {
+            Some(server_name) => server_name,
+            None => ServerName::try_from(host.to_owned())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        };
+        let tcp = TcpStream::connect((host, uri.port_u16().unwrap_or(443))).await?;
+        let tls = TlsConnector::from(self.provider.get_config())
+            .connect(server_name, tcp)
+            .await?;
+        if let Some(authorize) = &self.authorize {
+            let leaf = tls.get_ref().1.peer_certificates().and_then(<[_]>::first);
+            if !extract_spiffe_id(leaf).is_some_and(|id| authorize(&id)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "server SPIFFE ID is not authorized",
+                ));
+            }
+        }
+        let drained = self.registry.map(|registry| {
+            let mut guard = registry.register();
+            Box::pin(async move { guard.drained().await }) as Drained
+        });
+        Ok(SpiffeConnection {
+            tls: TokioIo::new(tls),
+            drained,
+            closed: false,
+        })
+    }
+}
+
+impl Service<Uri> for SpiffeConnector {
+    type Response = SpiffeConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(connector.connect(uri))
+    }
+}
+
+type Drained = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A TLS connection made by a [`SpiffeConnector`], reading as closed once
+/// drained by its connection registry.
+pub struct SpiffeConnection {
+    tls: TokioIo<TlsStream<TcpStream>>,
+    drained: Option<Drained>,
+    closed: bool,
+}
+
+impl SpiffeConnection {
+    /// Returns the TLS stream of the connection.
+    #[must_use]
+    pub fn get_ref(&self) -> &TlsStream<TcpStream> {
+        self.tls.inner()
+    }
+}
+
+impl Read for SpiffeConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.closed
+            && let Some(drained) = &mut self.drained
+            && drained.as_mut().poll(cx).is_ready()
+        {
+            self.closed = true;
+            self.drained = None;
+        }
+        if self.closed {
+            // end of stream, so the channel reconnects
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.tls).poll_read(cx, buf)
+    }
+}
+
+impl Write for SpiffeConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.tls).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.tls).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.tls).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.tls.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.tls).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(feature = "hyper")]
+impl Connection for SpiffeConnection {
+    fn connected(&self) -> Connected {
+        let connected = Connected::new();
+        if self.get_ref().get_ref().1.alpn_protocol() == Some(b"h2") {
+            connected.negotiated_h2()
+        } else {
+            connected
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! The subset of the SPIRE agent `delegatedidentity.v1` API messages used to
+//! subscribe to the X509-SVIDs and bundles of other workloads.
+
+use std::collections::HashMap;
+
+pub const SUBSCRIBE_TO_X509_SVIDS: &str =
+    "/spire.api.agent.delegatedidentity.v1.DelegatedIdentity/SubscribeToX509SVIDs";
+pub const SUBSCRIBE_TO_X509_BUNDLES: &str =
+    "/spire.api.agent.delegatedidentity.v1.DelegatedIdentity/SubscribeToX509Bundles";
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SubscribeToX509SvidsRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub selectors: Vec<Selector>,
+    #[prost(int32, tag = "2")]
+    pub pid: i32,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Selector {
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SubscribeToX509SvidsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub x509_svids: Vec<X509SvidWithKey>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct X509SvidWithKey {
+    #[prost(message, optional, tag = "1")]
+    pub x509_svid: Option<X509Svid>,
+    /// The PKCS#8 DER private key of the SVID.
+    #[prost(bytes = "vec", tag = "2")]
+    pub x509_svid_key: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct X509Svid {
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub cert_chain: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SubscribeToX509BundlesRequest {}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SubscribeToX509BundlesResponse {
+    /// The concatenated DER authorities of each bundle, by trust domain ID.
+    #[prost(map = "string, bytes", tag = "1")]
+    pub ca_certificates: HashMap<String, Vec<u8>>,
+}
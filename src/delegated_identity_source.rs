@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    Request, Streaming, client::Grpc, codegen::http::uri::PathAndQuery, transport::Channel,
+};
+use tonic_prost::ProstCodec;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::{
+    SourceError,
+    delegated_identity_proto::{
+        SUBSCRIBE_TO_X509_BUNDLES, SUBSCRIBE_TO_X509_SVIDS, Selector,
+        SubscribeToX509BundlesRequest, SubscribeToX509BundlesResponse, SubscribeToX509SvidsRequest,
+        SubscribeToX509SvidsResponse,
+    },
+    grpc::channel_for_endpoint,
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] streaming the X509-SVIDs and bundles of another
+/// workload from the SPIRE agent Delegated Identity API, so a privileged
+/// proxy can build configs on behalf of the workloads behind it.
+///
+/// The workload is identified by selectors, which must all match one of its
+/// registration entries, or by the PID of a process the agent attests
+/// itself. An update is yielded once both the SVIDs and the bundles,
+/// including federated ones, have been received, and again whenever either
+/// changes; SVID updates without any SVID, e.g. before the agent has synced
+/// the workload's entries, are skipped.
+///
+/// The API is served on the agent's admin socket, and only to callers whose
+/// SPIFFE ID is listed in the agent's `authorized_delegates`. Build one
+/// config stream, with its own source, per workload.
+#[derive(Clone, Debug)]
+pub struct DelegatedIdentitySource {
+    target: Target,
+    selectors: Vec<Selector>,
+    pid: i32,
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Endpoint(String),
+    Channel(Channel),
+}
+
+impl DelegatedIdentitySource {
+    /// Connect to the SPIRE agent admin API at `endpoint`, either
+    /// `unix:///path/to/socket` or `tcp://IP:port`, e.g.
+    /// `unix:///run/spire/agent/admin.sock`.
+    ///
+    /// Identify the workload with [`with_selector`](Self::with_selector) or
+    /// [`with_pid`](Self::with_pid); the agent refuses subscriptions with
+    /// neither.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self::with_target(Target::Endpoint(endpoint.into()))
+    }
+
+    /// Subscribe over an already-configured [`Channel`].
+    #[must_use]
+    pub const fn from_channel(channel: Channel) -> Self {
+        Self::with_target(Target::Channel(channel))
+    }
+
+    const fn with_target(target: Target) -> Self {
+        Self {
+            target,
+            selectors: Vec::new(),
+            pid: 0,
+        }
+    }
+
+    /// Add a selector the workload's registration entry must match, such as
+    /// `with_selector("k8s", "sa:payments")`.
+    #[must_use]
+    pub fn with_selector(mut self, kind: impl Into<String>, value: impl Into<String>) -> Self {
+        self.selectors.push(Selector {
+            kind: kind.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Identify the workload as the process `pid`, which the agent attests
+    /// itself, instead of by selectors.
+    #[must_use]
+    pub const fn with_pid(mut self, pid: i32) -> Self {
+        self.pid = pid;
+        self
+    }
+
+    async fn channel(&self) -> Result<Channel, SourceError> {
+        match &self.target {
+            Target::Channel(channel) => Ok(channel.clone()),
+            Target::Endpoint(endpoint) => channel_for_endpoint(endpoint, "SPIRE agent admin").await,
+        }
+    }
+
+    async fn subscribe(
+        &self,
+    ) -> Result<
+        (
+            Streaming<SubscribeToX509SvidsResponse>,
+            Streaming<SubscribeToX509BundlesResponse>,
+        ),
+        SourceError,
+    > {
+        let mut grpc = Grpc::new(self.channel().await?);
+        grpc.ready().await?;
+        let request = SubscribeToX509SvidsRequest {
+            selectors: self.selectors.clone(),
+            pid: self.pid,
+        };
+        let svids = grpc
+            .server_streaming(
+                Request::new(request),
+                PathAndQuery::from_static(SUBSCRIBE_TO_X509_SVIDS),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+        grpc.ready().await?;
+        let bundles = grpc
+            .server_streaming(
+                Request::new(SubscribeToX509BundlesRequest {}),
+                PathAndQuery::from_static(SUBSCRIBE_TO_X509_BUNDLES),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+        Ok((svids, bundles))
+    }
+
+    async fn watch(
+        mut svids: Streaming<SubscribeToX509SvidsResponse>,
+        mut bundles: Streaming<SubscribeToX509BundlesResponse>,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        let mut latest = Latest::default();
+        loop {
+            let applied = tokio::select! {
+                response = svids.message() => match response {
+                    Ok(Some(response)) => latest.apply_svids(response),
+                    Ok(None) => return,
+                    Err(err) => Err(err.into()),
+                },
+                response = bundles.message() => match response {
+                    Ok(Some(response)) => latest.apply_bundles(response),
+                    Ok(None) => return,
+                    Err(err) => Err(err.into()),
+                },
+                () = tx.closed() => return,
+            };
+            let update = match applied {
+                Ok(true) => latest.x509_context(),
+                Ok(false) => continue,
+                Err(err) => Err(err),
+            };
+            let failed = update.is_err();
+            if tx.send(update).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+impl SvidSource for DelegatedIdentitySource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let (svids, bundles) = self.subscribe().await?;
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(Self::watch(svids, bundles, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+}
+
+/// The latest SVIDs and bundles received.
+#[derive(Default)]
+struct Latest {
+    svids: Option<Vec<X509Svid>>,
+    bundles: Option<X509BundleSet>,
+}
+
+impl Latest {
+    /// Store the SVIDs of `response`, returning whether an update can be
+    /// yielded.
+    fn apply_svids(&mut self, response: SubscribeToX509SvidsResponse) -> Result<bool, SourceError> {
+        let svids = response
+            .x509_svids
+            .into_iter()
+            .map(|svid| {
+                let chain = svid
+                    .x509_svid
+                    .ok_or("SPIRE agent returned an SVID without certificates")?
+                    .cert_chain
+                    .concat();
+                Ok(X509Svid::parse_from_der(&chain, &svid.x509_svid_key)?)
+            })
+            .collect::<Result<Vec<_>, SourceError>>()?;
+        if svids.is_empty() {
+            return Ok(false);
+        }
+
+        #[cfg(feature = "tracing")]
+        debug!(name: "delegated_identity_source", count = svids.len(), "received delegated X509-SVIDs");
+
+        self.svids = Some(svids);
+        Ok(self.bundles.is_some())
+    }
+
+    /// Store the bundles of `response`, returning whether an update can be
+    /// yielded.
+    fn apply_bundles(
+        &mut self,
+        response: SubscribeToX509BundlesResponse,
+    ) -> Result<bool, SourceError> {
+        let mut bundles = X509BundleSet::new();
+        for (trust_domain_id, authorities) in response.ca_certificates {
+            bundles.add_bundle(X509Bundle::parse_from_der(
+                TrustDomain::new(&trust_domain_id)?,
+                &authorities,
+            )?);
+        }
+
+        #[cfg(feature = "tracing")]
+        debug!(name: "delegated_identity_source", "received delegated X509 bundles");
+
+        self.bundles = Some(bundles);
+        Ok(self.svids.is_some())
+    }
+
+    fn x509_context(&self) -> Result<X509Context, SourceError> {
+        let (Some(svids), Some(bundles)) = (&self.svids, &self.bundles) else {
+            return Err("SPIRE agent delegated SVIDs or bundles not received".into());
+        };
+        Ok(X509Context::new(svids.clone(), bundles.clone()))
+    }
+}
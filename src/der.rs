@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! Just enough DER to build certificate requests and keys by hand.
+
+pub const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+pub const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+#[cfg(any(feature = "spire-server", all(test, feature = "aws-lc-rs")))]
+pub const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+#[cfg(any(feature = "spire-server", all(test, feature = "aws-lc-rs")))]
+pub const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// The DER encoding of `contents` with `tag`.
+pub fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+/// The DER BIT STRING of `bits`, with no unused bits.
+#[cfg(any(feature = "spire-server", all(test, feature = "aws-lc-rs")))]
+pub fn bit_string(bits: &[u8]) -> Vec<u8> {
+    der(0x03, &[&[0], bits].concat())
+}
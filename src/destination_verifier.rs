@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{collections::HashMap, sync::Arc};
+
+use rustls::{
+    CertificateError, DigitallySignedStruct, Error, RootCertStore, SignatureScheme,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use rustls_config_stream::ClientConfigStreamError;
+use spiffe::{TrustDomain, X509BundleSet};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::trust_domain_store::{RootStoreOptions, root_store_for};
+
+/// A [`ServerCertVerifier`] that verifies each server against the roots of the
+/// trust domain mapped to its server name, falling back to the roots of all
+/// configured trust domains for unmapped server names.
+///
+/// A mapped trust domain without a bundle fails closed: servers reached under
+/// that name are rejected rather than verified against the fallback roots.
+#[derive(Debug)]
+pub struct DestinationVerifier {
+    default: Arc<WebPkiServerVerifier>,
+    destinations: HashMap<ServerName<'static>, Option<Arc<WebPkiServerVerifier>>>,
+}
+
+impl DestinationVerifier {
+    pub fn new(
+        default_roots: Arc<RootCertStore>,
+        destinations: &HashMap<ServerName<'static>, TrustDomain>,
+        bundles: &X509BundleSet,
+        options: RootStoreOptions,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Self, ClientConfigStreamError> {
+        let options = RootStoreOptions {
+            strict: false,
+            ..options
+        };
+        let default =
+            WebPkiServerVerifier::builder_with_provider(default_roots, provider.clone()).build()?;
+        let mut verifiers = HashMap::with_capacity(destinations.len());
+        for (server_name, trust_domain) in destinations {
+            let roots = root_store_for(bundles, [trust_domain], options)
+                .map_err(|e| ClientConfigStreamError::StreamError(e.into()))?;
+            let verifier = if roots.is_empty() {
+                #[cfg(feature = "tracing")]
+                warn!(server_name = server_name.to_str().as_ref(), trust_domain = %trust_domain, "no roots for destination trust domain, connections will be rejected");
+
+                None
+            } else {
+                Some(
+                    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                        .build()?,
+                )
+            };
+            verifiers.insert(server_name.clone(), verifier);
+        }
+        Ok(Self {
+            default,
+            destinations: verifiers,
+        })
+    }
+}
+
+impl ServerCertVerifier for DestinationVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let verifier = match self.destinations.get(&server_name.to_owned()) {
+            None => &self.default,
+            Some(Some(verifier)) => verifier,
+            Some(None) => {
+                return Err(Error::InvalidCertificate(CertificateError::UnknownIssuer));
+            }
+        };
+        verifier.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.default.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.default.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.default.supported_verify_schemes()
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use rustls::{
+        CertificateError, Error,
+        client::danger::ServerCertVerifier,
+        crypto::aws_lc_rs,
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    };
+    use spiffe::TrustDomain;
+
+    use super::DestinationVerifier;
+    use crate::{
+        SourceError,
+        test_certs::{Ca, DAY, bundle_set},
+        trust_domain_store::{RootStoreOptions, root_store_for},
+    };
+
+    const NAMES: [&str; 4] = ["mapped.test", "wrong.test", "missing.test", "unmapped.test"];
+
+    fn server_name(name: &str) -> Result<ServerName<'static>, SourceError> {
+        Ok(ServerName::try_from(name.to_owned())?)
+    }
+
+    #[test]
+    fn verifies_against_the_mapped_trust_domain() -> Result<(), SourceError> {
+        let (ca, other) = (Ca::root("example")?, Ca::root("other")?);
+        let svid = ca.svid_with_dns_names("spiffe://example.org/server", &NAMES, DAY)?;
+        let leaf = CertificateDer::from(svid.leaf().content());
+        let bundles = bundle_set(vec![
+            ca.bundle("example.org", false)?,
+            other.bundle("other.org", false)?,
+        ]);
+        let example = TrustDomain::new("example.org")?;
+        let destinations = HashMap::from([
+            (server_name("mapped.test")?, example.clone()),
+            (server_name("wrong.test")?, TrustDomain::new("other.org")?),
+            (
+                server_name("missing.test")?,
+                TrustDomain::new("absent.org")?,
+            ),
+        ]);
+        let default_roots = root_store_for(&bundles, [&example], RootStoreOptions::default())?;
+        let verifier = DestinationVerifier::new(
+            Arc::new(default_roots),
+            &destinations,
+            &bundles,
+            RootStoreOptions::default(),
+            &Arc::new(aws_lc_rs::default_provider()),
+        )?;
+        let verify = |name: &str| -> Result<Result<(), Error>, SourceError> {
+            Ok(verifier
+                .verify_server_cert(&leaf, &[], &server_name(name)?, &[], UnixTime::now())
+                .map(|_| ()))
+        };
+
+        assert_eq!(verify("mapped.test")?, Ok(()));
+        assert_eq!(verify("unmapped.test")?, Ok(()));
+        assert_eq!(
+            verify("wrong.test")?,
+            Err(Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+        // fails closed rather than falling back to the default roots
+        assert_eq!(
+            verify("missing.test")?,
+            Err(Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+        Ok(())
+    }
+}
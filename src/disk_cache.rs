@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use pem::{EncodeConfig, LineEnding, Pem};
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio_stream::StreamExt;
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+use crate::{
+    FallbackSource, SourceError, SpiffeConfigError, TrustDomainHandle, WorkloadIdentity,
+    private_key::pkcs8_private_key,
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+    write_atomic,
+};
+
+const TRUST_DOMAIN_HEADER: &str = "Trust-Domain";
+
+/// An [`SvidSource`] that persists every update of another source to disk and
+/// serves the last persisted update when that source is unavailable on
+/// connect.
+///
+/// The cache is a single PEM file, readable by its owner only and replaced
+/// atomically: each SVID is stored as its PKCS#8 private key followed by its
+/// certificate chain, and each bundle as certificates with a `Trust-Domain`
+/// header; SEC1 and PKCS#1 keys are read as well. It is not encrypted; keep
+/// it on a private, node-local volume.
+///
+/// Bundles of the SVIDs' trust domains are cached, plus those of the trust
+/// domains of a [`TrustDomainHandle`] set with
+/// [`with_trust_domain_handle`](Self::with_trust_domain_handle).
+///
+/// A cached update is only used while its default SVID has not expired and,
+/// with [`with_max_age`](Self::with_max_age), while the cache is younger than
+/// the maximum age. Serving it is logged with the cache age and SVID
+/// expiry; the primary source is then retried as by [`FallbackSource`].
+#[derive(Clone)]
+pub struct DiskCacheSource {
+    primary: Arc<dyn SvidSource>,
+    path: PathBuf,
+    trust_domains: Option<TrustDomainHandle>,
+    max_age: Option<Duration>,
+}
+
+impl DiskCacheSource {
+    /// Cache updates of `primary` in the file at `path`.
+    pub fn new(primary: impl SvidSource, path: impl Into<PathBuf>) -> Self {
+        Self::from_arc(Arc::new(primary), path.into())
+    }
+
+    pub(crate) fn from_arc(primary: Arc<dyn SvidSource>, path: PathBuf) -> Self {
+        Self {
+            primary,
+            path,
+            trust_domains: None,
+            max_age: None,
+        }
+    }
+
+    /// Also cache the bundles of the trust domains configured on `handle`.
+    #[must_use]
+    pub fn with_trust_domain_handle(mut self, handle: TrustDomainHandle) -> Self {
+        self.trust_domains = Some(handle);
+        self
+    }
+
+    /// Ignore the cache once it is older than `max_age`.
+    #[must_use]
+    pub const fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Read the cached update.
+    ///
+    /// # Errors
+    /// The cache cannot be read or parsed, is older than the maximum age, or
+    /// its default SVID has expired.
+    pub async fn load(&self) -> Result<X509Context, SourceError> {
+        let age = tokio::fs::metadata(&self.path)
+            .await?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+        if self.max_age.is_some_and(|max_age| age > max_age) {
+            return Err(format!(
+                "SVID cache {} is older than the maximum age ({}s)",
+                self.path.display(),
+                age.as_secs()
+            )
+            .into());
+        }
+        let x509_context = decode(&tokio::fs::read(&self.path).await?)?;
+        let identity = x509_context
+            .default_svid()
+            .and_then(WorkloadIdentity::from_svid)
+            .ok_or(SpiffeConfigError::MissingSvid)?;
+        if identity.not_after <= SystemTime::now() {
+            return Err(format!(
+                "cached SVID {} in {} has expired",
+                identity.spiffe_id,
+                self.path.display()
+            )
+            .into());
+        }
+
+        #[cfg(feature = "tracing")]
+        warn!(
+            path = %self.path.display(),
+            age_secs = age.as_secs(),
+            spiffe_id = %identity.spiffe_id,
+            expires_in_secs = identity.not_after.duration_since(SystemTime::now()).unwrap_or_default().as_secs(),
+            "serving cached SVID while the SVID source is unavailable"
+        );
+
+        Ok(x509_context)
+    }
+
+    /// Write `x509_context` to the cache, logging failures. Blocks on the
+    /// file system.
+    fn store(&self, x509_context: &X509Context) {
+        let mut trust_domains: Vec<TrustDomain> = self
+            .trust_domains
+            .as_ref()
+            .map(TrustDomainHandle::trust_domains)
+            .unwrap_or_default();
+        for svid in x509_context.svids() {
+            let trust_domain = svid.spiffe_id().trust_domain();
+            if !trust_domains.contains(trust_domain) {
+                trust_domains.push(trust_domain.clone());
+            }
+        }
+        let contents = encode(x509_context, &trust_domains);
+        match write_atomic(&self.path, contents.as_bytes(), true) {
+            Ok(()) => {
+                #[cfg(feature = "tracing")]
+                debug!(path = %self.path.display(), "cached SVID update");
+            }
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                warn!(path = %self.path.display(), error = %err, "failed to cache SVID update");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+            }
+        }
+    }
+}
+
+impl SvidSource for DiskCacheSource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let writer = Arc::new(CacheWriter(self.clone()));
+            match writer.connect().await {
+                Ok(stream) => Ok(stream),
+                Err(err) => {
+                    let Ok(cached) = self.load().await else {
+                        return Err(err);
+                    };
+                    FallbackSource::from_arc(writer, cached).connect().await
+                }
+            }
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        self.primary.describe()
+    }
+}
+
+/// The primary source of a [`DiskCacheSource`], storing every update it
+/// yields.
+struct CacheWriter(DiskCacheSource);
+
+impl SvidSource for CacheWriter {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let cache = self.0.clone();
+            let stream = self.0.primary.connect().await?.then(move |update| {
+                let cache = cache.clone();
+                async move {
+                    // awaited, so that writes land in the order of updates
+                    if let Ok(x509_context) = &update {
+                        let x509_context = x509_context.clone();
+                        let _ =
+                            tokio::task::spawn_blocking(move || cache.store(&x509_context)).await;
+                    }
+                    update
+                }
+            });
+            Ok(Box::pin(stream) as X509ContextStream)
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        self.0.primary.describe()
+    }
+}
+
+fn encode(x509_context: &X509Context, trust_domains: &[TrustDomain]) -> String {
+    let mut pems = Vec::new();
+    for svid in x509_context.svids() {
+        pems.push(Pem::new("PRIVATE KEY", svid.private_key().content()));
+        pems.extend(
+            svid.cert_chain()
+                .iter()
+                .map(|cert| Pem::new("CERTIFICATE", cert.content())),
+        );
+    }
+    for trust_domain in trust_domains {
+        let Some(bundle) = x509_context.bundle_set().get_bundle(trust_domain) else {
+            continue;
+        };
+        for authority in bundle.authorities() {
+            let mut pem = Pem::new("CERTIFICATE", authority.content());
+            // Trust domain names never contain ':' or newlines.
+            let _ = pem
+                .headers_mut()
+                .add(TRUST_DOMAIN_HEADER, trust_domain.as_ref());
+            pems.push(pem);
+        }
+    }
+    pem::encode_many_config(&pems, EncodeConfig::new().set_line_ending(LineEnding::LF))
+}
+
+fn decode(contents: &[u8]) -> Result<X509Context, SourceError> {
+    let mut svids = Vec::new();
+    let mut svid: Option<(Vec<u8>, Vec<u8>)> = None;
+    let mut bundles: Vec<(TrustDomain, Vec<u8>)> = Vec::new();
+    for pem in pem::parse_many(contents)? {
+        if matches!(
+            pem.tag(),
+            "PRIVATE KEY" | "EC PRIVATE KEY" | "RSA PRIVATE KEY"
+        ) {
+            if let Some((key, chain)) = svid.take() {
+                svids.push(X509Svid::parse_from_der(&chain, &key)?);
+            }
+            svid = Some((pkcs8_private_key(pem.contents()), Vec::new()));
+        } else if let Some(trust_domain) = pem.headers().get(TRUST_DOMAIN_HEADER) {
+            let trust_domain = TrustDomain::new(trust_domain)?;
+            match bundles.iter_mut().find(|(td, _)| *td == trust_domain) {
+                Some((_, authorities)) => authorities.extend_from_slice(pem.contents()),
+                None => bundles.push((trust_domain, pem.into_contents())),
+            }
+        } else if let Some((_, chain)) = &mut svid {
+            chain.extend_from_slice(pem.contents());
+        } else {
+            return Err("SVID cache has a certificate before any private key".into());
+        }
+    }
+    if let Some((key, chain)) = svid {
+        svids.push(X509Svid::parse_from_der(&chain, &key)?);
+    }
+    let mut bundle_set = X509BundleSet::new();
+    for (trust_domain, authorities) in bundles {
+        bundle_set.add_bundle(X509Bundle::parse_from_der(trust_domain, &authorities)?);
+    }
+    Ok(X509Context::new(svids, bundle_set))
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use spiffe::TrustDomain;
+    use tokio_stream::StreamExt;
+
+    use super::{DiskCacheSource, decode, encode};
+    use crate::{
+        SourceError,
+        svid_source::{ConnectFuture, SvidSource},
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    const ID: &str = "spiffe://example.org/workload";
+
+    /// A source that cannot be connected to.
+    struct Unavailable;
+
+    impl SvidSource for Unavailable {
+        fn connect(&self) -> ConnectFuture<'_> {
+            Box::pin(async { Err("unavailable".into()) })
+        }
+    }
+
+    #[test]
+    fn updates_round_trip() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let federated = Ca::root("federated")?;
+        let x509_context = context(
+            vec![
+                ca.svid(ID, DAY)?,
+                ca.svid("spiffe://example.org/other", DAY)?,
+            ],
+            vec![
+                ca.bundle("example.org", false)?,
+                federated.bundle("other.org", false)?,
+            ],
+        );
+        let trust_domains = [
+            TrustDomain::new("example.org")?,
+            TrustDomain::new("other.org")?,
+        ];
+
+        let decoded = decode(encode(&x509_context, &trust_domains).as_bytes())?;
+        assert_eq!(decoded, x509_context);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn updates_are_written_as_they_are_streamed() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cache.pem");
+        let ca = Ca::root("root")?;
+        let x509_context = context(
+            vec![ca.svid(ID, DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+        let (source, tx) = ChannelSource::new(x509_context.clone());
+        let cache = DiskCacheSource::new(source, &path);
+
+        let mut stream = cache.connect().await?;
+        tx.send(Ok(x509_context.clone())).await?;
+        stream.next().await.ok_or("stream ended")??;
+        assert_eq!(decode(&std::fs::read(&path)?)?, x509_context);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_svids_are_not_served() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cache.pem");
+        let ca = Ca::root("root")?;
+        let expired = context(
+            vec![ca.expired_svid(ID)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+        std::fs::write(&path, encode(&expired, &[]))?;
+
+        let cache = DiskCacheSource::new(Unavailable, &path);
+        let err = cache.load().await.err().ok_or("served an expired SVID")?;
+        assert!(err.to_string().contains("has expired"));
+        assert!(cache.connect().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn caches_older_than_the_maximum_age_are_not_served() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cache.pem");
+        let ca = Ca::root("root")?;
+        let x509_context = context(
+            vec![ca.svid(ID, DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+        std::fs::write(
+            &path,
+            encode(&x509_context, &[TrustDomain::new("example.org")?]),
+        )?;
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now() - Duration::from_hours(1))?;
+
+        let cache = DiskCacheSource::new(Unavailable, &path);
+        assert_eq!(cache.load().await?, x509_context);
+        let cache = cache.with_max_age(Duration::from_mins(1));
+        let err = cache.load().await.err().ok_or("served a stale cache")?;
+        assert!(err.to_string().contains("older than the maximum age"));
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use spiffe::{SpiffeId, TrustDomain};
+use thiserror::Error;
+
+/// Error produced by an SVID source.
+pub type SourceError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Errors produced while turning SPIFFE Workload API material into
+/// [`rustls`] configuration.
+///
+/// These are surfaced on the config streams wrapped in
+/// `ClientConfigStreamError::StreamError` / `ServerConfigStreamError::StreamError`
+/// and can be recovered with `downcast_ref::<SpiffeConfigError>()`. The
+/// lower-level streams, such as `SpiffeCertifiedKeyStream`, yield them directly.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SpiffeConfigError {
+    /// A configured trust domain has no bundle in the Workload API update.
+    ///
+    /// Only returned when strict trust domains are enabled on the builder.
+    #[error("no bundle for configured trust domain {0}")]
+    MissingBundle(TrustDomain),
+
+    /// The SVID selected on the builder is not in the Workload API update.
+    #[error("no SVID with SPIFFE ID {0}")]
+    SvidNotFound(SpiffeId),
+
+    /// None of the configured trust domains contributed a root certificate.
+    #[error("no root certificates for the configured trust domains")]
+    MissingRoots,
+
+    /// The Workload API update contains no default SVID.
+    #[error("no default SVID")]
+    MissingSvid,
+
+    /// The SVID source, by default the Workload API, returned an error.
+    #[error("svid source error")]
+    Source(#[source] SourceError),
+
+    /// The SVID source could not be connected within the retries of the
+    /// configured `RetryPolicy`; holds the number of retries and the last
+    /// error.
+    #[error("svid source unavailable after {0} retries")]
+    RetriesExhausted(u32, #[source] SourceError),
+
+    /// No config was built within the maximum staleness configured on the
+    /// builder; holds the time since the last config was built.
+    #[error("no config built for {0:?}, over the maximum staleness")]
+    Stale(std::time::Duration),
+
+    /// No config was built within the timeout passed to `wait_ready`.
+    #[error("no config built within {0:?}")]
+    NotReady(std::time::Duration),
+
+    /// No SVID update arrived within the startup timeout configured on the
+    /// builder; holds the timeout and where the builder's SVID source reads
+    /// from.
+    #[error(
+        "no SVID update within {timeout:?} of startup; {}",
+        startup_hint(svid_source.as_deref())
+    )]
+    StartupTimeout {
+        /// The startup timeout.
+        timeout: std::time::Duration,
+        /// The [`describe`](crate::SvidSource::describe)d SVID source, e.g.
+        /// the Workload API endpoints in use, or `None` if no Workload API
+        /// socket is configured or was detected.
+        svid_source: Option<String>,
+    },
+
+    /// The crypto provider of the config stream builder is not in FIPS mode,
+    /// with the `fips` feature enabled.
+    #[error("crypto provider is not FIPS-compliant")]
+    NotFips,
+
+    /// The in-memory handshake testing a freshly built config failed, so the
+    /// config was not yielded.
+    #[error("self-test handshake failed")]
+    SelfTest(#[source] rustls::Error),
+
+    /// Writing SVID or bundle files failed.
+    #[error("i/o error")]
+    Io(#[from] std::io::Error),
+
+    /// Wrapper for any [`rustls::Error`] error.
+    #[error("rustls error")]
+    Rustls(#[from] rustls::Error),
+}
+
+fn startup_hint(source: Option<&str>) -> String {
+    source.map_or_else(
+        || "no Workload API socket found: set SPIFFE_ENDPOINT_SOCKET or configure the endpoint on the SVID source".into(),
+        |source| format!("check that {source} is reachable and this workload is registered"),
+    )
+}
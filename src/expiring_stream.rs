@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+use tokio_rustls::server::TlsStream;
+
+use crate::svid_extractor::expiry_timer;
+
+/// A connection accepted with peer expiry enforcement, failing reads and
+/// writes once the X509-SVID the peer presented expires.
+///
+/// Servers drop connections failing with an error, so long-lived
+/// connections do not outlive the SVID they were authenticated with; mTLS
+/// only checks validity during the handshake. Shutting the connection down
+/// still works after expiry. Without enforcement, or for peers presenting no
+/// certificate, the connection is never failed.
+#[derive(Debug)]
+pub struct ExpiringStream<S> {
+    inner: S,
+    expiry: Option<Pin<Box<Sleep>>>,
+}
+
+impl<IO> ExpiringStream<TlsStream<IO>> {
+    /// Wrap the accepted `tls`, failing it once the peer's X509-SVID expires
+    /// if `enforce` is set.
+    pub(crate) fn accepted(tls: TlsStream<IO>, enforce: bool) -> Self {
+        let expiry = if enforce {
+            expiry_timer(tls.get_ref().1.peer_certificates()).map(Box::pin)
+        } else {
+            None
+        };
+        Self { inner: tls, expiry }
+    }
+}
+
+impl<S> ExpiringStream<S> {
+    /// Returns the wrapped connection.
+    #[must_use]
+    pub const fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the wrapped connection mutably.
+    #[must_use]
+    pub const fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped connection, no longer failing on expiry.
+    #[must_use]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Fails once the peer's X509-SVID has expired, registering for a wakeup
+    /// at expiry otherwise.
+    fn poll_expiry(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let Some(expiry) = &mut self.expiry else {
+            return Ok(());
+        };
+        if expiry.as_mut().poll(cx).is_pending() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "the peer X509-SVID expired",
+        ))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ExpiringStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_expiry(cx)?;
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ExpiringStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_expiry(cx)?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_expiry(cx)?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{io, time::Duration};
+
+    use rustls::pki_types::CertificateDer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::ExpiringStream;
+    use crate::{
+        SourceError,
+        svid_extractor::expiry_timer,
+        test_certs::{Ca, DAY},
+    };
+
+    #[tokio::test]
+    async fn fails_once_the_peer_svid_expires() -> Result<(), SourceError> {
+        tokio::time::pause();
+        let svid = Ca::root("root")?.svid("spiffe://example.org/client", DAY)?;
+        let leaf = CertificateDer::from(svid.leaf().content().to_vec());
+        let (mut peer, io) = tokio::io::duplex(64);
+        let mut stream = ExpiringStream {
+            inner: io,
+            expiry: expiry_timer(Some(&[leaf])).map(Box::pin),
+        };
+
+        let mut buf = [0; 1];
+        peer.write_all(b"a").await?;
+        stream.read_exact(&mut buf).await?;
+        stream.write_all(b"b").await?;
+
+        tokio::time::advance(DAY + Duration::from_secs(1)).await;
+        peer.write_all(b"c").await?;
+        let err = stream
+            .read_exact(&mut buf)
+            .await
+            .err()
+            .ok_or("read after expiry")?;
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        let err = stream
+            .write_all(b"d")
+            .await
+            .err()
+            .ok_or("wrote after expiry")?;
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
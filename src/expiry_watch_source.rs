@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use spiffe::X509Context;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::{
+    SourceError, WorkloadIdentity,
+    svid_selector::SvidSelector,
+    svid_source::{ConnectFuture, FetchFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] that ends the stream of another source when an SVID gets
+/// within a threshold of its expiry without a renewal arriving, so that the
+/// stream is rebuilt.
+///
+/// Only the SVIDs the builder's configs are built from are watched, as set
+/// on `watched` when a stream is built. Rebuilding only happens once per
+/// `notAfter`: if the rebuilt stream still carries the same expiring SVID, it
+/// is kept until the SVID is renewed.
+#[derive(Clone)]
+pub struct ExpiryWatchSource {
+    inner: Arc<dyn SvidSource>,
+    threshold: Duration,
+    watched: watch::Receiver<WatchedSvids>,
+    forced: watch::Sender<Option<SystemTime>>,
+}
+
+/// The SVIDs of an update whose expiry an [`ExpiryWatchSource`] watches.
+#[derive(Clone, Debug)]
+pub enum WatchedSvids {
+    /// Every SVID, as all of them are served.
+    All,
+    /// Only the SVID the config is built from.
+    Selected(SvidSelector),
+}
+
+impl WatchedSvids {
+    /// The earliest `notAfter` of the watched SVIDs of `x509_context`.
+    fn not_after(&self, x509_context: &X509Context) -> Option<SystemTime> {
+        let not_after = |svid| WorkloadIdentity::from_svid(svid).map(|identity| identity.not_after);
+        match self {
+            Self::All => x509_context.svids().iter().filter_map(not_after).min(),
+            Self::Selected(selector) => selector.select(x509_context).and_then(not_after),
+        }
+    }
+}
+
+impl ExpiryWatchSource {
+    pub fn new(
+        inner: Arc<dyn SvidSource>,
+        threshold: Duration,
+        watched: watch::Receiver<WatchedSvids>,
+    ) -> Self {
+        Self {
+            inner,
+            threshold,
+            watched,
+            forced: watch::Sender::new(None),
+        }
+    }
+
+    async fn run(
+        self,
+        mut stream: X509ContextStream,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        let mut not_after = None;
+        loop {
+            let deadline = not_after
+                .filter(|not_after| *self.forced.borrow() != Some(*not_after))
+                .map(|not_after: SystemTime| {
+                    not_after
+                        .checked_sub(self.threshold)
+                        .unwrap_or(SystemTime::UNIX_EPOCH)
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+                });
+            let update = tokio::select! {
+                update = stream.next() => update,
+                () = expiry(deadline) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(
+                        name: "expiry_watch_source",
+                        threshold_secs = self.threshold.as_secs(),
+                        "SVID near expiry without a renewal, rebuilding the SVID source stream"
+                    );
+
+                    self.forced.send_replace(not_after);
+                    return;
+                }
+                () = tx.closed() => return,
+            };
+            let Some(update) = update else {
+                return;
+            };
+            if let Ok(x509_context) = &update {
+                not_after = self.watched.borrow().not_after(x509_context);
+            }
+            if tx.send(update).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl SvidSource for ExpiryWatchSource {
+    fn fetch(&self) -> FetchFuture<'_> {
+        self.inner.fetch()
+    }
+
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let stream = self.inner.connect().await?;
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(self.clone().run(stream, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        self.inner.describe()
+    }
+}
+
+/// Resolves after `deadline`, or never without one.
+async fn expiry(deadline: Option<Duration>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use rustls::{ClientConfig, ServerConfig};
+use rustls_config_stream::{
+    ClientConfigStreamBuilder, ClientConfigStreamError, ServerConfigStreamBuilder,
+    ServerConfigStreamError,
+};
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+#[cfg(feature = "tracing")]
+use tracing::{info, warn};
+
+/// A config stream builder that yields configs from a primary builder's
+/// streams and fails over to a secondary builder's streams while the primary
+/// is unavailable.
+///
+/// Works with any client or server config stream builders, e.g. a
+/// [`SpiffeServerConfigStream`](crate::SpiffeServerConfigStream) builder as
+/// primary and one reading from a [`FileSvidSource`] as secondary.
+///
+/// The primary stream is abandoned when it yields an error, ends, or, with
+/// [`with_stall_timeout`](Self::with_stall_timeout), yields nothing for that
+/// long. The secondary stream is then built and its configs are yielded while
+/// the primary is rebuilt in the background with exponential backoff (10ms up
+/// to 10s); the first config from the rebuilt primary switches back and drops
+/// the secondary stream.
+///
+/// Because failover happens inside the stream, the provider only sees an
+/// error, and marks itself unhealthy, when the secondary stream fails too.
+/// Use [`fallback_updates`](Self::fallback_updates) to observe which builder
+/// is serving.
+///
+/// [`FileSvidSource`]: https://docs.rs/rustls-spiffe/latest/rustls_spiffe/struct.FileSvidSource.html
+pub struct FallbackConfigStream<P, S> {
+    primary: Arc<Mutex<P>>,
+    secondary: Arc<Mutex<S>>,
+    stall_timeout: Option<Duration>,
+    fallback: watch::Sender<bool>,
+}
+
+impl<P, S> FallbackConfigStream<P, S> {
+    /// Yield configs from `primary`, failing over to `secondary`.
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self {
+            primary: Arc::new(Mutex::new(primary)),
+            secondary: Arc::new(Mutex::new(secondary)),
+            stall_timeout: None,
+            fallback: watch::Sender::new(false),
+        }
+    }
+
+    /// Fail over when the primary stream yields nothing for `timeout`.
+    ///
+    /// SPIFFE streams only yield on rotation, so the timeout must be longer
+    /// than the SVID and bundle rotation interval.
+    #[must_use]
+    pub const fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Subscribe to whether the secondary builder is currently serving.
+    #[must_use]
+    pub fn fallback_updates(&self) -> watch::Receiver<bool> {
+        self.fallback.subscribe()
+    }
+}
+
+/// The shape shared by [`ClientConfigStreamBuilder`] and
+/// [`ServerConfigStreamBuilder`].
+trait Builder: Send + 'static {
+    type Config: Send + Sync + 'static;
+    type Error: std::fmt::Display + Send + Sync + 'static;
+    type Stream: Stream<Item = Result<Arc<Self::Config>, Self::Error>> + Send + Unpin + 'static;
+
+    fn build_stream(&mut self) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send;
+}
+
+struct Client<B>(Arc<Mutex<B>>);
+
+impl<B: ClientConfigStreamBuilder + Send + 'static> Builder for Client<B> {
+    type Config = ClientConfig;
+    type Error = ClientConfigStreamError;
+    type Stream = B::ConfigStream;
+
+    fn build_stream(&mut self) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+        let builder = self.0.clone();
+        async move { builder.lock().await.build().await }
+    }
+}
+
+struct Server<B>(Arc<Mutex<B>>);
+
+impl<B: ServerConfigStreamBuilder + Send + 'static> Builder for Server<B> {
+    type Config = ServerConfig;
+    type Error = ServerConfigStreamError;
+    type Stream = B::ConfigStream;
+
+    fn build_stream(&mut self) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send {
+        let builder = self.0.clone();
+        async move { builder.lock().await.build().await }
+    }
+}
+
+impl<P, S> ClientConfigStreamBuilder for FallbackConfigStream<P, S>
+where
+    P: ClientConfigStreamBuilder + Send + 'static,
+    S: ClientConfigStreamBuilder + Send + 'static,
+{
+    type ConfigStream = ReceiverStream<Result<Arc<ClientConfig>, ClientConfigStreamError>>;
+
+    async fn build(&mut self) -> Result<Self::ConfigStream, ClientConfigStreamError> {
+        Failover {
+            primary: Client(self.primary.clone()),
+            secondary: Client(self.secondary.clone()),
+            stall_timeout: self.stall_timeout,
+            fallback: self.fallback.clone(),
+        }
+        .start()
+        .await
+    }
+}
+
+impl<P, S> ServerConfigStreamBuilder for FallbackConfigStream<P, S>
+where
+    P: ServerConfigStreamBuilder + Send + 'static,
+    S: ServerConfigStreamBuilder + Send + 'static,
+{
+    type ConfigStream = ReceiverStream<Result<Arc<ServerConfig>, ServerConfigStreamError>>;
+
+    async fn build(&mut self) -> Result<Self::ConfigStream, ServerConfigStreamError> {
+        Failover {
+            primary: Server(self.primary.clone()),
+            secondary: Server(self.secondary.clone()),
+            stall_timeout: self.stall_timeout,
+            fallback: self.fallback.clone(),
+        }
+        .start()
+        .await
+    }
+}
+
+type Item<B> = Result<Arc<<B as Builder>::Config>, <B as Builder>::Error>;
+
+struct Failover<P, S> {
+    primary: P,
+    secondary: S,
+    stall_timeout: Option<Duration>,
+    fallback: watch::Sender<bool>,
+}
+
+impl<P, S> Failover<P, S>
+where
+    P: Builder,
+    S: Builder<Config = P::Config, Error = P::Error>,
+{
+    async fn start(mut self) -> Result<ReceiverStream<Item<P>>, P::Error> {
+        let (primary, secondary) = match self.primary.build_stream().await {
+            Ok(primary) => (Some(primary), None),
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                warn!(name: "fallback_config_stream", error = %err, "primary config stream unavailable, building secondary");
+
+                let Ok(secondary) = self.secondary.build_stream().await else {
+                    return Err(err);
+                };
+                (None, Some(secondary))
+            }
+        };
+        self.fallback.send_replace(primary.is_none());
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(self.run(primary, secondary, tx));
+        Ok(ReceiverStream::new(rx))
+    }
+
+    async fn run(
+        mut self,
+        mut primary: Option<P::Stream>,
+        mut secondary: Option<S::Stream>,
+        tx: mpsc::Sender<Item<P>>,
+    ) {
+        let initial_delay = Duration::from_millis(10);
+        let max_delay = Duration::from_secs(10);
+        let mut delay = initial_delay;
+        loop {
+            if let Some(stream) = &mut primary {
+                let update = tokio::select! {
+                    update = stream.next() => update,
+                    () = stall(self.stall_timeout) => None,
+                    () = tx.closed() => return,
+                };
+                if let Some(Ok(config)) = update {
+                    if secondary.take().is_some() {
+                        #[cfg(feature = "tracing")]
+                        info!(name: "fallback_config_stream", "primary config stream recovered, leaving secondary");
+
+                        self.fallback.send_replace(false);
+                    }
+                    delay = initial_delay;
+                    if tx.send(Ok(config)).await.is_err() {
+                        return;
+                    }
+                } else {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "fallback_config_stream", "primary config stream failed or stalled, failing over");
+
+                    primary = None;
+                }
+                continue;
+            }
+
+            if secondary.is_none() {
+                match self.secondary.build_stream().await {
+                    Ok(stream) => {
+                        self.fallback.send_replace(true);
+                        secondary = Some(stream);
+                    }
+                    Err(err) => {
+                        let _ = tx.send(Err(err)).await;
+                        return;
+                    }
+                }
+            }
+            let Some(stream) = &mut secondary else {
+                continue;
+            };
+            tokio::select! {
+                update = stream.next() => match update {
+                    Some(update) => {
+                        let failed = update.is_err();
+                        if tx.send(update).await.is_err() || failed {
+                            return;
+                        }
+                    }
+                    // An ended secondary stream is rebuilt on the next pass.
+                    None => secondary = None,
+                },
+                () = tokio::time::sleep(delay) => {
+                    match self.primary.build_stream().await {
+                        Ok(stream) => primary = Some(stream),
+                        Err(_) => delay = (delay * 2).min(max_delay),
+                    }
+                }
+                () = tx.closed() => return,
+            }
+        }
+    }
+}
+
+/// Resolves after `timeout`, or never without one.
+async fn stall(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
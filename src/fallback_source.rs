@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use spiffe::X509Context;
+use tokio::sync::mpsc;
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+#[cfg(feature = "tracing")]
+use tracing::{info, warn};
+
+use crate::{
+    SourceError,
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] that serves a static [`X509Context`] while another
+/// source is unavailable.
+///
+/// If the primary source cannot be connected, the static update is yielded
+/// first so providers can start during agent outages; the primary source is
+/// then retried in the background with exponential backoff (10ms up to 10s)
+/// and its updates take over as soon as they arrive. With
+/// [`with_stale_after`](Self::with_stale_after), the static update is served
+/// again once the primary source has been down for that long.
+///
+/// Because reconnection happens inside this source, the stream it returns
+/// does not fail when the primary source does.
+#[derive(Clone)]
+pub struct FallbackSource {
+    primary: Arc<dyn SvidSource>,
+    fallback: Arc<X509Context>,
+    stale_after: Option<Duration>,
+}
+
+impl FallbackSource {
+    /// Serve `fallback` while `primary` is unavailable.
+    pub fn new(primary: impl SvidSource, fallback: X509Context) -> Self {
+        Self::from_arc(Arc::new(primary), fallback)
+    }
+
+    pub(crate) fn from_arc(primary: Arc<dyn SvidSource>, fallback: X509Context) -> Self {
+        Self {
+            primary,
+            fallback: Arc::new(fallback),
+            stale_after: None,
+        }
+    }
+
+    /// Serve the static update again when the primary source has been down
+    /// for `stale_after`.
+    #[must_use]
+    pub const fn with_stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    async fn run(
+        self,
+        mut stream: Option<X509ContextStream>,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        let initial_delay = Duration::from_millis(10);
+        let max_delay = Duration::from_secs(10);
+        let mut delay = initial_delay;
+        let mut down_since = Instant::now();
+        let mut serving_fallback = stream.is_none();
+        loop {
+            if let Some(primary) = &mut stream {
+                let update = tokio::select! {
+                    update = primary.next() => update,
+                    () = tx.closed() => return,
+                };
+                if let Some(Ok(x509_context)) = update {
+                    if serving_fallback {
+                        #[cfg(feature = "tracing")]
+                        info!("primary SVID source recovered, leaving static fallback");
+                    }
+                    serving_fallback = false;
+                    delay = initial_delay;
+                    if tx.send(Ok(x509_context)).await.is_err() {
+                        return;
+                    }
+                } else {
+                    #[cfg(feature = "tracing")]
+                    warn!("primary SVID source stream failed, reconnecting");
+
+                    stream = None;
+                    down_since = Instant::now();
+                }
+                continue;
+            }
+
+            let stale = self
+                .stale_after
+                .is_some_and(|stale_after| down_since.elapsed() >= stale_after);
+            if stale && !serving_fallback {
+                #[cfg(feature = "tracing")]
+                warn!("primary SVID source stale, serving static fallback");
+
+                serving_fallback = true;
+                if tx.send(Ok((*self.fallback).clone())).await.is_err() {
+                    return;
+                }
+            }
+            if let Ok(primary) = self.primary.connect().await {
+                stream = Some(primary);
+            } else {
+                tokio::select! {
+                    () = tokio::time::sleep(delay) => {}
+                    () = tx.closed() => return,
+                }
+                delay = (delay * 2).min(max_delay);
+            }
+        }
+    }
+}
+
+impl SvidSource for FallbackSource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let (tx, rx) = mpsc::channel(1);
+            let primary = match self.primary.connect().await {
+                Ok(primary) => Some(primary),
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(error = %err, "primary SVID source unavailable, serving static fallback");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+
+                    tx.send(Ok((*self.fallback).clone())).await?;
+                    None
+                }
+            };
+            tokio::spawn(self.clone().run(primary, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        self.primary.describe()
+    }
+}
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{path::PathBuf, time::Duration};
+
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+use crate::{
+    SourceError,
+    private_key::pkcs8_private_key,
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] reading an X509-SVID and trust bundle from files, as
+/// written by cert-manager's csi-driver-spiffe or spiffe-helper.
+///
+/// The certificate chain, private key and bundle files may be PEM or DER, and
+/// the private key PKCS#8, SEC1 (`EC PRIVATE KEY`) or PKCS#1
+/// (`RSA PRIVATE KEY`). The files are polled and a new update is yielded whenever their contents
+/// change, so workloads that cannot reach the Workload API socket still get
+/// rotated material. A read or parse failure on connect fails the stream;
+/// later failures, e.g. while files are being replaced, are logged and the
+/// previous update stays in use until the next poll succeeds.
+#[derive(Clone, Debug)]
+pub struct FileSvidSource {
+    cert: PathBuf,
+    key: PathBuf,
+    bundle: PathBuf,
+    bundle_trust_domain: Option<TrustDomain>,
+    poll_interval: Duration,
+}
+
+/// File contents from one poll, compared to detect changes.
+type Snapshot = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+impl FileSvidSource {
+    /// Read the SVID certificate chain from `cert`, its private key from `key`
+    /// and trust bundle authorities from `bundle`.
+    pub fn new(
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+        bundle: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            bundle: bundle.into(),
+            bundle_trust_domain: None,
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Associate the bundle with `trust_domain` instead of the trust domain of
+    /// the SVID.
+    #[must_use]
+    pub fn with_bundle_trust_domain(mut self, trust_domain: TrustDomain) -> Self {
+        self.bundle_trust_domain = Some(trust_domain);
+        self
+    }
+
+    /// Check the files for changes every `interval`. Defaults to 5 seconds.
+    #[must_use]
+    pub const fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Read the files once, e.g. to build a static fallback for
+    /// `with_static_fallback` on the config stream builders.
+    ///
+    /// # Errors
+    /// Any error reading or parsing the files.
+    pub async fn load(&self) -> Result<X509Context, SourceError> {
+        self.parse(&self.read().await?)
+    }
+
+    async fn read(&self) -> Result<Snapshot, SourceError> {
+        Ok((
+            tokio::fs::read(&self.cert).await?,
+            tokio::fs::read(&self.key).await?,
+            tokio::fs::read(&self.bundle).await?,
+        ))
+    }
+
+    fn parse(&self, (cert, key, bundle): &Snapshot) -> Result<X509Context, SourceError> {
+        let svid = X509Svid::parse_from_der(&der(cert)?, &pkcs8_private_key(&der(key)?))?;
+        let trust_domain = self
+            .bundle_trust_domain
+            .clone()
+            .unwrap_or_else(|| svid.spiffe_id().trust_domain().clone());
+        let mut bundles = X509BundleSet::new();
+        bundles.add_bundle(X509Bundle::parse_from_der(trust_domain, &der(bundle)?)?);
+        Ok(X509Context::new(vec![svid], bundles))
+    }
+
+    async fn watch(self, mut last: Snapshot, tx: mpsc::Sender<Result<X509Context, SourceError>>) {
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(self.poll_interval) => {}
+                () = tx.closed() => return,
+            }
+            let update = match self.read().await {
+                Ok(snapshot) if snapshot == last => continue,
+                Ok(snapshot) => self.parse(&snapshot).map(|ctx| (snapshot, ctx)),
+                Err(err) => Err(err),
+            };
+            match update {
+                Ok((snapshot, x509_context)) => {
+                    #[cfg(feature = "tracing")]
+                    debug!(cert = %self.cert.display(), "SVID files changed");
+
+                    last = snapshot;
+                    if tx.send(Ok(x509_context)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(error = %err, "failed to read SVID files, keeping previous update");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+            }
+        }
+    }
+}
+
+impl SvidSource for FileSvidSource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let snapshot = self.read().await?;
+            let initial = self.parse(&snapshot)?;
+            let (tx, rx) = mpsc::channel(1);
+            tx.send(Ok(initial)).await?;
+            tokio::spawn(self.clone().watch(snapshot, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+}
+
+/// The concatenated DER contents of the PEM blocks in `data`, or `data` itself
+/// if it holds no PEM blocks.
+fn der(data: &[u8]) -> Result<Vec<u8>, SourceError> {
+    let pems = pem::parse_many(data)?;
+    if pems.is_empty() {
+        return Ok(data.to_vec());
+    }
+    Ok(pems.iter().flat_map(|p| p.contents().to_vec()).collect())
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{fs, io, path::Path, time::Duration};
+
+    use pem::Pem;
+    use rustls::crypto::aws_lc_rs;
+    use spiffe::{TrustDomain, X509Svid, cert::Certificate};
+    use tokio_stream::StreamExt;
+
+    use super::FileSvidSource;
+    use crate::{
+        SourceError,
+        certified_key::certified_key,
+        svid_source::{SvidSource, X509ContextStream},
+        test_certs::{Ca, DAY, sec1_private_key},
+    };
+
+    const POLL: Duration = Duration::from_millis(10);
+
+    const ID: &str = "spiffe://example.org/workload";
+
+    fn pem(tag: &str, ders: &[&[u8]]) -> String {
+        let pems: Vec<_> = ders.iter().map(|der| Pem::new(tag, der.to_vec())).collect();
+        pem::encode_many(&pems)
+    }
+
+    /// Write the chain and bundle of `svid`, issued by `ca`, as PEM files
+    /// into `dir` next to `key`, returning a source reading them.
+    fn write_files(dir: &Path, ca: &Ca, svid: &X509Svid, key: &str) -> io::Result<FileSvidSource> {
+        let chain: Vec<_> = svid.cert_chain().iter().map(Certificate::content).collect();
+        let bundle = ca.bundle("example.org", false).map_err(io::Error::other)?;
+        let authorities: Vec<_> = bundle
+            .authorities()
+            .iter()
+            .map(Certificate::content)
+            .collect();
+        fs::write(dir.join("svid.pem"), pem("CERTIFICATE", &chain))?;
+        fs::write(dir.join("svid_key.pem"), key)?;
+        fs::write(dir.join("bundle.pem"), pem("CERTIFICATE", &authorities))?;
+        Ok(FileSvidSource::new(
+            dir.join("svid.pem"),
+            dir.join("svid_key.pem"),
+            dir.join("bundle.pem"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn loads_sec1_key_files() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let ca = Ca::root("root")?;
+        let svid = ca.svid(ID, DAY)?;
+        let key = pem("EC PRIVATE KEY", &[&sec1_private_key(&svid)?]);
+        let source = write_files(dir.path(), &ca, &svid, &key)?;
+
+        let x509_context = source.load().await?;
+        let [loaded] = x509_context.svids().as_slice() else {
+            return Err("expected one SVID".into());
+        };
+        assert_eq!(loaded.cert_chain(), svid.cert_chain());
+        certified_key(loaded, None, &aws_lc_rs::default_provider())?;
+        Ok(())
+    }
+
+    fn pkcs8_key(svid: &X509Svid) -> String {
+        pem("PRIVATE KEY", &[svid.private_key().content()])
+    }
+
+    async fn next_svid(updates: &mut X509ContextStream) -> Result<X509Svid, SourceError> {
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.next())
+            .await?
+            .ok_or("stream ended")??;
+        Ok(update.default_svid().ok_or("no SVID")?.clone())
+    }
+
+    #[tokio::test]
+    async fn loads_pem_and_der_files() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let svid = ca.svid(ID, DAY)?;
+        let bundle = ca.bundle("example.org", false)?;
+        let (pem_dir, der_dir) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let pem_source = write_files(pem_dir.path(), &ca, &svid, &pkcs8_key(&svid))?;
+        let der = der_dir.path();
+        let chain: Vec<_> = svid.cert_chain().iter().map(Certificate::content).collect();
+        let authorities: Vec<_> = bundle
+            .authorities()
+            .iter()
+            .map(Certificate::content)
+            .collect();
+        fs::write(der.join("svid.der"), chain.concat())?;
+        fs::write(der.join("svid_key.der"), svid.private_key().content())?;
+        fs::write(der.join("bundle.der"), authorities.concat())?;
+        let der_source = FileSvidSource::new(
+            der.join("svid.der"),
+            der.join("svid_key.der"),
+            der.join("bundle.der"),
+        );
+
+        for source in [pem_source, der_source] {
+            let x509_context = source.load().await?;
+            assert_eq!(x509_context.svids(), &vec![svid.clone()]);
+            let trust_domain = TrustDomain::new("example.org")?;
+            assert_eq!(
+                x509_context.bundle_set().get_bundle(&trust_domain),
+                Some(&bundle)
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn picks_up_changes_on_the_next_poll() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let ca = Ca::root("root")?;
+        let (first, second) = (ca.svid(ID, DAY)?, ca.svid(ID, DAY)?);
+        let source =
+            write_files(dir.path(), &ca, &first, &pkcs8_key(&first))?.with_poll_interval(POLL);
+        let mut updates = source.connect().await?;
+        assert_eq!(next_svid(&mut updates).await?, first);
+
+        write_files(dir.path(), &ca, &second, &pkcs8_key(&second))?;
+        assert_eq!(next_svid(&mut updates).await?, second);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_the_previous_update_on_parse_failures() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let ca = Ca::root("root")?;
+        let (first, second) = (ca.svid(ID, DAY)?, ca.svid(ID, DAY)?);
+        let source =
+            write_files(dir.path(), &ca, &first, &pkcs8_key(&first))?.with_poll_interval(POLL);
+        let mut updates = source.connect().await?;
+        assert_eq!(next_svid(&mut updates).await?, first);
+
+        fs::write(dir.path().join("svid_key.pem"), "not a key")?;
+        let skipped = tokio::time::timeout(POLL * 10, updates.next()).await;
+        assert!(skipped.is_err(), "a broken update was yielded");
+
+        write_files(dir.path(), &ca, &second, &pkcs8_key(&second))?;
+        assert_eq!(next_svid(&mut updates).await?, second);
+        Ok(())
+    }
+}
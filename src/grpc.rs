@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::sync::Arc;
+
+use hyper_util::rt::TokioIo;
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+
+use crate::SourceError;
+
+/// Connect a plaintext gRPC [`Channel`] to `endpoint`, either
+/// `unix:///path/to/socket` or `tcp://IP:port`.
+///
+/// `service` names the API in the error for other schemes.
+pub async fn channel_for_endpoint(endpoint: &str, service: &str) -> Result<Channel, SourceError> {
+    if let Some(address) = endpoint.strip_prefix("tcp://") {
+        let uri = format!("http://{}", address.trim_end_matches('/'));
+        return Ok(Endpoint::from_shared(uri)?.connect().await?);
+    }
+    let Some(path) = endpoint.strip_prefix("unix://") else {
+        return Err(format!("unsupported {service} endpoint {endpoint:?}").into());
+    };
+    let path: Arc<str> = path.into();
+    Ok(Endpoint::from_static("http://[::]:50051")
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let path = path.clone();
+            async move { UnixStream::connect(&*path).await.map(TokioIo::new) }
+        }))
+        .await?)
+}
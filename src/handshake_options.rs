@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{future::Future, io, time::Duration};
+
+/// How the acceptors of this crate run handshakes and treat the connections
+/// they accept.
+///
+/// Shared by [`HealthGatedAcceptor`](crate::HealthGatedAcceptor),
+/// [`SelectingAcceptor`](crate::SelectingAcceptor) and, with the `axum` or
+/// `tonic` feature, `TlsIncoming`. By default handshakes are aborted after 10 seconds and peer expiry is not
+/// enforced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HandshakeOptions {
+    timeout: Option<Duration>,
+    enforce_peer_expiry: bool,
+}
+
+impl Default for HandshakeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HandshakeOptions {
+    /// The default options.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(10)),
+            enforce_peer_expiry: false,
+        }
+    }
+
+    /// Abort handshakes, including reading the client hello, that take
+    /// longer than `timeout`, so slow or stalled clients cannot hold
+    /// connections open.
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Let handshakes take as long as the client does.
+    #[must_use]
+    pub const fn without_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    /// Fail reads and writes on accepted connections once the X509-SVID the
+    /// client presented expires, so servers drop them; see
+    /// [`ExpiringStream`](crate::ExpiringStream).
+    #[must_use]
+    pub const fn with_peer_expiry_enforcement(mut self) -> Self {
+        self.enforce_peer_expiry = true;
+        self
+    }
+
+    pub(crate) const fn enforces_peer_expiry(self) -> bool {
+        self.enforce_peer_expiry
+    }
+
+    /// Runs `handshake`, failing with [`io::ErrorKind::TimedOut`] after the
+    /// timeout.
+    pub(crate) async fn run<T>(
+        self,
+        handshake: impl Future<Output = io::Result<T>>,
+    ) -> io::Result<T> {
+        let Some(timeout) = self.timeout else {
+            return handshake.await;
+        };
+        tokio::time::timeout(timeout, handshake)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{io, time::Duration};
+
+    use spiffe::TrustDomain;
+
+    use super::HandshakeOptions;
+    use crate::{
+        SelectingAcceptor, ServerConfigProvider, SourceError, SpiffeServerConfigStream,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    #[tokio::test]
+    async fn stalled_clients_time_out() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let update = context(
+            vec![ca.svid("spiffe://example.org/server", DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+        let (source, tx) = ChannelSource::new(update.clone());
+        tx.send(Ok(update)).await?;
+        let provider = ServerConfigProvider::start(
+            SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+                .with_svid_source(source),
+        )
+        .await?;
+        let acceptor = SelectingAcceptor::new(provider, |_, provider| provider.get_config())
+            .with_handshake_options(
+                HandshakeOptions::new().with_timeout(Duration::from_millis(50)),
+            );
+
+        // the client never sends its hello
+        let (_client, server) = tokio::io::duplex(1024);
+        let err = acceptor
+            .accept(server)
+            .await
+            .err()
+            .ok_or("handshake completed")?;
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        Ok(())
+    }
+}
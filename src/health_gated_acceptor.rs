@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{io, sync::Arc, time::Duration};
+
+use rustls::{ServerConfig, server::Acceptor};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::watch,
+};
+use tokio_rustls::{LazyConfigAcceptor, server::TlsStream};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::{
+    ExpiringStream, HandshakeOptions, ServerConfigProvider, StreamHealth, stream_health::Rejecting,
+};
+
+/// A TLS acceptor serving the config of a [`ServerConfigProvider`] that
+/// refuses new handshakes while the provider's stream has been unhealthy for
+/// too long, instead of presenting a possibly expired SVID.
+///
+/// A handshake is refused when the provider reports an unhealthy stream and
+/// the last config was built more than `max_age` ago. The client then
+/// receives a TLS alert and [`accept`](Self::accept) fails; connections
+/// already established are left alone.
+///
+/// ```rust
+/// # async fn example(listener: tokio::net::TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+///
+/// use rustls_spiffe::{HealthGatedAcceptor, ServerConfigProvider, SpiffeServerConfigStream};
+///
+/// let builder = SpiffeServerConfigStream::builder(vec!["example.org".try_into()?]);
+/// let health = builder.health_updates();
+/// let provider = ServerConfigProvider::start(builder).await?;
+/// let acceptor = HealthGatedAcceptor::new(provider, health, Duration::from_secs(600));
+/// loop {
+///     let (tcp, _) = listener.accept().await?;
+///     let Ok(tls) = acceptor.accept(tcp).await else {
+///         continue;
+///     };
+///     // serve `tls`...
+/// }
+/// # }
+/// ```
+pub struct HealthGatedAcceptor {
+    provider: Arc<ServerConfigProvider>,
+    health: watch::Receiver<StreamHealth>,
+    max_age: Duration,
+    rejecting: Arc<ServerConfig>,
+    handshake: HandshakeOptions,
+}
+
+impl HealthGatedAcceptor {
+    /// Accept with the configs of `provider`, refusing handshakes while its
+    /// stream is unhealthy and `health`, from the builder's `health_updates`,
+    /// reports no config built within `max_age`.
+    #[must_use]
+    pub fn new(
+        provider: Arc<ServerConfigProvider>,
+        health: watch::Receiver<StreamHealth>,
+        max_age: Duration,
+    ) -> Self {
+        let mut rejecting = ServerConfig::clone(&provider.get_config());
+        rejecting.cert_resolver = Arc::new(Rejecting(rejecting.crypto_provider().clone()));
+        Self {
+            provider,
+            health,
+            max_age,
+            rejecting: Arc::new(rejecting),
+            handshake: HandshakeOptions::new(),
+        }
+    }
+
+    /// Run handshakes and treat accepted connections according to `options`.
+    #[must_use]
+    pub const fn with_handshake_options(mut self, options: HandshakeOptions) -> Self {
+        self.handshake = options;
+        self
+    }
+
+    /// Returns whether new handshakes are currently refused.
+    #[must_use]
+    pub fn is_refusing(&self) -> bool {
+        !self.provider.stream_healthy()
+            && self
+                .health
+                .borrow()
+                .staleness()
+                .is_none_or(|age| age > self.max_age)
+    }
+
+    /// Read the client hello from `io` and complete the handshake, or refuse
+    /// it with a TLS alert.
+    ///
+    /// # Errors
+    /// The handshake fails, times out or is refused.
+    pub async fn accept<IO>(&self, io: IO) -> io::Result<ExpiringStream<TlsStream<IO>>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let tls = self.handshake.run(self.handshake(io)).await?;
+        Ok(ExpiringStream::accepted(
+            tls,
+            self.handshake.enforces_peer_expiry(),
+        ))
+    }
+
+    async fn handshake<IO>(&self, io: IO) -> io::Result<TlsStream<IO>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let start = LazyConfigAcceptor::new(Acceptor::default(), io).await?;
+        if !self.is_refusing() {
+            return start.into_stream(self.provider.get_config()).await;
+        }
+
+        #[cfg(feature = "tracing")]
+        warn!(
+            name: "health_gated_acceptor",
+            staleness = ?self.health.borrow().staleness(),
+            "refusing handshake while the config stream is unhealthy"
+        );
+
+        // The rejecting config fails the handshake with an alert.
+        start.into_stream(self.rejecting.clone()).await?;
+        Err(io::Error::other(
+            "handshake refused while the config stream is unhealthy",
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rustls::{ClientConfig, pki_types::ServerName};
+    use spiffe::{TrustDomain, X509Context};
+    use tokio_rustls::TlsConnector;
+
+    use super::HealthGatedAcceptor;
+    use crate::{
+        ServerConfigProvider, SourceError, SpiffeClientConfigStream, SpiffeServerConfigStream,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    fn update(ca: &Ca, id: &str) -> Result<X509Context, SourceError> {
+        Ok(context(
+            vec![ca.svid_with_dns_names(id, &["server.example.org"], DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        ))
+    }
+
+    /// Whether a client completes a handshake with `acceptor`.
+    async fn is_served(
+        acceptor: &HealthGatedAcceptor,
+        client: Arc<ClientConfig>,
+    ) -> Result<bool, SourceError> {
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let name = ServerName::try_from("server.example.org")?;
+        let (accepted, connected) = tokio::join!(
+            acceptor.accept(server_io),
+            TlsConnector::from(client).connect(name, client_io)
+        );
+        assert_eq!(accepted.is_ok(), connected.is_ok());
+        Ok(connected.is_ok())
+    }
+
+    #[tokio::test]
+    async fn refuses_while_unhealthy_past_max_age() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let (source, tx) = ChannelSource::new(update(&ca, "spiffe://example.org/server")?);
+        tx.send(Ok(update(&ca, "spiffe://example.org/server")?))
+            .await?;
+        let builder = SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source);
+        let health = builder.health_updates();
+        let provider = ServerConfigProvider::start(builder).await?;
+        let client = SpiffeClientConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_static_fallback(update(&ca, "spiffe://example.org/client")?, None)
+            .fetch_client_config()
+            .await?;
+        let strict = HealthGatedAcceptor::new(provider.clone(), health.clone(), Duration::ZERO);
+        let lenient = HealthGatedAcceptor::new(provider.clone(), health, Duration::from_hours(1));
+
+        assert!(!strict.is_refusing());
+        assert!(is_served(&strict, client.clone()).await?);
+
+        // the stream ends and cannot be reconnected
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while provider.stream_healthy() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(strict.is_refusing());
+        assert!(!is_served(&strict, client.clone()).await?);
+        assert!(!lenient.is_refusing());
+        assert!(is_served(&lenient, client).await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use spiffe::{X509Context, X509Svid};
+use tokio::time::{Instant, Sleep};
+
+use crate::{WorkloadIdentity, svid_selector::SvidSelector};
+
+/// Holds updates back before they are built into configs.
+///
+/// With a debounce window, the first update of a burst opens the window and
+/// only the latest update received within it is released when it closes.
+/// With an adoption delay, an update rotating the selected SVID is held for
+/// the delay, but released once the current SVID is within its minimum
+/// remaining lifetime of expiry.
+#[derive(Debug)]
+pub struct Holdback {
+    window: Duration,
+    adoption: Option<(Duration, Duration)>,
+    svid: SvidSelector,
+    deadline: Pin<Box<Sleep>>,
+    pending: Option<X509Context>,
+}
+
+impl Holdback {
+    /// Hold updates for the debounce `window` and the `adoption` delay and
+    /// minimum remaining lifetime of the SVID chosen by `svid`, or `None` if
+    /// neither is set.
+    pub fn new(
+        window: Option<Duration>,
+        adoption: Option<(Duration, Duration)>,
+        svid: SvidSelector,
+    ) -> Option<Self> {
+        if window.is_none() && adoption.is_none() {
+            return None;
+        }
+        Some(Self {
+            window: window.unwrap_or_default(),
+            adoption,
+            svid,
+            deadline: Box::pin(tokio::time::sleep(Duration::ZERO)),
+            pending: None,
+        })
+    }
+
+    /// Hold `x509_context`, replacing any update held so far, given the
+    /// `current` update configs are built from.
+    pub fn push(&mut self, x509_context: X509Context, current: Option<&X509Context>) {
+        let now = Instant::now();
+        let mut until = if self.pending.is_some() {
+            self.deadline.deadline()
+        } else {
+            now + self.window
+        };
+        if let Some((delay, min_remaining)) = self.adoption
+            && let Some(current) = current.and_then(|current| self.svid.select(current))
+            && self.rotates(&x509_context, current)
+            && !self
+                .pending
+                .as_ref()
+                .is_some_and(|pending| self.rotates(pending, current))
+        {
+            let remaining =
+                WorkloadIdentity::from_svid(current).map_or(Duration::ZERO, |identity| {
+                    identity
+                        .not_after
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+                        .saturating_sub(min_remaining)
+                });
+            until = until.max(now + delay.min(remaining));
+        }
+        self.deadline.as_mut().reset(until);
+        self.pending = Some(x509_context);
+    }
+
+    /// Polls for the held update to be released.
+    pub fn poll_release(&mut self, cx: &mut Context<'_>) -> Poll<X509Context> {
+        if self.pending.is_none() || self.deadline.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.pending.take().map_or(Poll::Pending, Poll::Ready)
+    }
+
+    /// Whether the selected SVID of `update` differs from `current`.
+    fn rotates(&self, update: &X509Context, current: &X509Svid) -> bool {
+        self.svid
+            .select(update)
+            .is_some_and(|svid| svid.leaf() != current.leaf())
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{
+        future::poll_fn,
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use rustls_config_stream::ServerConfigStreamBuilder;
+    use spiffe::{SpiffeId, TrustDomain, X509Context};
+    use tokio::time::Instant;
+    use tokio_stream::StreamExt;
+
+    use super::Holdback;
+    use crate::{
+        SourceError, SpiffeServerConfigStream,
+        svid_selector::SvidSelector,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    const WINDOW: Duration = Duration::from_secs(5);
+    const ID: &str = "spiffe://example.org/workload";
+
+    fn poll(holdback: &mut Holdback) -> Poll<X509Context> {
+        holdback.poll_release(&mut Context::from_waker(Waker::noop()))
+    }
+
+    async fn release(holdback: &mut Holdback) -> X509Context {
+        poll_fn(|cx| holdback.poll_release(cx)).await
+    }
+
+    /// An update with a new SVID, expiring after `lifetime`, issued by `ca`.
+    fn update(ca: &Ca, lifetime: Duration) -> Result<X509Context, SourceError> {
+        Ok(context(
+            vec![ca.svid(ID, lifetime)?],
+            vec![ca.bundle("example.org", false)?],
+        ))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesces_updates_within_the_window() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let (first, second) = (update(&ca, DAY)?, update(&ca, DAY)?);
+        let mut holdback =
+            Holdback::new(Some(WINDOW), None, SvidSelector::Default).ok_or("no holdback")?;
+        let start = Instant::now();
+
+        holdback.push(first, None);
+        tokio::time::advance(WINDOW / 2).await;
+        holdback.push(second.clone(), None);
+        assert!(poll(&mut holdback).is_pending());
+
+        assert_eq!(release(&mut holdback).await, second);
+        assert_eq!(start.elapsed(), WINDOW);
+        assert!(poll(&mut holdback).is_pending());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rotations_wait_for_the_adoption_delay() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let current = update(&ca, DAY)?;
+        let mut holdback = Holdback::new(
+            None,
+            Some((WINDOW, Duration::from_mins(1))),
+            SvidSelector::Default,
+        )
+        .ok_or("no holdback")?;
+        let start = Instant::now();
+
+        holdback.push(update(&ca, DAY)?, Some(&current));
+        release(&mut holdback).await;
+        assert_eq!(start.elapsed(), WINDOW);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rotations_near_expiry_are_adopted_at_once() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        // the current SVID is already within its minimum remaining lifetime
+        let current = update(&ca, Duration::from_secs(30))?;
+        let mut holdback = Holdback::new(
+            None,
+            Some((WINDOW, Duration::from_mins(1))),
+            SvidSelector::Default,
+        )
+        .ok_or("no holdback")?;
+
+        holdback.push(update(&ca, DAY)?, Some(&current));
+        assert!(poll(&mut holdback).is_ready());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_rotations_of_the_selected_svid_are_delayed() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let selected = "spiffe://example.org/selected";
+        let bundle = ca.bundle("example.org", false)?;
+        let with_svids = |default, selected| context(vec![default, selected], vec![bundle.clone()]);
+        let current = with_svids(ca.svid(ID, DAY)?, ca.svid(selected, DAY)?);
+        let mut holdback = Holdback::new(
+            None,
+            Some((WINDOW, Duration::from_mins(1))),
+            SvidSelector::SpiffeId(SpiffeId::new(selected)?),
+        )
+        .ok_or("no holdback")?;
+
+        let default_rotated = with_svids(ca.svid(ID, DAY)?, current.svids()[1].clone());
+        holdback.push(default_rotated, Some(&current));
+        assert!(poll(&mut holdback).is_ready());
+
+        let start = Instant::now();
+        let selected_rotated = with_svids(current.svids()[0].clone(), ca.svid(selected, DAY)?);
+        holdback.push(selected_rotated, Some(&current));
+        release(&mut holdback).await;
+        assert_eq!(start.elapsed(), WINDOW);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bundle_only_updates_are_not_delayed() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let current = update(&ca, DAY)?;
+        let bundle_change = context(current.svids().clone(), Vec::new());
+        let mut holdback = Holdback::new(
+            None,
+            Some((WINDOW, Duration::from_mins(1))),
+            SvidSelector::Default,
+        )
+        .ok_or("no holdback")?;
+
+        holdback.push(bundle_change, Some(&current));
+        assert!(poll(&mut holdback).is_ready());
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_and_trust_domain_changes_skip_the_window() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let (source, tx) = ChannelSource::new(update(&ca, DAY)?);
+        tx.send(Ok(update(&ca, DAY)?)).await?;
+        let mut builder = SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source)
+            .with_debounce(WINDOW);
+        let refresh = builder.refresh_handle();
+        let trust_domains = builder.trust_domain_handle();
+        let mut stream = builder.build().await?;
+        let start = Instant::now();
+
+        stream.next().await.ok_or("stream ended")??;
+        assert_eq!(start.elapsed(), WINDOW);
+
+        trust_domains.add(TrustDomain::new("other.org")?);
+        stream.next().await.ok_or("stream ended")??;
+        refresh.refresh_now();
+        stream.next().await.ok_or("stream ended")??;
+        assert_eq!(start.elapsed(), WINDOW);
+        drop(tx);
+        Ok(())
+    }
+}
@@ -5,6 +5,21 @@
 //!
 //! Provides [`SpiffeClientConfigStream`] and [`SpiffeServerConfigStream`] for
 //! use with [`ClientConfigProvider`] and [`ServerConfigProvider`]
+//!
+//! ## Public root programs
+//!
+//! There is no `webpki-roots` feature. To trust the Mozilla root program next
+//! to the SPIFFE trust bundles, add `webpki-roots` to your own dependencies
+//! and pass `webpki_roots::TLS_SERVER_ROOTS.to_vec()` to
+//! `SpiffeClientConfigStreamBuilder::with_extra_trust_anchors`.
+//!
+//! ## OS trust store
+//!
+//! There is no `native-roots` feature either, as `rustls-native-certs` is not
+//! a dependency. Load the OS trust store once at startup and pass it to
+//! `with_extra_roots` of either builder, e.g.
+//! `rustls_native_certs::load_native_certs().certs`, to merge it into every
+//! rotated root store.
 
 #![forbid(rust_2018_idioms)]
 #![forbid(missing_docs, unsafe_code)]
@@ -18,23 +33,261 @@
     clippy::todo
 )]
 
+#[cfg(all(
+    feature = "spire-server",
+    not(any(feature = "aws-lc-rs", feature = "ring"))
+))]
+compile_error!("the spire-server feature requires the aws-lc-rs or ring feature");
+
+#[cfg(feature = "tower")]
+mod authorization_layer;
+#[cfg(feature = "axum")]
+mod axum_peer;
+#[cfg(feature = "config-stream")]
+mod certified_key;
+#[cfg(feature = "config-stream")]
+mod certified_key_stream;
 #[cfg(feature = "config-stream")]
 mod client_stream;
 #[cfg(feature = "config-stream")]
+mod connection_registry;
+#[cfg(any(feature = "hyper", feature = "tonic"))]
+mod connector;
+#[cfg(feature = "delegated-identity")]
+mod delegated_identity_proto;
+#[cfg(feature = "delegated-identity")]
+mod delegated_identity_source;
+#[cfg(any(
+    feature = "disk-cache",
+    feature = "file-source",
+    feature = "sds",
+    feature = "spire-server",
+    all(test, feature = "aws-lc-rs")
+))]
+mod der;
+#[cfg(feature = "config-stream")]
+mod destination_verifier;
+#[cfg(feature = "disk-cache")]
+mod disk_cache;
+#[cfg(feature = "acceptor")]
+mod expiring_stream;
+#[cfg(feature = "config-stream")]
+mod expiry_watch_source;
+#[cfg(feature = "config-stream")]
+mod fallback_config_stream;
+#[cfg(feature = "config-stream")]
+mod fallback_source;
+#[cfg(feature = "file-source")]
+mod file_source;
+#[cfg(any(
+    feature = "delegated-identity",
+    feature = "sds",
+    feature = "spire-server"
+))]
+mod grpc;
+#[cfg(feature = "acceptor")]
+mod handshake_options;
+#[cfg(feature = "acceptor")]
+mod health_gated_acceptor;
+#[cfg(feature = "config-stream")]
+mod holdback;
+#[cfg(feature = "config-stream")]
+mod multi_tenant;
+#[cfg(feature = "tower")]
+mod peer_identity_layer;
+#[cfg(feature = "pem-export")]
+mod pem_export;
+#[cfg(any(feature = "disk-cache", feature = "file-source", feature = "sds"))]
+mod private_key;
+#[cfg(feature = "config-stream")]
+mod reconnect_source;
+#[cfg(feature = "config-stream")]
+mod refresh_handle;
+#[cfg(feature = "config-stream")]
+mod retry;
+#[cfg(feature = "config-stream")]
+mod root_store_stream;
+#[cfg(feature = "config-stream")]
+mod rotating;
+#[cfg(feature = "config-stream")]
+mod rotating_client;
+#[cfg(feature = "config-stream")]
+mod rotating_server;
+#[cfg(feature = "config-stream")]
+mod rotation_event;
+#[cfg(feature = "sds")]
+mod sds_proto;
+#[cfg(feature = "sds-server")]
+mod sds_server;
+#[cfg(feature = "sds")]
+mod sds_source;
+#[cfg(feature = "acceptor")]
+mod selecting_acceptor;
+#[cfg(feature = "config-stream")]
+mod self_test;
+#[cfg(feature = "config-stream")]
 mod server_stream;
+#[cfg(all(
+    feature = "config-stream",
+    any(feature = "aws-lc-rs", feature = "ring")
+))]
+mod session_tickets;
+#[cfg(feature = "config-stream")]
+mod shutdown_handle;
+#[cfg(feature = "config-stream")]
+mod sni_resolver;
+#[cfg(feature = "spire-server")]
+mod spire_proto;
+#[cfg(feature = "spire-server")]
+mod spire_server_source;
 #[cfg(feature = "svid-extractor")]
 mod svid_extractor;
+#[cfg(feature = "config-stream")]
+mod svid_selector;
+#[cfg(feature = "config-stream")]
+mod svid_source;
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod test_certs;
+#[cfg(any(feature = "axum", feature = "tonic"))]
+mod tls_incoming;
+#[cfg(feature = "tonic")]
+mod tonic_server;
 
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use authorization_layer::{SpiffeAuthorization, SpiffeAuthorizationLayer};
+#[cfg(feature = "axum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+pub use axum_peer::SpiffePeer;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use certified_key_stream::SpiffeCertifiedKeyStream;
 #[cfg(feature = "config-stream")]
 #[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
 pub use client_stream::{ClientConfigProvider, SpiffeClientConfigStream};
 #[cfg(feature = "config-stream")]
 #[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use connection_registry::{ConnectionGuard, ConnectionRegistry};
+#[cfg(any(feature = "hyper", feature = "tonic"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "hyper", feature = "tonic"))))]
+pub use connector::{SpiffeConnection, SpiffeConnector};
+#[cfg(feature = "acceptor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "acceptor")))]
+pub use handshake_options::HandshakeOptions;
+#[cfg(feature = "acceptor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "acceptor")))]
+pub use health_gated_acceptor::HealthGatedAcceptor;
+
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use multi_tenant::MultiTenantServerConfigProvider;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use refresh_handle::RefreshHandle;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use retry::RetryPolicy;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use root_store_stream::SpiffeRootStoreStream;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use rotating_client::RotatingClientConfig;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use rotating_server::RotatingServerConfig;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use rotation_event::{BundleChange, RotationEvent};
+#[cfg(feature = "acceptor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "acceptor")))]
+pub use selecting_acceptor::SelectingAcceptor;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
 pub use server_stream::{ServerConfigProvider, SpiffeServerConfigStream};
 
+mod error;
+pub use error::{SourceError, SpiffeConfigError};
+
+#[cfg(feature = "config-stream")]
+mod stream_health;
+#[cfg(feature = "config-stream")]
 mod trust_domain_store;
+#[cfg(feature = "config-stream")]
+mod workload_identity;
+#[cfg(feature = "config-stream")]
+mod x509_source;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use shutdown_handle::ShutdownHandle;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use stream_health::{ErrorRecord, HealthState, StalePolicy, StreamHealth};
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use svid_source::{
+    ConnectFuture, FetchFuture, SvidSource, WELL_KNOWN_SOCKET_PATHS, WorkloadApiSource,
+    X509ContextStream, detect_socket_path,
+};
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use trust_domain_store::TrustDomainHandle;
+#[cfg(feature = "config-stream")]
 pub(crate) use trust_domain_store::TrustDomainStore;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use workload_identity::{RotationInfo, WorkloadIdentity};
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use x509_source::SharedX509Source;
+
+#[cfg(feature = "delegated-identity")]
+#[cfg_attr(docsrs, doc(cfg(feature = "delegated-identity")))]
+pub use delegated_identity_source::DelegatedIdentitySource;
+#[cfg(feature = "disk-cache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "disk-cache")))]
+pub use disk_cache::DiskCacheSource;
+#[cfg(feature = "acceptor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "acceptor")))]
+pub use expiring_stream::ExpiringStream;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use fallback_config_stream::FallbackConfigStream;
+#[cfg(feature = "config-stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config-stream")))]
+pub use fallback_source::FallbackSource;
+#[cfg(feature = "file-source")]
+#[cfg_attr(docsrs, doc(cfg(feature = "file-source")))]
+pub use file_source::FileSvidSource;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use peer_identity_layer::{PeerIdentityLayer, PeerIdentityService};
+#[cfg(feature = "pem-export")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pem-export")))]
+pub use pem_export::{
+    PemFiles, SvidFileWriter, bundle_pem, cert_chain_pem, private_key_pem, write_atomic,
+};
+
+#[cfg(feature = "sds-server")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sds-server")))]
+pub use sds_server::SdsServer;
+#[cfg(feature = "sds")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sds")))]
+pub use sds_source::SdsSource;
+#[cfg(feature = "spire-server")]
+#[cfg_attr(docsrs, doc(cfg(feature = "spire-server")))]
+pub use spire_server_source::SpireServerSource;
 
 #[cfg(feature = "svid-extractor")]
 #[cfg_attr(docsrs, doc(cfg(feature = "svid-extractor")))]
-pub use svid_extractor::{extract_leaf_cert, extract_spiffe_id};
+pub use svid_extractor::{
+    PeerIdentity, extract_leaf_cert, extract_not_after, extract_spiffe_id, peer_identity,
+    peer_svid_expiry_timer,
+};
+
+#[cfg(any(feature = "axum", feature = "tonic"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "axum", feature = "tonic"))))]
+pub use tls_incoming::{PeerInfo, TlsConnection, TlsIncoming};
+#[cfg(feature = "tonic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tonic")))]
+pub use tonic_server::{peer_spiffe_id, peer_spiffe_id_interceptor};
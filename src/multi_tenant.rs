@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{collections::HashMap, sync::Arc};
+
+use rustls::{ServerConfig, server::ClientHello};
+use rustls_config_stream::{ServerConfigProvider, ServerConfigStreamError};
+
+use crate::server_stream::SpiffeServerConfigStreamBuilder;
+
+/// Builder for a [`MultiTenantServerConfigProvider`].
+#[derive(Default)]
+pub struct MultiTenantServerConfigProviderBuilder {
+    default: Option<SpiffeServerConfigStreamBuilder>,
+    tenants: Vec<(String, SpiffeServerConfigStreamBuilder)>,
+}
+
+impl MultiTenantServerConfigProviderBuilder {
+    /// Serve handshakes for `server_name` with configs from `builder`.
+    ///
+    /// Server names are matched against the `ClientHello` SNI
+    /// case-insensitively. Adding the same server name twice keeps the last
+    /// builder.
+    #[must_use]
+    pub fn with_tenant(
+        mut self,
+        server_name: impl Into<String>,
+        builder: SpiffeServerConfigStreamBuilder,
+    ) -> Self {
+        self.tenants
+            .push((server_name.into().to_ascii_lowercase(), builder));
+        self
+    }
+
+    /// Serve handshakes without SNI, or with an unknown server name, with
+    /// configs from `builder`.
+    ///
+    /// Without a default tenant those handshakes get no config.
+    #[must_use]
+    pub fn with_default_tenant(mut self, builder: SpiffeServerConfigStreamBuilder) -> Self {
+        self.default = Some(builder);
+        self
+    }
+
+    /// Start a [`ServerConfigProvider`] for every tenant.
+    ///
+    /// # Errors
+    /// The first error returned by [`ServerConfigProvider::start`].
+    pub async fn start(
+        self,
+    ) -> Result<Arc<MultiTenantServerConfigProvider>, ServerConfigStreamError> {
+        let default = match self.default {
+            Some(builder) => Some(ServerConfigProvider::start(builder).await?),
+            None => None,
+        };
+        let mut tenants = HashMap::with_capacity(self.tenants.len());
+        for (server_name, builder) in self.tenants {
+            tenants.insert(server_name, ServerConfigProvider::start(builder).await?);
+        }
+        Ok(Arc::new(MultiTenantServerConfigProvider {
+            default,
+            tenants,
+        }))
+    }
+}
+
+/// Several [`ServerConfigProvider`]s, each with its own SVID and trust
+/// domains, selected per handshake by the `ClientHello` SNI.
+///
+/// For shared ingress processes serving several logical services from one
+/// listener: accept with [`tokio_rustls::LazyConfigAcceptor`] and pass the
+/// `ClientHello` to [`get_config`](Self::get_config).
+///
+/// [`tokio_rustls::LazyConfigAcceptor`]: https://docs.rs/tokio-rustls/latest/tokio_rustls/struct.LazyConfigAcceptor.html
+pub struct MultiTenantServerConfigProvider {
+    default: Option<Arc<ServerConfigProvider>>,
+    tenants: HashMap<String, Arc<ServerConfigProvider>>,
+}
+
+impl MultiTenantServerConfigProvider {
+    /// Create a builder without any tenants.
+    #[must_use]
+    pub fn builder() -> MultiTenantServerConfigProviderBuilder {
+        MultiTenantServerConfigProviderBuilder::default()
+    }
+
+    /// Returns the current [`ServerConfig`] for the tenant addressed by
+    /// `client_hello`, or `None` if no tenant matches and there is no default.
+    #[must_use]
+    pub fn get_config(&self, client_hello: &ClientHello<'_>) -> Option<Arc<ServerConfig>> {
+        self.provider(client_hello.server_name())
+            .map(|provider| provider.get_config())
+    }
+
+    /// Returns the provider serving `server_name`, falling back to the default
+    /// tenant.
+    #[must_use]
+    pub fn provider(&self, server_name: Option<&str>) -> Option<&Arc<ServerConfigProvider>> {
+        server_name
+            .and_then(|name| self.tenants.get(&name.to_ascii_lowercase()))
+            .or(self.default.as_ref())
+    }
+
+    /// Returns whether the streams of all tenants are currently healthy.
+    #[must_use]
+    pub fn stream_healthy(&self) -> bool {
+        self.tenants
+            .values()
+            .chain(&self.default)
+            .all(|provider| provider.stream_healthy())
+    }
+}
@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::task::{Context, Poll};
+
+use tower::{Layer, Service};
+
+use crate::PeerIdentity;
+
+/// A [`Layer`] inserting the [`PeerIdentity`] of a connection into the
+/// extensions of every [`http::Request`] received over it.
+///
+/// Wrap the service of each connection after its handshake, so handlers of
+/// any tower-based framework read the identity the same way, with
+/// `request.extensions().get::<PeerIdentity>()`:
+///
+/// ```rust
+/// # fn example<S>(tls: &tokio_rustls::server::TlsStream<tokio::net::TcpStream>, service: S) {
+/// use rustls_spiffe::{PeerIdentityLayer, peer_identity};
+/// use tower::Layer;
+///
+/// let service = PeerIdentityLayer::new(peer_identity(tls)).layer(service);
+/// // serve the connection with `service`...
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PeerIdentityLayer {
+    identity: PeerIdentity,
+}
+
+impl PeerIdentityLayer {
+    /// Insert `identity` into every request.
+    #[must_use]
+    pub const fn new(identity: PeerIdentity) -> Self {
+        Self { identity }
+    }
+}
+
+impl<S> Layer<S> for PeerIdentityLayer {
+    type Service = PeerIdentityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PeerIdentityService {
+            inner,
+            identity: self.identity.clone(),
+        }
+    }
+}
+
+/// The service of a [`PeerIdentityLayer`].
+#[derive(Clone, Debug)]
+pub struct PeerIdentityService<S> {
+    inner: S,
+    identity: PeerIdentity,
+}
+
+impl<S, B> Service<http::Request<B>> for PeerIdentityService<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        request.extensions_mut().insert(self.identity.clone());
+        self.inner.call(request)
+    }
+}
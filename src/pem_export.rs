@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use pem::{EncodeConfig, LineEnding, Pem};
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio::{process::Command, sync::watch, task::JoinHandle};
+#[cfg(feature = "tracing")]
+use tracing::{error, info, warn};
+
+use crate::SpiffeConfigError;
+
+/// PEM encoding of the certificate chain of `svid`, leaf first.
+#[must_use]
+pub fn cert_chain_pem(svid: &X509Svid) -> String {
+    encode(
+        svid.cert_chain()
+            .iter()
+            .map(|cert| Pem::new("CERTIFICATE", cert.content())),
+    )
+}
+
+/// PEM encoding of the PKCS#8 private key of `svid`.
+#[must_use]
+pub fn private_key_pem(svid: &X509Svid) -> String {
+    encode([Pem::new("PRIVATE KEY", svid.private_key().content())])
+}
+
+/// PEM encoding of the authorities of `bundle`.
+#[must_use]
+pub fn bundle_pem(bundle: &X509Bundle) -> String {
+    encode(
+        bundle
+            .authorities()
+            .iter()
+            .map(|cert| Pem::new("CERTIFICATE", cert.content())),
+    )
+}
+
+fn encode(pems: impl IntoIterator<Item = Pem>) -> String {
+    let config = EncodeConfig::new().set_line_ending(LineEnding::LF);
+    pems.into_iter()
+        .map(|pem| pem::encode_config(&pem, config))
+        .collect()
+}
+
+/// Replace the file at `path` with `contents` atomically.
+///
+/// The contents are written and synced to a new, uniquely named temporary
+/// file in the same directory, which is then renamed over `path`, so readers
+/// never see a partially written file; the directory is synced after the
+/// rename so the replacement survives a crash. On Unix the file gets mode
+/// `0o600` with `private` set and `0o644` otherwise, regardless of the umask.
+///
+/// # Errors
+/// Any I/O error from writing, syncing or renaming the file; the temporary
+/// file is removed again.
+pub fn write_atomic(path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let (tmp, mut file) = create_temp(path, file_name, private)?;
+    let replaced = file
+        .write_all(contents)
+        .and_then(|()| file.sync_all())
+        .and_then(|()| {
+            drop(file);
+            fs::rename(&tmp, path)
+        });
+    if let Err(err) = replaced {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+    sync_parent(path)
+}
+
+/// Creates a temporary file next to `path` that did not exist before, so
+/// concurrent writers never share one.
+fn create_temp(path: &Path, file_name: &OsStr, private: bool) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    let mode = if private { 0o600 } else { 0o644 };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    loop {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = path.with_file_name(tmp_name);
+        match options.open(&tmp) {
+            Ok(file) => {
+                // the mode passed on creation is narrowed by the umask
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    if let Err(err) = file.set_permissions(fs::Permissions::from_mode(mode)) {
+                        let _ = fs::remove_file(&tmp);
+                        return Err(err);
+                    }
+                }
+                return Ok((tmp, file));
+            }
+            // left behind by a writer that crashed, or taken by another one
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Syncs the directory holding `path`, persisting a rename into it.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing on this platform.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+const fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// File paths for writing an X509-SVID and its trust bundle as PEM.
+///
+/// Meant for sidecars and legacy applications that only read file-based TLS
+/// material. Each [`write`](Self::write) replaces the files atomically; the
+/// private key file is readable by its owner only.
+///
+/// Only PEM is written: there is no PKCS#12 export, as no PKCS#12 encoder is
+/// available to the crate. Applications that need a keystore can convert the
+/// files after each write, e.g. with `openssl pkcs12 -export` as the reload
+/// command of a [`SvidFileWriter`].
+#[derive(Clone, Debug)]
+pub struct PemFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    bundle: Option<PathBuf>,
+    bundle_trust_domains: Vec<TrustDomain>,
+}
+
+impl PemFiles {
+    /// Write the certificate chain to `cert` and the private key to `key`.
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            bundle: None,
+            bundle_trust_domains: Vec::new(),
+        }
+    }
+
+    /// Also write trust bundle authorities to `bundle`.
+    ///
+    /// By default the bundle of the SVID's own trust domain is written; use
+    /// [`with_bundle_trust_domains`](Self::with_bundle_trust_domains) to choose
+    /// the trust domains instead.
+    #[must_use]
+    pub fn with_bundle(mut self, bundle: impl Into<PathBuf>) -> Self {
+        self.bundle = Some(bundle.into());
+        self
+    }
+
+    /// Write the bundles of these trust domains to the bundle file, in order,
+    /// instead of the bundle of the SVID's trust domain.
+    #[must_use]
+    pub fn with_bundle_trust_domains(mut self, trust_domains: Vec<TrustDomain>) -> Self {
+        self.bundle_trust_domains = trust_domains;
+        self
+    }
+
+    /// Write the default SVID and bundles of `x509_context`.
+    ///
+    /// # Errors
+    /// - [`SpiffeConfigError::MissingSvid`] if the update has no default SVID.
+    /// - [`SpiffeConfigError::MissingBundle`] if a bundle to write is missing.
+    /// - [`SpiffeConfigError::Io`] if a file cannot be written.
+    pub fn write(&self, x509_context: &X509Context) -> Result<(), SpiffeConfigError> {
+        let svid = x509_context
+            .default_svid()
+            .ok_or(SpiffeConfigError::MissingSvid)?;
+        self.write_svid(svid, x509_context.bundle_set())
+    }
+
+    /// Write `svid` and the bundles to write from `bundles`.
+    ///
+    /// The bundle file is written first and the key last, so a reader that
+    /// reloads on key changes sees matching material.
+    ///
+    /// # Errors
+    /// - [`SpiffeConfigError::MissingBundle`] if a bundle to write is missing.
+    /// - [`SpiffeConfigError::Io`] if a file cannot be written.
+    pub fn write_svid(
+        &self,
+        svid: &X509Svid,
+        bundles: &X509BundleSet,
+    ) -> Result<(), SpiffeConfigError> {
+        if let Some(path) = &self.bundle {
+            let local = [svid.spiffe_id().trust_domain().clone()];
+            let trust_domains = if self.bundle_trust_domains.is_empty() {
+                &local[..]
+            } else {
+                &self.bundle_trust_domains[..]
+            };
+            let mut contents = String::new();
+            for trust_domain in trust_domains {
+                let bundle = bundles
+                    .get_bundle(trust_domain)
+                    .ok_or_else(|| SpiffeConfigError::MissingBundle(trust_domain.clone()))?;
+                contents.push_str(&bundle_pem(bundle));
+            }
+            write_atomic(path, contents.as_bytes(), false)?;
+        }
+        write_atomic(&self.cert, cert_chain_pem(svid).as_bytes(), false)?;
+        write_atomic(&self.key, private_key_pem(svid).as_bytes(), true)?;
+        Ok(())
+    }
+}
+
+/// A task that writes the SVID and bundles of every Workload API update to
+/// [`PemFiles`], in the manner of spiffe-helper.
+///
+/// Updates are taken from a config stream builder's `context_updates`, so the
+/// files follow the same Workload API stream as the rustls configs. After each
+/// successful write an optional reload command is run and an optional process
+/// is sent `SIGHUP`. Write and reload failures are logged and retried on the
+/// next update.
+pub struct SvidFileWriter {
+    files: PemFiles,
+    contexts: watch::Receiver<Option<Arc<X509Context>>>,
+    reload_command: Option<(String, Vec<String>)>,
+    signal_pid: Option<u32>,
+}
+
+impl SvidFileWriter {
+    /// Write every update received on `contexts` to `files`.
+    #[must_use]
+    pub const fn new(files: PemFiles, contexts: watch::Receiver<Option<Arc<X509Context>>>) -> Self {
+        Self {
+            files,
+            contexts,
+            reload_command: None,
+            signal_pid: None,
+        }
+    }
+
+    /// Run `program` with `args` after the files have been written.
+    #[must_use]
+    pub fn with_reload_command(
+        mut self,
+        program: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.reload_command = Some((program.into(), args.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Send `SIGHUP` to `pid` after the files have been written.
+    ///
+    /// The signal is sent with `kill(1)`, which must be on the `PATH`.
+    #[must_use]
+    pub const fn with_sighup(mut self, pid: u32) -> Self {
+        self.signal_pid = Some(pid);
+        self
+    }
+
+    /// Spawn the writer on the current tokio runtime.
+    #[allow(clippy::must_use_candidate)]
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    /// Write updates until the sending builder and all its streams are dropped.
+    ///
+    /// An update already received before the writer starts is written
+    /// immediately.
+    pub async fn run(mut self) {
+        self.contexts.mark_changed();
+        while self.contexts.changed().await.is_ok() {
+            let x509_context = self.contexts.borrow_and_update().clone();
+            let Some(x509_context) = x509_context else {
+                continue;
+            };
+            let files = self.files.clone();
+            let written = tokio::task::spawn_blocking(move || files.write(&x509_context))
+                .await
+                .unwrap_or_else(|err| Err(io::Error::other(err).into()));
+            if let Err(err) = written {
+                #[cfg(feature = "tracing")]
+                error!(error = %err, "failed to write SVID files");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+
+                continue;
+            }
+
+            #[cfg(feature = "tracing")]
+            info!(cert = %self.files.cert.display(), "wrote SVID files");
+
+            self.reload().await;
+        }
+    }
+
+    async fn reload(&self) {
+        if let Some((program, args)) = &self.reload_command {
+            run_command(Command::new(program).args(args)).await;
+        }
+        if let Some(pid) = self.signal_pid {
+            run_command(Command::new("kill").arg("-HUP").arg(pid.to_string())).await;
+        }
+    }
+}
+
+async fn run_command(command: &mut Command) {
+    match command.status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            #[cfg(feature = "tracing")]
+            warn!(?command, %status, "reload command failed");
+
+            #[cfg(not(feature = "tracing"))]
+            let _ = status;
+        }
+        Err(err) => {
+            #[cfg(feature = "tracing")]
+            warn!(?command, error = %err, "could not run reload command");
+
+            #[cfg(not(feature = "tracing"))]
+            let _ = err;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use super::write_atomic;
+
+    /// A new empty directory for one test.
+    fn test_dir(name: &str) -> io::Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("rustls-spiffe-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn replaces_contents_without_leaving_temp_files() -> io::Result<()> {
+        let dir = test_dir("write-atomic")?;
+        let path = dir.join("svid.pem");
+        write_atomic(&path, b"first", false)?;
+        write_atomic(&path, b"second", false)?;
+        assert_eq!(fs::read(&path)?, b"second");
+        assert_eq!(fs::read_dir(&dir)?.count(), 1);
+        fs::remove_dir_all(dir)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sets_modes_explicitly() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("write-atomic-modes")?;
+        let key = dir.join("key.pem");
+        let cert = dir.join("cert.pem");
+        write_atomic(&key, b"key", true)?;
+        write_atomic(&cert, b"cert", false)?;
+        assert_eq!(fs::metadata(&key)?.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&cert)?.permissions().mode() & 0o777, 0o644);
+        fs::remove_dir_all(dir)
+    }
+
+    #[test]
+    fn rejects_paths_without_file_name() {
+        let err = write_atomic("/".as_ref(), b"", false).err();
+        assert_eq!(err.map(|err| err.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    #[cfg(all(unix, feature = "aws-lc-rs"))]
+    mod writer {
+        use std::{fs, os::unix::process::ExitStatusExt, path::Path, sync::Arc, time::Duration};
+
+        use spiffe::X509Context;
+        use tokio::{process::Command, sync::watch};
+
+        use crate::{
+            PemFiles, SourceError, SvidFileWriter, cert_chain_pem, private_key_pem,
+            test_certs::{Ca, DAY, context},
+        };
+
+        fn update(ca: &Ca) -> Result<X509Context, SourceError> {
+            Ok(context(
+                vec![ca.svid("spiffe://example.org/workload", DAY)?],
+                vec![ca.bundle("example.org", false)?],
+            ))
+        }
+
+        /// Waits for `path` to hold `lines` lines.
+        async fn lines(path: &Path, lines: usize) -> Result<(), SourceError> {
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while fs::read_to_string(path).map_or(0, |contents| contents.lines().count())
+                    < lines
+                {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await?;
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn writes_each_update_and_runs_the_reload_command() -> Result<(), SourceError> {
+            let dir = tempfile::tempdir()?;
+            let (cert, key) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+            let reloads = dir.path().join("reloads");
+            let ca = Ca::root("root")?;
+            let first = update(&ca)?;
+            let (tx, rx) = watch::channel(Some(Arc::new(first.clone())));
+            let writer = SvidFileWriter::new(PemFiles::new(&cert, &key), rx)
+                .with_reload_command(
+                    "sh",
+                    ["-c", &format!("echo reloaded >> {}", reloads.display())],
+                )
+                .spawn();
+
+            lines(&reloads, 1).await?;
+            let svid = first.default_svid().ok_or("no SVID")?;
+            assert_eq!(fs::read_to_string(&cert)?, cert_chain_pem(svid));
+            assert_eq!(fs::read_to_string(&key)?, private_key_pem(svid));
+
+            let second = update(&ca)?;
+            tx.send_replace(Some(Arc::new(second.clone())));
+            lines(&reloads, 2).await?;
+            let svid = second.default_svid().ok_or("no SVID")?;
+            assert_eq!(fs::read_to_string(&cert)?, cert_chain_pem(svid));
+
+            drop(tx);
+            tokio::time::timeout(Duration::from_secs(5), writer).await??;
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn sends_sighup_after_writing() -> Result<(), SourceError> {
+            let dir = tempfile::tempdir()?;
+            let mut child = Command::new("sleep").arg("30").kill_on_drop(true).spawn()?;
+            let pid = child.id().ok_or("child exited")?;
+            let (_tx, rx) = watch::channel(Some(Arc::new(update(&Ca::root("root")?)?)));
+            let files = PemFiles::new(dir.path().join("cert.pem"), dir.path().join("key.pem"));
+            let _writer = SvidFileWriter::new(files, rx).with_sighup(pid).spawn();
+
+            let status = tokio::time::timeout(Duration::from_secs(5), child.wait()).await??;
+            assert_eq!(status.signal(), Some(1));
+            assert!(dir.path().join("key.pem").exists());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use rustls::pki_types::PrivateKeyDer;
+
+use crate::der::{OID_EC_PUBLIC_KEY, OID_PRIME256V1, der};
+
+const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_SECP521R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x23];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// The PKCS#8 encoding of the DER private key `key`, the only encoding
+/// `X509Svid::parse_from_der` accepts.
+///
+/// SEC1 EC keys (`EC PRIVATE KEY`), as written by OpenSSL and cert-manager,
+/// and PKCS#1 RSA keys (`RSA PRIVATE KEY`) are wrapped in a PKCS#8
+/// `PrivateKeyInfo`. PKCS#8 keys, and keys that cannot be identified, are
+/// returned unchanged for the SVID parser to accept or reject.
+pub fn pkcs8_private_key(key: &[u8]) -> Vec<u8> {
+    let algorithm = match PrivateKeyDer::try_from(key) {
+        Ok(PrivateKeyDer::Pkcs1(_)) => {
+            Some([der(0x06, OID_RSA_ENCRYPTION), der(0x05, &[])].concat())
+        }
+        Ok(PrivateKeyDer::Sec1(_)) => {
+            sec1_curve(key).map(|curve| [der(0x06, OID_EC_PUBLIC_KEY), curve].concat())
+        }
+        _ => None,
+    };
+    algorithm.map_or_else(
+        || key.to_vec(),
+        |algorithm| {
+            der(
+                0x30,
+                &[der(0x02, &[0]), der(0x30, &algorithm), der(0x04, key)].concat(),
+            )
+        },
+    )
+}
+
+/// The DER curve OID of the SEC1 `ECPrivateKey` `key`: its parameters, or
+/// the curve implied by the length of the private key if it has none.
+fn sec1_curve(key: &[u8]) -> Option<Vec<u8>> {
+    let (_, fields, _) = read(key)?;
+    let (_, _version, rest) = read(fields)?;
+    let (_, private_key, rest) = read(rest)?;
+    if let Some((0xa0, parameters, _)) = read(rest) {
+        return Some(parameters.to_vec());
+    }
+    let oid = match private_key.len() {
+        32 => OID_PRIME256V1,
+        48 => OID_SECP384R1,
+        66 => OID_SECP521R1,
+        _ => return None,
+    };
+    Some(der(0x06, oid))
+}
+
+/// The tag, contents and remaining bytes of the first DER element in
+/// `input`, or `None` if it is truncated.
+fn read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count > size_of::<usize>() {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at_checked(count)?;
+        let len = bytes
+            .iter()
+            .fold(0, |len, &byte| (len << 8) | usize::from(byte));
+        (len, rest)
+    };
+    let (contents, rest) = rest.split_at_checked(len)?;
+    Some((tag, contents, rest))
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::sync::Arc;
+
+use spiffe::X509Context;
+use tokio::sync::mpsc;
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+#[cfg(feature = "tracing")]
+use tracing::{info, warn};
+
+use crate::{
+    RetryPolicy, SourceError, SpiffeConfigError,
+    svid_source::{ConnectFuture, FetchFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] that connects to another source, and survives failures
+/// of its stream by reconnecting, according to a [`RetryPolicy`].
+#[derive(Clone)]
+pub struct ReconnectSource {
+    inner: Arc<dyn SvidSource>,
+    policy: RetryPolicy,
+}
+
+impl ReconnectSource {
+    pub fn new(inner: Arc<dyn SvidSource>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Connect to the inner source, retrying according to the policy, after
+    /// the policy's first delay if `delay_first`.
+    async fn connect_with_retries(
+        &self,
+        delay_first: bool,
+    ) -> Result<X509ContextStream, SpiffeConfigError> {
+        let mut retries = 0;
+        if delay_first {
+            tokio::time::sleep(self.policy.delay(0)).await;
+        }
+        loop {
+            match self.inner.connect().await {
+                Ok(stream) => return Ok(stream),
+                Err(err) if self.policy.exhausted(retries) => {
+                    return Err(SpiffeConfigError::RetriesExhausted(retries, err));
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "reconnect_source", retries, error = %err, "failed to connect SVID source");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+            }
+            tokio::time::sleep(self.policy.delay(retries + u32::from(delay_first))).await;
+            retries += 1;
+        }
+    }
+
+    async fn run(
+        self,
+        mut stream: X509ContextStream,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        loop {
+            let update = tokio::select! {
+                update = stream.next() => update,
+                () = tx.closed() => return,
+            };
+            match update {
+                Some(Ok(x509_context)) => {
+                    if tx.send(Ok(x509_context)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                Some(Err(err)) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "reconnect_source", error = %err, "SVID source stream failed, reconnecting");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+                None => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "reconnect_source", "SVID source stream ended, reconnecting");
+                }
+            }
+            stream = tokio::select! {
+                reconnected = self.connect_with_retries(true) => match reconnected {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        let _ = tx.send(Err(err.into())).await;
+                        return;
+                    }
+                },
+                () = tx.closed() => return,
+            };
+
+            #[cfg(feature = "tracing")]
+            info!(name: "reconnect_source", "reconnected SVID source");
+        }
+    }
+}
+
+impl SvidSource for ReconnectSource {
+    fn fetch(&self) -> FetchFuture<'_> {
+        self.inner.fetch()
+    }
+
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let stream = self.connect_with_retries(false).await?;
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(self.clone().run(stream, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        self.inner.describe()
+    }
+}
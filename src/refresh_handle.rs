@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use tokio::sync::watch;
+
+/// Handle for forcing running config streams to refresh.
+///
+/// Obtained from `SpiffeClientConfigStreamBuilder::refresh_handle` or
+/// `SpiffeServerConfigStreamBuilder::refresh_handle`. A refresh applies to
+/// every stream built by that builder: each stream fetches a new X509 context
+/// from its SVID source and yields a config rebuilt from it, e.g. after SPIRE
+/// registrations were fixed.
+#[derive(Clone, Debug)]
+pub struct RefreshHandle {
+    refresh: watch::Sender<()>,
+}
+
+impl RefreshHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            refresh: watch::Sender::new(()),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.refresh.subscribe()
+    }
+
+    /// Make every stream fetch a new X509 context and rebuild its config.
+    ///
+    /// A refresh requested while the previous one is still being fetched
+    /// replaces it.
+    pub fn refresh_now(&self) {
+        self.refresh.send_replace(());
+    }
+}
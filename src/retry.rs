@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// How a config stream connects to its SVID source, and reconnects after the
+/// source's stream fails or ends.
+///
+/// A failed connection is retried up to the maximum number of retries, if
+/// any; once they are exhausted the config stream fails with
+/// [`SpiffeConfigError::RetriesExhausted`](crate::SpiffeConfigError::RetriesExhausted).
+///
+/// Delays start at the initial backoff and double after every failed attempt
+/// up to the maximum backoff. With jitter, each delay is shortened by a random
+/// fraction of up to `jitter`, so that many workloads restarted by the same
+/// agent outage do not reconnect in lockstep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct RetryPolicy {
+    /// Retries after a failed connection before giving up, or `None` to retry
+    /// forever. Defaults to `None`.
+    pub max_retries: Option<u32>,
+    /// Delay before the first reconnection attempt. Defaults to 10ms.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts. Defaults to 10s.
+    pub max_backoff: Duration,
+    /// Fraction, between 0 and 1, by which delays are randomly shortened.
+    /// Defaults to 0; values outside the range are clamped to it, and NaN
+    /// counts as 0.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy: unlimited retries, 10ms doubling up to 10s,
+    /// without jitter.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_retries: None,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+        }
+    }
+
+    /// Give up after `retries` failed retries.
+    #[must_use]
+    pub const fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Wait `backoff` before the first reconnection attempt.
+    #[must_use]
+    pub const fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Wait at most `backoff` between attempts.
+    #[must_use]
+    pub const fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Shorten delays by a random fraction of up to `jitter`, clamped to
+    /// between 0 and 1.
+    #[must_use]
+    pub const fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = clamp_jitter(jitter);
+        self
+    }
+
+    /// Returns whether `retries` retries have used up the policy.
+    pub(crate) fn exhausted(&self, retries: u32) -> bool {
+        self.max_retries.is_some_and(|max| retries >= max)
+    }
+
+    /// The delay before reconnection attempt `attempt`, counting from 0.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        // the field is public, so it may not have gone through `with_jitter`
+        let jitter = clamp_jitter(self.jitter);
+        if jitter <= 0.0 {
+            return backoff;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        backoff.mul_f64(jitter.mul_add(-random, 1.0))
+    }
+}
+
+const fn clamp_jitter(jitter: f64) -> f64 {
+    if jitter.is_nan() {
+        0.0
+    } else {
+        jitter.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn delay_doubles_up_to_max_backoff() {
+        let policy = RetryPolicy::new()
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_secs(1));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_only_shortens_delays() {
+        let policy = RetryPolicy::new()
+            .with_initial_backoff(Duration::from_secs(1))
+            .with_jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.delay(0);
+            assert!(delay >= Duration::from_millis(500), "{delay:?}");
+            assert!(delay <= Duration::from_secs(1), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn out_of_range_jitter_is_clamped() {
+        let mut policy = RetryPolicy::new().with_initial_backoff(Duration::from_secs(1));
+        for jitter in [2.0, -1.0, f64::NAN, f64::INFINITY] {
+            policy.jitter = jitter;
+            assert!(policy.delay(0) <= Duration::from_secs(1));
+        }
+        assert!(RetryPolicy::new().with_jitter(f64::NAN).jitter == 0.0);
+    }
+
+    #[test]
+    fn exhausted_after_max_retries() {
+        let policy = RetryPolicy::new().with_max_retries(2);
+        assert!(!policy.exhausted(1));
+        assert!(policy.exhausted(2));
+        assert!(!RetryPolicy::new().exhausted(u32::MAX));
+    }
+}
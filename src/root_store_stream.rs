@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use rustls::RootCertStore;
+use spiffe::{TrustDomain, X509Context};
+use tokio::sync::watch;
+use tokio_stream::{Stream, wrappers::WatchStream};
+
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::{
+    SpiffeConfigError, TrustDomainHandle, TrustDomainStore,
+    svid_source::{SvidSource, WorkloadApiSource, X509ContextStream},
+    trust_domain_store::RootStoreOptions,
+};
+
+/// Builder for a [`SpiffeRootStoreStream`].
+///
+/// The builder controls which SPIFFE trust bundles are included in each
+/// yielded [`RootCertStore`].
+pub struct SpiffeRootStoreStreamBuilder {
+    trust_domains: TrustDomainHandle,
+    root_store_options: RootStoreOptions,
+    source: Arc<dyn SvidSource>,
+}
+
+impl SpiffeRootStoreStreamBuilder {
+    fn new(trust_domains: Vec<TrustDomain>) -> Self {
+        Self {
+            trust_domains: TrustDomainHandle::new(trust_domains),
+            root_store_options: RootStoreOptions::default(),
+            source: Arc::new(WorkloadApiSource::new()),
+        }
+    }
+
+    /// Also trust the trust domain of the workload's default SVID.
+    #[must_use]
+    pub const fn with_local_trust_domain(mut self, trust_local: bool) -> Self {
+        self.root_store_options.trust_local_domain = trust_local;
+        self
+    }
+
+    /// Returns a [`TrustDomainHandle`] for changing the trust domains of streams
+    /// built by this builder after they have started.
+    #[must_use]
+    pub fn trust_domain_handle(&self) -> TrustDomainHandle {
+        self.trust_domains.clone()
+    }
+
+    /// Require every configured trust domain to be present in each Workload API
+    /// update.
+    ///
+    /// When enabled, an update whose bundle set is missing any configured trust
+    /// domain yields [`SpiffeConfigError::MissingBundle`] instead of a root
+    /// store built from the remaining trust domains.
+    #[must_use]
+    pub const fn with_strict_trust_domains(mut self, strict: bool) -> Self {
+        self.root_store_options.strict = strict;
+        self
+    }
+
+    /// Skip bundle authorities whose `notAfter` has passed.
+    #[must_use]
+    pub const fn with_skip_expired_authorities(mut self, skip: bool) -> Self {
+        self.root_store_options.authorities.skip_expired = skip;
+        self
+    }
+
+    /// Only add bundle authorities that carry a basicConstraints extension with
+    /// `CA=true`.
+    #[must_use]
+    pub const fn with_require_ca_authorities(mut self, require: bool) -> Self {
+        self.root_store_options.authorities.require_ca = require;
+        self
+    }
+
+    /// Read bundles from `source` instead of the SPIFFE Workload API.
+    #[must_use]
+    pub fn with_svid_source(mut self, source: impl SvidSource) -> Self {
+        self.source = Arc::new(source);
+        self
+    }
+
+    /// Connect to the SVID source and start streaming root stores.
+    ///
+    /// # Errors
+    /// [`SpiffeConfigError::Source`] if the SVID source cannot be connected.
+    pub async fn build(&self) -> Result<SpiffeRootStoreStream, SpiffeConfigError> {
+        let inner = self
+            .source
+            .connect()
+            .await
+            .map_err(SpiffeConfigError::Source)?;
+        Ok(SpiffeRootStoreStream {
+            inner,
+            trust_domains: self.trust_domains.subscribe(),
+            trust_domain_updates: WatchStream::from_changes(self.trust_domains.subscribe()),
+            last_context: None,
+            root_store_options: self.root_store_options,
+        })
+    }
+}
+
+/// A stream that yields a [`RootCertStore`] built from the configured SPIFFE
+/// trust bundles on every Workload API update.
+///
+/// For services that only need rotating trust anchors, for example to verify
+/// webhook senders, without handling an SVID or private key. Changes made
+/// through the [`TrustDomainHandle`] rebuild the root store from the last
+/// update.
+///
+/// # Behavior
+///
+/// * SVID source errors are yielded as [`SpiffeConfigError::Source`].
+/// * An update that yields no roots produces [`SpiffeConfigError::MissingRoots`].
+/// * With strict trust domains enabled, an update missing a configured trust
+///   domain's bundle yields [`SpiffeConfigError::MissingBundle`].
+pub struct SpiffeRootStoreStream {
+    inner: X509ContextStream,
+    trust_domains: watch::Receiver<Vec<TrustDomain>>,
+    trust_domain_updates: WatchStream<Vec<TrustDomain>>,
+    last_context: Option<X509Context>,
+    root_store_options: RootStoreOptions,
+}
+
+impl TrustDomainStore for SpiffeRootStoreStream {
+    fn get_trust_domains(&self) -> Vec<TrustDomain> {
+        self.trust_domains.borrow().clone()
+    }
+
+    fn root_store_options(&self) -> RootStoreOptions {
+        self.root_store_options
+    }
+}
+
+impl SpiffeRootStoreStream {
+    /// Create a builder for root stores of the provided SPIFFE trust domains.
+    #[must_use]
+    pub fn builder(trust_domains: Vec<TrustDomain>) -> SpiffeRootStoreStreamBuilder {
+        SpiffeRootStoreStreamBuilder::new(trust_domains)
+    }
+
+    /// Create a builder that trusts only the trust domain of the workload's
+    /// default SVID.
+    ///
+    /// This is equivalent to `builder(vec![]).with_local_trust_domain(true)`.
+    #[must_use]
+    pub fn builder_local() -> SpiffeRootStoreStreamBuilder {
+        SpiffeRootStoreStreamBuilder::new(Vec::new()).with_local_trust_domain(true)
+    }
+
+    fn build_roots(
+        &self,
+        x509_context: &X509Context,
+    ) -> Result<Arc<RootCertStore>, SpiffeConfigError> {
+        let local = x509_context
+            .default_svid()
+            .map(|svid| svid.spiffe_id().trust_domain());
+        let roots = self.build_root_store(x509_context.bundle_set(), local)?;
+        if roots.is_empty() {
+            return Err(SpiffeConfigError::MissingRoots);
+        }
+        Ok(roots)
+    }
+}
+
+impl Stream for SpiffeRootStoreStream {
+    type Item = Result<Arc<RootCertStore>, SpiffeConfigError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // a trust domain change rebuilds the root store from the last known context
+        while let Poll::Ready(Some(_)) = Pin::new(&mut self.trust_domain_updates).poll_next(cx) {
+            if let Some(x509_context) = &self.last_context {
+                #[cfg(feature = "tracing")]
+                debug!("trust domains changed, rebuilding root store");
+
+                return Poll::Ready(Some(self.build_roots(x509_context)));
+            }
+        }
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(SpiffeConfigError::Source(err)))),
+            Poll::Ready(Some(Ok(x509_context))) => {
+                let roots = self.build_roots(&x509_context);
+                self.last_context = Some(x509_context);
+                Poll::Ready(Some(roots))
+            }
+        }
+    }
+}
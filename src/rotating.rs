@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use tokio::{sync::oneshot, time::sleep};
+#[cfg(feature = "tracing")]
+use tracing::{debug, error, info};
+
+/// A config stream builder whose streams a rotating config follows.
+pub trait Follow: Send + 'static {
+    type Stream: Send + 'static;
+    type Parts: Send + Sync + 'static;
+    type Error: Display + Send;
+
+    /// The next parts of `stream`, or `None` once it ends.
+    fn next_parts(
+        stream: &mut Self::Stream,
+    ) -> impl Future<Output = Option<Result<Self::Parts, Self::Error>>> + Send;
+
+    /// Build a new stream after the last one failed or ended.
+    fn rebuild(&mut self) -> impl Future<Output = Result<Self::Stream, Self::Error>> + Send;
+
+    /// Whether the builder has been shut down, so streams are not rebuilt.
+    fn is_shut_down(&self) -> bool;
+}
+
+/// The state a rotating config reads its parts from.
+pub struct Shared<P> {
+    pub parts: ArcSwap<P>,
+    stream_healthy: AtomicBool,
+    /// Stops the refresh task once the config and its handle are dropped.
+    _dropped: oneshot::Sender<()>,
+}
+
+/// Resolves once the [`Shared`] state it was created with is dropped.
+pub type Dropped = oneshot::Receiver<()>;
+
+impl<P> Shared<P> {
+    /// Share `initial`, from a healthy stream.
+    pub fn new(initial: P) -> (Arc<Self>, Dropped) {
+        let (dropped_tx, dropped) = oneshot::channel();
+        let shared = Arc::new(Self {
+            parts: ArcSwap::from_pointee(initial),
+            stream_healthy: AtomicBool::new(true),
+            _dropped: dropped_tx,
+        });
+        (shared, dropped)
+    }
+
+    pub fn stream_healthy(&self) -> bool {
+        self.stream_healthy.load(Ordering::Relaxed)
+    }
+}
+
+impl<P> std::fmt::Debug for Shared<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shared")
+            .field("stream_healthy", &self.stream_healthy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Spawn the task storing every update of `stream` in `shared`, rebuilding
+/// the stream with `builder` when it fails, until `shared` is dropped.
+pub fn spawn_refresh<B: Follow>(
+    builder: B,
+    stream: B::Stream,
+    shared: &Arc<Shared<B::Parts>>,
+    dropped: Dropped,
+) {
+    tokio::spawn(refresh(builder, stream, Arc::downgrade(shared), dropped));
+}
+
+async fn refresh<B: Follow>(
+    builder: B,
+    stream: B::Stream,
+    shared: Weak<Shared<B::Parts>>,
+    dropped: Dropped,
+) {
+    tokio::select! {
+        () = follow(builder, stream, shared) => {}
+        _ = dropped => {}
+    }
+
+    #[cfg(feature = "tracing")]
+    debug!(name: "rotating_config", "config dropped, stopped following the workload api");
+}
+
+/// Stores every update, rebuilding the stream with exponential backoff
+/// starting at 10ms and capping at 10s when it fails, until the shared state
+/// is dropped.
+async fn follow<B: Follow>(mut builder: B, mut stream: B::Stream, shared: Weak<Shared<B::Parts>>) {
+    let initial_delay = Duration::from_millis(10);
+    let mut delay = initial_delay;
+    let max_delay = Duration::from_secs(10);
+    loop {
+        let update = B::next_parts(&mut stream).await;
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        if let Some(Ok(parts)) = update {
+            shared.parts.store(Arc::new(parts));
+
+            #[cfg(feature = "tracing")]
+            debug!(name: "rotating_config", "stored updated cert resolver and verifier");
+        } else {
+            shared.stream_healthy.store(false, Ordering::Relaxed);
+            if builder.is_shut_down() {
+                return;
+            }
+
+            #[cfg(feature = "tracing")]
+            error!(name: "rotating_config", "workload api stream returned error or none, trying to build new stream");
+
+            match builder.rebuild().await {
+                Ok(s) => {
+                    shared.stream_healthy.store(true, Ordering::Relaxed);
+                    delay = initial_delay;
+                    stream = s;
+
+                    #[cfg(feature = "tracing")]
+                    info!(name: "rotating_config", "reestablished workload api stream");
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    error!(name: "rotating_config", retry_in_ms = delay.as_millis(), error = %err, "failed to reestablish workload api stream");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+
+                    drop(shared);
+
+                    sleep(delay).await;
+                    delay = (delay * 2).min(max_delay);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, time::Duration};
+
+    use tokio::sync::mpsc;
+
+    use super::{Follow, Shared, spawn_refresh};
+    use crate::SourceError;
+
+    type Updates = mpsc::Receiver<Result<u32, String>>;
+
+    /// Streams the receivers it is given, one per build.
+    struct Builder(mpsc::Receiver<Updates>);
+
+    impl Follow for Builder {
+        type Stream = Updates;
+        type Parts = u32;
+        type Error = String;
+
+        fn next_parts(
+            stream: &mut Updates,
+        ) -> impl Future<Output = Option<Result<u32, String>>> + Send {
+            stream.recv()
+        }
+
+        async fn rebuild(&mut self) -> Result<Updates, String> {
+            self.0.recv().await.ok_or_else(|| "no more streams".into())
+        }
+
+        fn is_shut_down(&self) -> bool {
+            false
+        }
+    }
+
+    /// Waits until the parts of `shared` are `parts`.
+    async fn parts(shared: &Shared<u32>, parts: u32) -> Result<(), SourceError> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while **shared.parts.load() != parts {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stores_updates_and_follows_rebuilt_streams() -> Result<(), SourceError> {
+        let (streams_tx, streams) = mpsc::channel(1);
+        let (tx, stream) = mpsc::channel(1);
+        let (shared, dropped) = Shared::new(0);
+        spawn_refresh(Builder(streams), stream, &shared, dropped);
+
+        tx.send(Ok(1)).await?;
+        parts(&shared, 1).await?;
+
+        let (next_tx, next) = mpsc::channel(1);
+        streams_tx.send(next).await?;
+        tx.send(Err("stream failed".into())).await?;
+        next_tx.send(Ok(2)).await?;
+        parts(&shared, 2).await?;
+        assert!(shared.stream_healthy());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dropping_the_shared_state_stops_the_task() -> Result<(), SourceError> {
+        let (_streams_tx, streams) = mpsc::channel(1);
+        let (tx, stream) = mpsc::channel(1);
+        let (shared, dropped) = Shared::new(0);
+        spawn_refresh(Builder(streams), stream, &shared, dropped);
+
+        drop(shared);
+        tokio::time::timeout(Duration::from_secs(5), tx.closed()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    future::{Future, poll_fn},
+    sync::Arc,
+};
+
+use rustls::{
+    ClientConfig, DigitallySignedStruct, Error, SignatureScheme,
+    client::{
+        ResolvesClientCert,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    sign::CertifiedKey,
+};
+use rustls_config_stream::{ClientConfigStreamBuilder, ClientConfigStreamError};
+use tokio::sync::watch;
+
+use crate::{
+    WorkloadIdentity,
+    client_stream::{ClientParts, SpiffeClientConfigStream, SpiffeClientConfigStreamBuilder},
+    rotating::{Follow, Shared, spawn_refresh},
+};
+
+/// A single long-lived [`ClientConfig`] that follows SPIFFE Workload API
+/// updates without being replaced.
+///
+/// The client counterpart of [`RotatingServerConfig`](crate::RotatingServerConfig):
+/// the config returned by [`client_config`](Self::client_config) uses a client
+/// certificate resolver and server certificate verifier that read from the
+/// latest Workload API update, so libraries that only accept a [`ClientConfig`]
+/// once still present renewed SVIDs and trust renewed bundles on new
+/// connections.
+///
+/// Stream failures are handled the same way as
+/// [`ClientConfigProvider`](crate::ClientConfigProvider): the stream is
+/// rebuilt with exponential backoff starting at 10ms and capping at 10s, and
+/// the last good SVID and roots stay in use meanwhile.
+pub struct RotatingClientConfig {
+    config: Arc<ClientConfig>,
+    shared: Arc<Shared<ClientParts>>,
+    identity: watch::Receiver<Option<WorkloadIdentity>>,
+}
+
+impl RotatingClientConfig {
+    /// Build the stream, wait for the first Workload API update and spawn the
+    /// background refresh task.
+    ///
+    /// # Errors
+    /// - [`ClientConfigStreamError::EmptyStream`]: the initial stream yielded no item.
+    /// - Any error from building the stream or the first update.
+    pub async fn start(
+        mut builder: SpiffeClientConfigStreamBuilder,
+    ) -> Result<Arc<Self>, ClientConfigStreamError> {
+        let identity = builder.identity_updates();
+        let mut stream = builder.build().await?;
+        let initial = SpiffeClientConfigStreamBuilder::next_parts(&mut stream)
+            .await
+            .ok_or(ClientConfigStreamError::EmptyStream)??;
+        let (shared, dropped) = Shared::new(initial);
+        let mut config = builder
+            .config_builder()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(RotatingVerifier(shared.clone())))
+            .with_client_cert_resolver(Arc::new(RotatingResolver(shared.clone())));
+        builder.customize(&mut config);
+
+        spawn_refresh(builder, stream, &shared, dropped);
+
+        Ok(Arc::new(Self {
+            config: Arc::new(config),
+            shared,
+            identity,
+        }))
+    }
+
+    /// Returns the long-lived [`ClientConfig`].
+    #[must_use]
+    pub fn client_config(&self) -> Arc<ClientConfig> {
+        self.config.clone()
+    }
+
+    /// Returns whether the Workload API stream is currently healthy.
+    #[must_use]
+    pub fn stream_healthy(&self) -> bool {
+        self.shared.stream_healthy()
+    }
+
+    /// Returns the identity of the SVID currently in use.
+    #[must_use]
+    pub fn identity(&self) -> Option<WorkloadIdentity> {
+        self.identity.borrow().clone()
+    }
+}
+
+impl Follow for SpiffeClientConfigStreamBuilder {
+    type Stream = SpiffeClientConfigStream;
+    type Parts = ClientParts;
+    type Error = ClientConfigStreamError;
+
+    async fn next_parts(
+        stream: &mut SpiffeClientConfigStream,
+    ) -> Option<Result<ClientParts, ClientConfigStreamError>> {
+        poll_fn(|cx| stream.poll_parts(cx)).await
+    }
+
+    fn rebuild(
+        &mut self,
+    ) -> impl Future<Output = Result<SpiffeClientConfigStream, ClientConfigStreamError>> + Send
+    {
+        self.build()
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown_handle().is_shut_down()
+    }
+}
+
+#[derive(Debug)]
+struct RotatingResolver(Arc<Shared<ClientParts>>);
+
+impl ResolvesClientCert for RotatingResolver {
+    fn resolve(
+        &self,
+        root_hint_subjects: &[&[u8]],
+        sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        self.0
+            .parts
+            .load()
+            .resolver
+            .resolve(root_hint_subjects, sigschemes)
+    }
+
+    fn has_certs(&self) -> bool {
+        self.0.parts.load().resolver.has_certs()
+    }
+}
+
+#[derive(Debug)]
+struct RotatingVerifier(Arc<Shared<ClientParts>>);
+
+impl ServerCertVerifier for RotatingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        self.0.parts.load().verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.0
+            .parts
+            .load()
+            .verifier
+            .verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.0
+            .parts
+            .load()
+            .verifier
+            .verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.parts.load().verifier.supported_verify_schemes()
+    }
+}
@@ -0,0 +1,234 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    future::{Future, poll_fn},
+    sync::Arc,
+};
+
+use rustls::{
+    DigitallySignedStruct, DistinguishedName, Error, ServerConfig, SignatureScheme,
+    client::danger::HandshakeSignatureValid,
+    pki_types::{CertificateDer, UnixTime},
+    server::{
+        ClientHello, ResolvesServerCert,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+    sign::CertifiedKey,
+};
+use rustls_config_stream::{ServerConfigStreamBuilder, ServerConfigStreamError};
+use tokio::sync::watch;
+
+use crate::{
+    WorkloadIdentity,
+    rotating::{Follow, Shared, spawn_refresh},
+    server_stream::{ServerParts, SpiffeServerConfigStream, SpiffeServerConfigStreamBuilder},
+};
+
+/// A single long-lived [`ServerConfig`] that follows SPIFFE Workload API
+/// updates without being replaced.
+///
+/// Where [`ServerConfigProvider`](crate::ServerConfigProvider) swaps whole
+/// configs, this installs a certificate resolver and client certificate
+/// verifier that read from the latest Workload API update. The config returned
+/// by [`server_config`](Self::server_config) never changes, so it can be handed
+/// to a plain `TlsAcceptor` once, and session tickets and the session cache
+/// survive SVID and bundle rotation.
+///
+/// Stream failures are handled the same way as
+/// [`ServerConfigProvider`](crate::ServerConfigProvider): the stream is
+/// rebuilt with exponential backoff starting at 10ms and capping at 10s, and
+/// the last good SVID and roots stay in use meanwhile.
+///
+/// The verifier cannot borrow `root_hint_subjects` across updates, so
+/// servers using this config send no CA hints in their `CertificateRequest`.
+pub struct RotatingServerConfig {
+    config: Arc<ServerConfig>,
+    shared: Arc<Shared<ServerParts>>,
+    identity: watch::Receiver<Option<WorkloadIdentity>>,
+}
+
+impl RotatingServerConfig {
+    /// Build the stream, wait for the first Workload API update and spawn the
+    /// background refresh task.
+    ///
+    /// # Errors
+    /// - [`ServerConfigStreamError::EmptyStream`]: the initial stream yielded no item.
+    /// - Any error from building the stream or the first update.
+    pub async fn start(
+        mut builder: SpiffeServerConfigStreamBuilder,
+    ) -> Result<Arc<Self>, ServerConfigStreamError> {
+        let identity = builder.identity_updates();
+        let mut stream = builder.build().await?;
+        let initial = SpiffeServerConfigStreamBuilder::next_parts(&mut stream)
+            .await
+            .ok_or(ServerConfigStreamError::EmptyStream)??;
+        let (shared, dropped) = Shared::new(initial);
+        let mut config = builder
+            .config_builder()?
+            .with_client_cert_verifier(Arc::new(RotatingVerifier(shared.clone())))
+            .with_cert_resolver(Arc::new(RotatingResolver(shared.clone())));
+        builder.customize(&mut config)?;
+
+        spawn_refresh(builder, stream, &shared, dropped);
+
+        Ok(Arc::new(Self {
+            config: Arc::new(config),
+            shared,
+            identity,
+        }))
+    }
+
+    /// Returns the long-lived [`ServerConfig`].
+    #[must_use]
+    pub fn server_config(&self) -> Arc<ServerConfig> {
+        self.config.clone()
+    }
+
+    /// Returns whether the Workload API stream is currently healthy.
+    #[must_use]
+    pub fn stream_healthy(&self) -> bool {
+        self.shared.stream_healthy()
+    }
+
+    /// Returns the identity of the SVID currently in use.
+    #[must_use]
+    pub fn identity(&self) -> Option<WorkloadIdentity> {
+        self.identity.borrow().clone()
+    }
+}
+
+impl Follow for SpiffeServerConfigStreamBuilder {
+    type Stream = SpiffeServerConfigStream;
+    type Parts = ServerParts;
+    type Error = ServerConfigStreamError;
+
+    async fn next_parts(
+        stream: &mut SpiffeServerConfigStream,
+    ) -> Option<Result<ServerParts, ServerConfigStreamError>> {
+        poll_fn(|cx| stream.poll_parts(cx)).await
+    }
+
+    fn rebuild(
+        &mut self,
+    ) -> impl Future<Output = Result<SpiffeServerConfigStream, ServerConfigStreamError>> + Send
+    {
+        self.build()
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown_handle().is_shut_down()
+    }
+}
+
+#[derive(Debug)]
+struct RotatingResolver(Arc<Shared<ServerParts>>);
+
+impl ResolvesServerCert for RotatingResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.0.parts.load().resolver.resolve(client_hello)
+    }
+}
+
+#[derive(Debug)]
+struct RotatingVerifier(Arc<Shared<ServerParts>>);
+
+impl ClientCertVerifier for RotatingVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.0.parts.load().verifier.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.0.parts.load().verifier.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        self.0
+            .parts
+            .load()
+            .verifier
+            .verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.0
+            .parts
+            .load()
+            .verifier
+            .verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.0
+            .parts
+            .load()
+            .verifier
+            .verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.parts.load().verifier.supported_verify_schemes()
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::time::Duration;
+
+    use spiffe::{TrustDomain, X509Context};
+
+    use super::RotatingServerConfig;
+    use crate::{
+        SourceError, SpiffeServerConfigStream,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    fn update(ca: &Ca) -> Result<X509Context, SourceError> {
+        Ok(context(
+            vec![ca.svid("spiffe://example.org/workload", DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        ))
+    }
+
+    #[tokio::test]
+    async fn follows_rotations_until_dropped() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let (source, tx) = ChannelSource::new(update(&ca)?);
+        let builder = SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source);
+        tx.send(Ok(update(&ca)?)).await?;
+        let rotating = RotatingServerConfig::start(builder).await?;
+        let first = rotating.identity().ok_or("no identity")?;
+
+        tx.send(Ok(update(&ca)?)).await?;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while rotating.identity().as_ref() == Some(&first) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await?;
+        assert!(rotating.stream_healthy());
+
+        drop(rotating);
+        tokio::time::timeout(Duration::from_secs(5), tx.closed()).await?;
+        Ok(())
+    }
+}
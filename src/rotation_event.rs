@@ -0,0 +1,267 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use rustls::pki_types::CertificateDer;
+use spiffe::{TrustDomain, X509Context};
+
+use crate::{WorkloadIdentity, svid_selector::SvidSelector};
+
+/// What changed between two Workload API updates, broadcast on the
+/// `rotation_events` of the config stream builders.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RotationEvent {
+    /// The identity of the new SVID, i.e. the one selected with
+    /// `with_svid_id` or else the default SVID.
+    pub identity: Option<WorkloadIdentity>,
+    /// The identity of the previous SVID, or `None` for the first update.
+    pub previous: Option<WorkloadIdentity>,
+    /// Whether the SPIFFE ID of the SVID changed, not only its certificate.
+    pub spiffe_id_changed: bool,
+    /// The bundle changes of every trust domain whose authorities changed.
+    pub bundles: Vec<BundleChange>,
+}
+
+/// The authorities added to and removed from the bundle of one trust domain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BundleChange {
+    /// The trust domain of the bundle.
+    pub trust_domain: TrustDomain,
+    /// The DER-encoded authorities that were added.
+    pub added: Vec<CertificateDer<'static>>,
+    /// The DER-encoded authorities that were removed.
+    pub removed: Vec<CertificateDer<'static>>,
+}
+
+impl RotationEvent {
+    /// Diff `current` against `previous` over the SVID chosen by `svid`, the
+    /// bundles of `trust_domains` and that SVID's trust domain, or `None` if
+    /// neither the SVID nor those bundles changed.
+    pub(crate) fn diff(
+        previous: Option<&X509Context>,
+        current: &X509Context,
+        svid: &SvidSelector,
+        trust_domains: &[TrustDomain],
+    ) -> Option<Self> {
+        let identity = svid.select(current).and_then(WorkloadIdentity::from_svid);
+        let previous_identity = previous
+            .and_then(|previous| svid.select(previous))
+            .and_then(WorkloadIdentity::from_svid);
+
+        let mut trust_domains = trust_domains.to_vec();
+        for identity in [&identity, &previous_identity].into_iter().flatten() {
+            let trust_domain = identity.spiffe_id.trust_domain();
+            if !trust_domains.contains(trust_domain) {
+                trust_domains.push(trust_domain.clone());
+            }
+        }
+        let bundles: Vec<BundleChange> = trust_domains
+            .into_iter()
+            .filter_map(|trust_domain| {
+                let authorities = |x509_context: Option<&X509Context>| {
+                    x509_context
+                        .and_then(|x509_context| {
+                            x509_context.bundle_set().get_bundle(&trust_domain)
+                        })
+                        .map(|bundle| {
+                            bundle
+                                .authorities()
+                                .iter()
+                                .map(|authority| CertificateDer::from(authority.content().to_vec()))
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default()
+                };
+                let old = authorities(previous);
+                let new = authorities(Some(current));
+                let added: Vec<_> = new
+                    .iter()
+                    .filter(|cert| !old.contains(cert))
+                    .cloned()
+                    .collect();
+                let removed: Vec<_> = old
+                    .iter()
+                    .filter(|cert| !new.contains(cert))
+                    .cloned()
+                    .collect();
+                (!added.is_empty() || !removed.is_empty()).then_some(BundleChange {
+                    trust_domain,
+                    added,
+                    removed,
+                })
+            })
+            .collect();
+
+        if identity == previous_identity && bundles.is_empty() {
+            return None;
+        }
+        let spiffe_id_changed = identity.as_ref().map(|identity| &identity.spiffe_id)
+            != previous_identity
+                .as_ref()
+                .map(|identity| &identity.spiffe_id);
+        Some(Self {
+            identity,
+            previous: previous_identity,
+            spiffe_id_changed,
+            bundles,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use rustls::pki_types::CertificateDer;
+    use spiffe::TrustDomain;
+
+    use spiffe::SpiffeId;
+
+    use super::RotationEvent;
+    use crate::{
+        SourceError,
+        svid_selector::SvidSelector,
+        test_certs::{Ca, DAY, context},
+    };
+
+    const ID: &str = "spiffe://example.org/workload";
+
+    fn root_der(ca: &Ca) -> Result<CertificateDer<'static>, SourceError> {
+        let bundle = ca.bundle("example.org", false)?;
+        let root = bundle.authorities().first().ok_or("empty bundle")?;
+        Ok(CertificateDer::from(root.content().to_vec()))
+    }
+
+    #[test]
+    fn first_update_reports_identity_and_bundles() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let current = context(
+            vec![ca.svid(ID, DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+
+        let event =
+            RotationEvent::diff(None, &current, &SvidSelector::Default, &[]).ok_or("no event")?;
+        assert_eq!(
+            event.identity.ok_or("no identity")?.spiffe_id.to_string(),
+            ID
+        );
+        assert_eq!(event.previous, None);
+        assert!(event.spiffe_id_changed);
+        let [change] = event.bundles.as_slice() else {
+            return Err("expected one bundle change".into());
+        };
+        assert_eq!(change.trust_domain, TrustDomain::new("example.org")?);
+        assert_eq!(change.added, vec![root_der(&ca)?]);
+        assert!(change.removed.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn unchanged_updates_report_nothing() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let current = context(
+            vec![ca.svid(ID, DAY)?],
+            vec![ca.bundle("example.org", false)?],
+        );
+
+        assert_eq!(
+            RotationEvent::diff(Some(&current), &current, &SvidSelector::Default, &[]),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn svid_rotation_keeps_the_spiffe_id() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let bundle = ca.bundle("example.org", false)?;
+        let previous = context(vec![ca.svid(ID, DAY)?], vec![bundle.clone()]);
+        let current = context(vec![ca.svid(ID, DAY)?], vec![bundle]);
+
+        let event = RotationEvent::diff(Some(&previous), &current, &SvidSelector::Default, &[])
+            .ok_or("no event")?;
+        assert_ne!(event.identity, event.previous);
+        assert!(!event.spiffe_id_changed);
+        assert!(event.bundles.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn bundle_rotation_reports_added_and_removed_authorities() -> Result<(), SourceError> {
+        let (old_ca, new_ca) = (Ca::root("old")?, Ca::root("new")?);
+        let svid = old_ca.svid(ID, DAY)?;
+        let previous = context(
+            vec![svid.clone()],
+            vec![old_ca.bundle("example.org", false)?],
+        );
+        let current = context(vec![svid], vec![new_ca.bundle("example.org", false)?]);
+
+        let event = RotationEvent::diff(Some(&previous), &current, &SvidSelector::Default, &[])
+            .ok_or("no event")?;
+        assert_eq!(event.identity, event.previous);
+        let [change] = event.bundles.as_slice() else {
+            return Err("expected one bundle change".into());
+        };
+        assert_eq!(change.added, vec![root_der(&new_ca)?]);
+        assert_eq!(change.removed, vec![root_der(&old_ca)?]);
+        Ok(())
+    }
+
+    #[test]
+    fn only_tracked_trust_domains_are_diffed() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let svid = ca.svid(ID, DAY)?;
+        let previous = context(vec![svid.clone()], vec![ca.bundle("example.org", false)?]);
+        let federated = Ca::root("federated")?.bundle("other.org", false)?;
+        let current = context(
+            vec![svid],
+            vec![ca.bundle("example.org", false)?, federated],
+        );
+
+        assert_eq!(
+            RotationEvent::diff(Some(&previous), &current, &SvidSelector::Default, &[]),
+            None
+        );
+        let event = RotationEvent::diff(
+            Some(&previous),
+            &current,
+            &SvidSelector::Default,
+            &[TrustDomain::new("other.org")?],
+        )
+        .ok_or("no event")?;
+        let [change] = event.bundles.as_slice() else {
+            return Err("expected one bundle change".into());
+        };
+        assert_eq!(change.trust_domain, TrustDomain::new("other.org")?);
+        Ok(())
+    }
+
+    #[test]
+    fn the_selected_svid_is_diffed() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let bundle = ca.bundle("example.org", false)?;
+        let selected = "spiffe://example.org/selected";
+        let selector = SvidSelector::SpiffeId(SpiffeId::new(selected)?);
+        let (default, svid) = (ca.svid(ID, DAY)?, ca.svid(selected, DAY)?);
+        let previous = context(vec![default, svid.clone()], vec![bundle.clone()]);
+        let default_rotated = context(vec![ca.svid(ID, DAY)?, svid], vec![bundle.clone()]);
+
+        assert_eq!(
+            RotationEvent::diff(Some(&previous), &default_rotated, &selector, &[]),
+            None
+        );
+        let current = context(
+            vec![previous.svids()[0].clone(), ca.svid(selected, DAY)?],
+            vec![bundle],
+        );
+        let event =
+            RotationEvent::diff(Some(&previous), &current, &selector, &[]).ok_or("no event")?;
+        let identity = event.identity.ok_or("no identity")?;
+        assert_eq!(identity.spiffe_id.to_string(), selected);
+        assert_eq!(
+            event.previous.ok_or("no previous identity")?.spiffe_id,
+            identity.spiffe_id
+        );
+        assert!(!event.spiffe_id_changed);
+        Ok(())
+    }
+}
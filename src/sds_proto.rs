@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! The subset of the Envoy v3 xDS and TLS messages used by SDS.
+
+pub const STREAM_SECRETS: &str = "/envoy.service.secret.v3.SecretDiscoveryService/StreamSecrets";
+pub const SECRET_TYPE_URL: &str =
+    "type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.Secret";
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct DiscoveryRequest {
+    #[prost(string, tag = "1")]
+    pub version_info: String,
+    #[prost(message, optional, tag = "2")]
+    pub node: Option<Node>,
+    #[prost(string, repeated, tag = "3")]
+    pub resource_names: Vec<String>,
+    #[prost(string, tag = "4")]
+    pub type_url: String,
+    #[prost(string, tag = "5")]
+    pub response_nonce: String,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Node {
+    #[prost(string, tag = "1")]
+    pub id: String,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct DiscoveryResponse {
+    #[prost(string, tag = "1")]
+    pub version_info: String,
+    #[prost(message, repeated, tag = "2")]
+    pub resources: Vec<Any>,
+    #[prost(string, tag = "4")]
+    pub type_url: String,
+    #[prost(string, tag = "5")]
+    pub nonce: String,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Any {
+    #[prost(string, tag = "1")]
+    pub type_url: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Secret {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "2")]
+    pub tls_certificate: Option<TlsCertificate>,
+    #[prost(message, optional, tag = "4")]
+    pub validation_context: Option<CertificateValidationContext>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct TlsCertificate {
+    #[prost(message, optional, tag = "1")]
+    pub certificate_chain: Option<DataSource>,
+    #[prost(message, optional, tag = "2")]
+    pub private_key: Option<DataSource>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CertificateValidationContext {
+    #[prost(message, optional, tag = "1")]
+    pub trusted_ca: Option<DataSource>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct DataSource {
+    #[prost(oneof = "data_source::Specifier", tags = "1, 2, 3")]
+    pub specifier: Option<data_source::Specifier>,
+}
+
+pub mod data_source {
+    #[derive(Clone, PartialEq, Eq, prost::Oneof)]
+    pub enum Specifier {
+        #[prost(string, tag = "1")]
+        Filename(String),
+        #[prost(bytes = "vec", tag = "2")]
+        InlineBytes(Vec<u8>),
+        #[prost(string, tag = "3")]
+        InlineString(String),
+    }
+}
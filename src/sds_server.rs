@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    convert::Infallible,
+    fs,
+    future::{Ready, ready},
+    io,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use prost::Message;
+use spiffe::{SpiffeId, X509Bundle, X509Context, X509Svid};
+use tokio::{
+    net::UnixListener,
+    sync::{mpsc, watch},
+};
+use tokio_stream::wrappers::{ReceiverStream, UnixListenerStream};
+use tonic::{
+    Request, Response, Status, Streaming,
+    body::Body,
+    codegen::{Body as HttpBody, BoxFuture, Service, StdError, http},
+    server::{Grpc, StreamingService},
+    transport::Server,
+};
+use tonic_prost::ProstCodec;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::{
+    SourceError, bundle_pem, cert_chain_pem, private_key_pem,
+    sds_proto::{
+        Any, CertificateValidationContext, DataSource, DiscoveryRequest, DiscoveryResponse,
+        SECRET_TYPE_URL, STREAM_SECRETS, Secret, TlsCertificate, data_source::Specifier,
+    },
+};
+
+/// An Envoy Secret Discovery Service (SDS) server handing the workload's
+/// SVIDs and bundles to colocated Envoy instances.
+///
+/// Updates are taken from a config stream builder's `context_updates`, so
+/// Envoy follows the same Workload API stream as the rustls configs. Secrets
+/// are named like the SPIRE agent's SDS API:
+///
+/// - `default`: the default SVID, as a `tls_certificate`.
+/// - `ROOTCA`: the bundle of the default SVID's trust domain, as a
+///   `validation_context`.
+/// - a SPIFFE ID, e.g. `spiffe://example.org/web`: the SVID with that ID.
+/// - a trust domain ID, e.g. `spiffe://example.org`: that trust domain's
+///   bundle.
+///
+/// Requested secrets that are not in the current update are left out of the
+/// response until they appear.
+#[derive(Clone, Debug)]
+pub struct SdsServer {
+    contexts: watch::Receiver<Option<Arc<X509Context>>>,
+}
+
+impl SdsServer {
+    /// Serve every update received on `contexts`.
+    #[must_use]
+    pub const fn new(contexts: watch::Receiver<Option<Arc<X509Context>>>) -> Self {
+        Self { contexts }
+    }
+
+    /// Listen on the unix socket at `path` and serve SDS until an error
+    /// occurs.
+    ///
+    /// The socket is made accessible to its owner only, mode `0o600`, as
+    /// connecting clients are served the private key; run the proxy as the
+    /// same user, or widen the mode afterwards. The mode is set right after
+    /// binding, so place the socket in a directory other users cannot enter
+    /// to leave them no window to connect.
+    ///
+    /// # Errors
+    /// Binding the socket or setting its mode fails, e.g. because `path`
+    /// already exists, or the server fails.
+    pub async fn serve_unix(self, path: impl AsRef<Path>) -> Result<(), SourceError> {
+        let listener = bind_private(path.as_ref())?;
+        Server::builder()
+            .serve_with_incoming(self, UnixListenerStream::new(listener))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Bind the unix socket at `path`, accessible to its owner only.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+impl<B> Service<http::Request<B>> for SdsServer
+where
+    B: HttpBody + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != STREAM_SECRETS {
+            return Box::pin(async {
+                Ok(Status::unimplemented("only StreamSecrets is served").into_http())
+            });
+        }
+        let stream_secrets = StreamSecrets(self.contexts.clone());
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::<DiscoveryResponse, DiscoveryRequest>::default());
+            Ok(grpc.streaming(stream_secrets, request).await)
+        })
+    }
+}
+
+struct StreamSecrets(watch::Receiver<Option<Arc<X509Context>>>);
+
+impl StreamingService<DiscoveryRequest> for StreamSecrets {
+    type Response = DiscoveryResponse;
+    type ResponseStream = ReceiverStream<Result<DiscoveryResponse, Status>>;
+    type Future = Ready<Result<Response<Self::ResponseStream>, Status>>;
+
+    fn call(&mut self, request: Request<Streaming<DiscoveryRequest>>) -> Self::Future {
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(stream_secrets(self.0.clone(), request.into_inner(), tx));
+        ready(Ok(Response::new(ReceiverStream::new(rx))))
+    }
+}
+
+/// Respond to every change of the requested secret names, ignoring ACKs, and
+/// to every update on `contexts`.
+async fn stream_secrets(
+    mut contexts: watch::Receiver<Option<Arc<X509Context>>>,
+    mut requests: Streaming<DiscoveryRequest>,
+    tx: mpsc::Sender<Result<DiscoveryResponse, Status>>,
+) {
+    let mut names = None;
+    let mut version = 0_u64;
+    loop {
+        tokio::select! {
+            request = requests.message() => {
+                let Ok(Some(request)) = request else {
+                    return;
+                };
+                if names.as_ref() == Some(&request.resource_names) {
+                    continue;
+                }
+                names = Some(request.resource_names);
+            }
+            changed = contexts.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            () = tx.closed() => return,
+        }
+        let x509_context = contexts.borrow_and_update().clone();
+        let (Some(names), Some(x509_context)) = (&names, x509_context) else {
+            continue;
+        };
+        version += 1;
+
+        #[cfg(feature = "tracing")]
+        debug!(name: "sds_server", version, ?names, "sending SDS secrets");
+
+        let response = DiscoveryResponse {
+            version_info: version.to_string(),
+            resources: names
+                .iter()
+                .filter_map(|name| secret(&x509_context, name))
+                .map(|secret| Any {
+                    type_url: SECRET_TYPE_URL.into(),
+                    value: secret.encode_to_vec(),
+                })
+                .collect(),
+            type_url: SECRET_TYPE_URL.into(),
+            nonce: version.to_string(),
+        };
+        if tx.send(Ok(response)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The secret named `name` in `x509_context`, if any.
+fn secret(x509_context: &X509Context, name: &str) -> Option<Secret> {
+    let default = x509_context.default_svid()?;
+    let bundles = x509_context.bundle_set();
+    if name == "default" {
+        return Some(tls_certificate(name, default));
+    }
+    if name == "ROOTCA" {
+        let bundle = bundles.get_bundle(default.spiffe_id().trust_domain())?;
+        return Some(validation_context(name, bundle));
+    }
+    let id = SpiffeId::new(name).ok()?;
+    if id.path().is_empty() {
+        return Some(validation_context(
+            name,
+            bundles.get_bundle(id.trust_domain())?,
+        ));
+    }
+    let svid = x509_context
+        .svids()
+        .iter()
+        .find(|svid| *svid.spiffe_id() == id)?;
+    Some(tls_certificate(name, svid))
+}
+
+fn tls_certificate(name: &str, svid: &X509Svid) -> Secret {
+    Secret {
+        name: name.into(),
+        tls_certificate: Some(TlsCertificate {
+            certificate_chain: Some(inline(cert_chain_pem(svid))),
+            private_key: Some(inline(private_key_pem(svid))),
+        }),
+        validation_context: None,
+    }
+}
+
+fn validation_context(name: &str, bundle: &X509Bundle) -> Secret {
+    Secret {
+        name: name.into(),
+        tls_certificate: None,
+        validation_context: Some(CertificateValidationContext {
+            trusted_ca: Some(inline(bundle_pem(bundle))),
+        }),
+    }
+}
+
+const fn inline(pem: String) -> DataSource {
+    DataSource {
+        specifier: Some(Specifier::InlineString(pem)),
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::{bind_private, secret};
+    use crate::{
+        SourceError, bundle_pem, cert_chain_pem, private_key_pem,
+        sds_proto::{DataSource, data_source::Specifier},
+        test_certs::{Ca, DAY, context},
+    };
+
+    fn inline_string(source: Option<DataSource>) -> Option<String> {
+        match source?.specifier? {
+            Specifier::InlineString(pem) => Some(pem),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn serves_secrets_named_like_spire() -> Result<(), SourceError> {
+        let (ca, federated) = (Ca::root("root")?, Ca::root("federated")?);
+        let default = ca.svid("spiffe://example.org/default", DAY)?;
+        let web = ca.svid("spiffe://example.org/web", DAY)?;
+        let bundle = ca.bundle("example.org", false)?;
+        let federated_bundle = federated.bundle("federated.org", false)?;
+        let x509_context = context(
+            vec![default.clone(), web.clone()],
+            vec![bundle.clone(), federated_bundle.clone()],
+        );
+        let tls = |name: &str| {
+            let secret = secret(&x509_context, name)?;
+            assert_eq!(secret.name, name);
+            let tls = secret.tls_certificate?;
+            Some((
+                inline_string(tls.certificate_chain)?,
+                inline_string(tls.private_key)?,
+            ))
+        };
+        let trusted_ca = |name: &str| {
+            let secret = secret(&x509_context, name)?;
+            assert_eq!(secret.name, name);
+            inline_string(secret.validation_context?.trusted_ca)
+        };
+
+        assert_eq!(
+            tls("default"),
+            Some((cert_chain_pem(&default), private_key_pem(&default)))
+        );
+        assert_eq!(
+            tls("spiffe://example.org/web"),
+            Some((cert_chain_pem(&web), private_key_pem(&web)))
+        );
+        assert_eq!(trusted_ca("ROOTCA"), Some(bundle_pem(&bundle)));
+        assert_eq!(
+            trusted_ca("spiffe://federated.org"),
+            Some(bundle_pem(&federated_bundle))
+        );
+        assert_eq!(secret(&x509_context, "spiffe://example.org/missing"), None);
+        assert_eq!(secret(&x509_context, "spiffe://missing.org"), None);
+        assert_eq!(secret(&x509_context, "unknown"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sockets_are_private() -> Result<(), SourceError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sds.sock");
+        let _listener = bind_private(&path)?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        Ok(())
+    }
+}
@@ -0,0 +1,399 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use prost::Message;
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    Request, Streaming, client::Grpc, codegen::http::uri::PathAndQuery, transport::Channel,
+};
+use tonic_prost::ProstCodec;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::{
+    SourceError,
+    grpc::channel_for_endpoint,
+    private_key::pkcs8_private_key,
+    sds_proto::{
+        self as proto, Any, DiscoveryRequest, DiscoveryResponse, SECRET_TYPE_URL, STREAM_SECRETS,
+        Secret, data_source::Specifier,
+    },
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] streaming an X509-SVID and trust bundle from an Envoy
+/// Secret Discovery Service (SDS) server, such as the SPIRE agent's SDS API.
+///
+/// The source subscribes to a `tls_certificate` secret, converted into the
+/// SVID, and a `validation_context` secret, converted into the bundle. An
+/// update is yielded once both have been received and again whenever either
+/// changes. Secrets must carry their PEM material inline; `filename` data
+/// sources are rejected. The private key may be PKCS#8, SEC1 or PKCS#1.
+///
+/// The bundle is associated with the trust domain named by the validation
+/// context secret if its name is a `spiffe://` trust domain ID, as SPIRE names
+/// federated bundles, and with the trust domain of the SVID otherwise.
+#[derive(Clone, Debug)]
+pub struct SdsSource {
+    target: Target,
+    node_id: String,
+    tls_certificate: String,
+    validation_context: String,
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Endpoint(String),
+    Channel(Channel),
+}
+
+impl SdsSource {
+    /// Connect to the SDS server at `endpoint`, either `unix:///path/to/socket`
+    /// or `tcp://IP:port`, and subscribe to the secrets SPIRE serves by
+    /// default: `default` for the SVID and `ROOTCA` for the bundle.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self::with_target(Target::Endpoint(endpoint.into()))
+    }
+
+    /// Stream secrets over an already-configured [`Channel`].
+    #[must_use]
+    pub fn from_channel(channel: Channel) -> Self {
+        Self::with_target(Target::Channel(channel))
+    }
+
+    fn with_target(target: Target) -> Self {
+        Self {
+            target,
+            node_id: String::new(),
+            tls_certificate: "default".into(),
+            validation_context: "ROOTCA".into(),
+        }
+    }
+
+    /// Subscribe to the `tls_certificate` secret named `name`.
+    #[must_use]
+    pub fn with_tls_certificate(mut self, name: impl Into<String>) -> Self {
+        self.tls_certificate = name.into();
+        self
+    }
+
+    /// Subscribe to the `validation_context` secret named `name`.
+    #[must_use]
+    pub fn with_validation_context(mut self, name: impl Into<String>) -> Self {
+        self.validation_context = name.into();
+        self
+    }
+
+    /// Identify as the Envoy node `id` in discovery requests. Empty by default.
+    #[must_use]
+    pub fn with_node_id(mut self, id: impl Into<String>) -> Self {
+        self.node_id = id.into();
+        self
+    }
+
+    async fn channel(&self) -> Result<Channel, SourceError> {
+        match &self.target {
+            Target::Channel(channel) => Ok(channel.clone()),
+            Target::Endpoint(endpoint) => channel_for_endpoint(endpoint, "SDS").await,
+        }
+    }
+
+    fn request(&self, version_info: String, response_nonce: String) -> DiscoveryRequest {
+        DiscoveryRequest {
+            version_info,
+            node: Some(proto::Node {
+                id: self.node_id.clone(),
+            }),
+            resource_names: vec![
+                self.tls_certificate.clone(),
+                self.validation_context.clone(),
+            ],
+            type_url: SECRET_TYPE_URL.into(),
+            response_nonce,
+        }
+    }
+
+    async fn watch(
+        self,
+        requests: mpsc::Sender<DiscoveryRequest>,
+        mut responses: Streaming<DiscoveryResponse>,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        let mut secrets = Secrets::default();
+        loop {
+            let response = tokio::select! {
+                response = responses.message() => response,
+                () = tx.closed() => return,
+            };
+            let response = match response {
+                Ok(Some(response)) => response,
+                Ok(None) => return,
+                Err(err) => {
+                    let _ = tx.send(Err(err.into())).await;
+                    return;
+                }
+            };
+
+            #[cfg(feature = "tracing")]
+            debug!(name: "sds_source", version = response.version_info, "received SDS secrets");
+
+            let update = secrets.apply(&self, &response.resources);
+            let ack = self.request(response.version_info, response.nonce);
+            if requests.send(ack).await.is_err() {
+                return;
+            }
+            let update = match update {
+                Ok(true) => secrets.x509_context(&self.validation_context),
+                Ok(false) => continue,
+                Err(err) => Err(err),
+            };
+            let failed = update.is_err();
+            if tx.send(update).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+impl SvidSource for SdsSource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let mut grpc = Grpc::new(self.channel().await?);
+            grpc.ready().await?;
+            let (requests, rx) = mpsc::channel(1);
+            requests
+                .send(self.request(String::new(), String::new()))
+                .await?;
+            let responses = grpc
+                .streaming(
+                    Request::new(ReceiverStream::new(rx)),
+                    PathAndQuery::from_static(STREAM_SECRETS),
+                    ProstCodec::default(),
+                )
+                .await?
+                .into_inner();
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(self.clone().watch(requests, responses, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+}
+
+/// The latest secrets received, as raw DER.
+#[derive(Default)]
+struct Secrets {
+    certificate: Option<(Vec<u8>, Vec<u8>)>,
+    authorities: Option<Vec<u8>>,
+}
+
+impl Secrets {
+    /// Store the subscribed secrets in `resources`, returning whether both are
+    /// now known and either changed.
+    fn apply(&mut self, source: &SdsSource, resources: &[Any]) -> Result<bool, SourceError> {
+        let mut changed = false;
+        for resource in resources {
+            if resource.type_url != SECRET_TYPE_URL {
+                continue;
+            }
+            let secret = Secret::decode(resource.value.as_slice())?;
+            if secret.name == source.tls_certificate {
+                let tls = secret
+                    .tls_certificate
+                    .ok_or("SDS secret is not a tls_certificate")?;
+                let certificate = Some((
+                    inline_der(tls.certificate_chain)?,
+                    inline_der(tls.private_key)?,
+                ));
+                changed |= certificate != self.certificate;
+                self.certificate = certificate;
+            } else if secret.name == source.validation_context {
+                let validation = secret
+                    .validation_context
+                    .ok_or("SDS secret is not a validation_context")?;
+                let authorities = Some(inline_der(validation.trusted_ca)?);
+                changed |= authorities != self.authorities;
+                self.authorities = authorities;
+            }
+        }
+        Ok(changed && self.certificate.is_some() && self.authorities.is_some())
+    }
+
+    fn x509_context(&self, validation_context: &str) -> Result<X509Context, SourceError> {
+        let (Some((chain, key)), Some(authorities)) = (&self.certificate, &self.authorities) else {
+            return Err("SDS tls_certificate or validation_context not received".into());
+        };
+        let svid = X509Svid::parse_from_der(chain, &pkcs8_private_key(key))?;
+        let trust_domain = match validation_context.strip_prefix("spiffe://") {
+            Some(_) => TrustDomain::new(validation_context)?,
+            None => svid.spiffe_id().trust_domain().clone(),
+        };
+        let mut bundles = X509BundleSet::new();
+        bundles.add_bundle(X509Bundle::parse_from_der(trust_domain, authorities)?);
+        Ok(X509Context::new(vec![svid], bundles))
+    }
+}
+
+/// The concatenated DER contents of the PEM blocks held inline by `source`.
+fn inline_der(source: Option<proto::DataSource>) -> Result<Vec<u8>, SourceError> {
+    let pem = match source.and_then(|source| source.specifier) {
+        Some(Specifier::InlineBytes(bytes)) => bytes,
+        Some(Specifier::InlineString(string)) => string.into_bytes(),
+        Some(Specifier::Filename(_)) => {
+            return Err("SDS filename data sources are unsupported".into());
+        }
+        None => return Err("SDS secret has no data source".into()),
+    };
+    Ok(pem::parse_many(pem)?
+        .iter()
+        .flat_map(|p| p.contents().to_vec())
+        .collect())
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use pem::Pem;
+    use prost::Message;
+    use spiffe::{TrustDomain, X509Bundle, X509Svid, cert::Certificate};
+
+    use super::{SdsSource, Secrets};
+    use crate::{
+        SourceError,
+        sds_proto::{
+            Any, CertificateValidationContext, DataSource, SECRET_TYPE_URL, Secret, TlsCertificate,
+            data_source::Specifier,
+        },
+        test_certs::{Ca, DAY},
+    };
+
+    fn inline(tag: &str, ders: &[&[u8]]) -> DataSource {
+        let pems: Vec<_> = ders.iter().map(|der| Pem::new(tag, der.to_vec())).collect();
+        DataSource {
+            specifier: Some(Specifier::InlineString(pem::encode_many(&pems))),
+        }
+    }
+
+    fn resource(secret: &Secret) -> Any {
+        Any {
+            type_url: SECRET_TYPE_URL.into(),
+            value: secret.encode_to_vec(),
+        }
+    }
+
+    fn tls_certificate(name: &str, svid: &X509Svid) -> Secret {
+        let chain: Vec<_> = svid.cert_chain().iter().map(Certificate::content).collect();
+        Secret {
+            name: name.into(),
+            tls_certificate: Some(TlsCertificate {
+                certificate_chain: Some(inline("CERTIFICATE", &chain)),
+                private_key: Some(inline("PRIVATE KEY", &[svid.private_key().content()])),
+            }),
+            validation_context: None,
+        }
+    }
+
+    fn validation_context(name: &str, bundle: &X509Bundle) -> Secret {
+        let authorities: Vec<_> = bundle
+            .authorities()
+            .iter()
+            .map(Certificate::content)
+            .collect();
+        Secret {
+            name: name.into(),
+            tls_certificate: None,
+            validation_context: Some(CertificateValidationContext {
+                trusted_ca: Some(inline("CERTIFICATE", &authorities)),
+            }),
+        }
+    }
+
+    #[test]
+    fn converts_secrets_once_both_are_received() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?.intermediate("intermediate")?;
+        let svid = ca.svid("spiffe://example.org/workload", DAY)?;
+        let bundle = ca.bundle("example.org", false)?;
+        let source = SdsSource::new("unix:///run/sds.sock");
+        let mut secrets = Secrets::default();
+
+        assert!(!secrets.apply(&source, &[resource(&tls_certificate("default", &svid))])?);
+        assert!(secrets.apply(&source, &[resource(&validation_context("ROOTCA", &bundle))])?);
+        let x509_context = secrets.x509_context("ROOTCA")?;
+        assert_eq!(x509_context.svids(), &vec![svid.clone()]);
+        assert_eq!(
+            x509_context
+                .bundle_set()
+                .get_bundle(&TrustDomain::new("example.org")?),
+            Some(&bundle)
+        );
+
+        // resent, unchanged secrets yield no update
+        assert!(!secrets.apply(&source, &[resource(&tls_certificate("default", &svid))])?);
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_unsubscribed_secrets() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let svid = ca.svid("spiffe://example.org/workload", DAY)?;
+        let bundle = ca.bundle("example.org", false)?;
+        let source = SdsSource::new("unix:///run/sds.sock").with_tls_certificate("web");
+        let mut secrets = Secrets::default();
+
+        let resources = [
+            resource(&tls_certificate("default", &svid)),
+            resource(&validation_context("ROOTCA", &bundle)),
+        ];
+        assert!(!secrets.apply(&source, &resources)?);
+        Ok(())
+    }
+
+    #[test]
+    fn names_bundles_by_spiffe_trust_domain_ids() -> Result<(), SourceError> {
+        let local = Ca::root("local")?;
+        let federated = Ca::root("federated")?;
+        let source = SdsSource::new("unix:///run/sds.sock")
+            .with_validation_context("spiffe://federated.org");
+        let mut secrets = Secrets::default();
+
+        let resources = [
+            resource(&tls_certificate(
+                "default",
+                &local.svid("spiffe://example.org/workload", DAY)?,
+            )),
+            resource(&validation_context(
+                "spiffe://federated.org",
+                &federated.bundle("federated.org", false)?,
+            )),
+        ];
+        assert!(secrets.apply(&source, &resources)?);
+        let x509_context = secrets.x509_context("spiffe://federated.org")?;
+        let federated_domain = TrustDomain::new("federated.org")?;
+        assert!(
+            x509_context
+                .bundle_set()
+                .get_bundle(&federated_domain)
+                .is_some()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_filename_data_sources() {
+        let source = SdsSource::new("unix:///run/sds.sock");
+        let secret = Secret {
+            name: "ROOTCA".into(),
+            tls_certificate: None,
+            validation_context: Some(CertificateValidationContext {
+                trusted_ca: Some(DataSource {
+                    specifier: Some(Specifier::Filename("/etc/ca.pem".into())),
+                }),
+            }),
+        };
+
+        assert!(
+            Secrets::default()
+                .apply(&source, &[resource(&secret)])
+                .is_err()
+        );
+    }
+}
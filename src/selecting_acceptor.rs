@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{io, sync::Arc};
+
+use rustls::{
+    ServerConfig,
+    server::{Acceptor, ClientHello},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{LazyConfigAcceptor, server::TlsStream};
+
+use crate::{ExpiringStream, HandshakeOptions, ServerConfigProvider};
+
+type Selector =
+    Box<dyn Fn(&ClientHello<'_>, &ServerConfigProvider) -> Arc<ServerConfig> + Send + Sync>;
+
+/// A TLS acceptor choosing the config of each handshake from its
+/// `ClientHello`, e.g. by SNI or ALPN, with a callback.
+///
+/// The callback receives the `ClientHello` and the acceptor's provider, and
+/// may return a config of another provider it captured, to serve several
+/// SPIFFE identities from one listener:
+///
+/// ```rust
+/// # async fn example(listener: tokio::net::TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+/// use rustls_spiffe::{SelectingAcceptor, ServerConfigProvider, SpiffeServerConfigStream};
+///
+/// let public = ServerConfigProvider::start(SpiffeServerConfigStream::builder(vec![
+///     "example.org".try_into()?,
+/// ]))
+/// .await?;
+/// let internal = ServerConfigProvider::start(SpiffeServerConfigStream::builder(vec![
+///     "internal.example.org".try_into()?,
+/// ]))
+/// .await?;
+/// let acceptor = SelectingAcceptor::new(public, move |hello, public| {
+///     if hello.server_name() == Some("internal.example.org") {
+///         internal.get_config()
+///     } else {
+///         public.get_config()
+///     }
+/// });
+/// loop {
+///     let (tcp, _) = listener.accept().await?;
+///     let Ok(tls) = acceptor.accept(tcp).await else {
+///         continue;
+///     };
+///     // serve `tls`...
+/// }
+/// # }
+/// ```
+pub struct SelectingAcceptor {
+    provider: Arc<ServerConfigProvider>,
+    select: Selector,
+    handshake: HandshakeOptions,
+}
+
+impl SelectingAcceptor {
+    /// Accept with the configs `select` returns for each `ClientHello`,
+    /// given `provider`.
+    #[must_use]
+    pub fn new(
+        provider: Arc<ServerConfigProvider>,
+        select: impl Fn(&ClientHello<'_>, &ServerConfigProvider) -> Arc<ServerConfig>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        Self {
+            provider,
+            select: Box::new(select),
+            handshake: HandshakeOptions::new(),
+        }
+    }
+
+    /// Run handshakes and treat accepted connections according to `options`.
+    #[must_use]
+    pub const fn with_handshake_options(mut self, options: HandshakeOptions) -> Self {
+        self.handshake = options;
+        self
+    }
+
+    /// Read the client hello from `io` and complete the handshake with the
+    /// selected config.
+    ///
+    /// # Errors
+    /// The handshake fails or times out.
+    pub async fn accept<IO>(&self, io: IO) -> io::Result<ExpiringStream<TlsStream<IO>>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let tls = self
+            .handshake
+            .run(async {
+                let start = LazyConfigAcceptor::new(Acceptor::default(), io).await?;
+                let config = (self.select)(&start.client_hello(), &self.provider);
+                start.into_stream(config).await
+            })
+            .await?;
+        Ok(ExpiringStream::accepted(
+            tls,
+            self.handshake.enforces_peer_expiry(),
+        ))
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::sync::Arc;
+
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    ServerConfig, ServerConnection, SignatureScheme,
+    client::{
+        ResolvesClientCert, WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    server::{ResolvesServerCert, WebPkiClientVerifier},
+    sign::SingleCertAndKey,
+};
+use spiffe::{X509BundleSet, X509Svid};
+
+use crate::{SpiffeConfigError, certified_key::certified_key};
+
+/// Handshake rounds after which a self-test gives up.
+const MAX_ROUNDS: usize = 16;
+
+/// Handshake in memory with `resolver` serving the certificates of a freshly
+/// built server config, verified against the bundle of the trust domain of
+/// `svid`.
+pub fn server(
+    resolver: Arc<dyn ResolvesServerCert>,
+    svid: &X509Svid,
+    bundles: &X509BundleSet,
+    provider: &Arc<CryptoProvider>,
+) -> Result<(), SpiffeConfigError> {
+    let roots = own_roots(svid, bundles);
+    let server = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(WebPkiClientVerifier::no_client_auth())
+        .with_cert_resolver(resolver);
+    let client = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(AnyNameVerifier::new(roots, provider)?)
+        .with_no_client_auth();
+    handshake(client, server)
+}
+
+/// Handshake in memory with `resolver` presenting the certificates of a
+/// freshly built client config, verified against the bundle of the trust
+/// domain of `svid`.
+pub fn client(
+    resolver: Arc<dyn ResolvesClientCert>,
+    svid: &X509Svid,
+    bundles: &X509BundleSet,
+    provider: &Arc<CryptoProvider>,
+) -> Result<(), SpiffeConfigError> {
+    let roots = own_roots(svid, bundles);
+    let verifier = WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone())
+        .build()
+        .map_err(|err| failed(&err))?;
+    let server = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_cert_resolver(Arc::new(SingleCertAndKey::from(certified_key(
+            svid, None, provider,
+        )?)));
+    let client = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(AnyNameVerifier::new(roots, provider)?)
+        .with_client_cert_resolver(resolver);
+    handshake(client, server)
+}
+
+fn own_roots(svid: &X509Svid, bundles: &X509BundleSet) -> Arc<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    if let Some(bundle) = bundles.get_bundle(svid.spiffe_id().trust_domain()) {
+        roots.add_parsable_certificates(
+            bundle
+                .authorities()
+                .iter()
+                .map(|authority| CertificateDer::from(authority.content())),
+        );
+    }
+    Arc::new(roots)
+}
+
+fn handshake(client: ClientConfig, server: ServerConfig) -> Result<(), SpiffeConfigError> {
+    let name = ServerName::try_from("self-test.invalid").map_err(|err| failed(&err))?;
+    let mut client =
+        ClientConnection::new(Arc::new(client), name).map_err(SpiffeConfigError::SelfTest)?;
+    let mut server =
+        ServerConnection::new(Arc::new(server)).map_err(SpiffeConfigError::SelfTest)?;
+    let mut buf = Vec::new();
+    for _ in 0..MAX_ROUNDS {
+        if !client.is_handshaking() && !server.is_handshaking() {
+            return Ok(());
+        }
+        while client.wants_write() {
+            buf.clear();
+            client.write_tls(&mut buf)?;
+            server.read_tls(&mut buf.as_slice())?;
+        }
+        server
+            .process_new_packets()
+            .map_err(SpiffeConfigError::SelfTest)?;
+        while server.wants_write() {
+            buf.clear();
+            server.write_tls(&mut buf)?;
+            client.read_tls(&mut buf.as_slice())?;
+        }
+        client
+            .process_new_packets()
+            .map_err(SpiffeConfigError::SelfTest)?;
+    }
+    Err(failed(&"handshake did not complete"))
+}
+
+fn failed(err: &impl ToString) -> SpiffeConfigError {
+    SpiffeConfigError::SelfTest(rustls::Error::General(err.to_string()))
+}
+
+/// Verifies server certificates against roots for any server name, as SVIDs
+/// carry no DNS names.
+#[derive(Debug)]
+struct AnyNameVerifier(Arc<WebPkiServerVerifier>);
+
+impl AnyNameVerifier {
+    fn new(
+        roots: Arc<RootCertStore>,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Arc<Self>, SpiffeConfigError> {
+        let verifier = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+            .build()
+            .map_err(|err| failed(&err))?;
+        Ok(Arc::new(Self(verifier)))
+    }
+}
+
+impl ServerCertVerifier for AnyNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            verified => verified,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use spiffe::{TrustDomain, X509Svid};
+
+    use crate::{
+        ServerConfigProvider, SourceError, SpiffeServerConfigStream,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    #[tokio::test]
+    async fn broken_chains_keep_the_previous_config() -> Result<(), SourceError> {
+        let root = Ca::root("root")?;
+        let intermediate = root.intermediate("intermediate")?;
+        let bundle = root.bundle("example.org", false)?;
+        let valid = context(
+            vec![intermediate.svid("spiffe://example.org/server", DAY)?],
+            vec![bundle.clone()],
+        );
+        // the SVID without the intermediate that issued it
+        let full = intermediate.svid("spiffe://example.org/server", DAY)?;
+        let broken = X509Svid::parse_from_der(full.leaf().content(), full.private_key().content())?;
+        let broken = context(vec![broken], vec![bundle]);
+
+        let (source, tx) = ChannelSource::new(valid.clone());
+        tx.send(Ok(valid)).await?;
+        let builder = SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source)
+            .with_self_test(true);
+        let health = builder.health_updates();
+        let provider = ServerConfigProvider::start(builder).await?;
+        let config = provider.get_config();
+
+        tx.send(Ok(broken)).await?;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while provider.stream_healthy() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await?;
+        let failed = health
+            .borrow()
+            .recent_errors
+            .iter()
+            .any(|record| record.error.contains("self-test handshake failed"));
+        assert!(failed);
+        assert!(Arc::ptr_eq(&config, &provider.get_config()));
+        Ok(())
+    }
+}
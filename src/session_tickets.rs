@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{sync::Arc, time::Duration};
+
+use arc_swap::ArcSwapOption;
+
+#[cfg(feature = "aws-lc-rs")]
+use rustls::crypto::aws_lc_rs::Ticketer;
+#[cfg(all(feature = "ring", not(feature = "aws-lc-rs")))]
+use rustls::crypto::ring::Ticketer;
+use rustls::{TicketRotator, crypto::GetRandomFailed, server::ProducesTickets};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+/// A ticketer whose tickets are accepted for at most `lifetime`, with fresh
+/// keys every half of it.
+pub fn rotating(lifetime: Duration) -> Result<Arc<dyn ProducesTickets>, rustls::Error> {
+    let half = u32::try_from(lifetime.as_secs() / 2).unwrap_or(u32::MAX);
+    Ok(Arc::new(TicketRotator::new(half.max(1), generate)?))
+}
+
+/// A ticketer whose keys are replaced on every SVID rotation, so tickets
+/// issued under one SVID are not accepted once it has rotated.
+#[derive(Debug)]
+pub struct SvidBound {
+    lifetime: Option<Duration>,
+    keys: ArcSwapOption<Keys>,
+}
+
+#[derive(Debug)]
+struct Keys(Arc<dyn ProducesTickets>);
+
+impl SvidBound {
+    /// Generate the first keys, rotated every half of `lifetime` as with
+    /// [`rotating`] if set.
+    pub fn new(lifetime: Option<Duration>) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            lifetime,
+            keys: ArcSwapOption::from_pointee(Keys(keys(lifetime)?)),
+        })
+    }
+
+    /// Replace the keys, or stop issuing and accepting tickets if new ones
+    /// cannot be generated.
+    pub fn rotate(&self) {
+        let keys = match keys(self.lifetime) {
+            Ok(keys) => Some(Arc::new(Keys(keys))),
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                warn!(name: "session_tickets", error = %err, "failed to rotate ticket keys, disabling tickets");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = err;
+
+                None
+            }
+        };
+        self.keys.store(keys);
+    }
+}
+
+impl ProducesTickets for SvidBound {
+    fn enabled(&self) -> bool {
+        self.keys.load_full().is_some_and(|keys| keys.0.enabled())
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.keys.load_full().map_or(0, |keys| keys.0.lifetime())
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.keys.load_full()?.0.encrypt(plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.keys.load_full()?.0.decrypt(cipher)
+    }
+}
+
+fn keys(lifetime: Option<Duration>) -> Result<Arc<dyn ProducesTickets>, rustls::Error> {
+    lifetime.map_or_else(Ticketer::new, rotating)
+}
+
+fn generate() -> Result<Box<dyn ProducesTickets>, GetRandomFailed> {
+    let ticketer = Ticketer::new().map_err(|_| GetRandomFailed)?;
+    Ok(Box::new(Shared(ticketer)))
+}
+
+/// A ticketer of the crypto provider, boxed for [`TicketRotator`].
+#[derive(Debug)]
+struct Shared(Arc<dyn ProducesTickets>);
+
+impl ProducesTickets for Shared {
+    fn enabled(&self) -> bool {
+        self.0.enabled()
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.0.lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.0.encrypt(plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.0.decrypt(cipher)
+    }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use tokio::sync::watch;
+
+/// Handle for shutting down running config streams.
+///
+/// Obtained from `SpiffeClientConfigStreamBuilder::shutdown_handle` or
+/// `SpiffeServerConfigStreamBuilder::shutdown_handle`. Shutting down applies to
+/// every stream built by that builder: each stream closes its SVID source
+/// connection, stopping the background tasks feeding it, drops its last
+/// X509 context and any update held back, publishes `None` on
+/// `context_updates` and `identity_updates`, and then ends. Building a stream
+/// afterwards never completes.
+///
+/// The task of a `RotatingServerConfig` or `RotatingClientConfig` exits once
+/// its stream ends. The provider's own task, which would otherwise keep
+/// rebuilding the ended stream, stays parked in the build until its runtime
+/// shuts down, and keeps serving the last config it stored; drop the provider
+/// to release that config.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    shutdown: watch::Sender<bool>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            shutdown: watch::Sender::new(false),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Shut down every stream built by the builder.
+    ///
+    /// A `ServerConfigProvider` or `ClientConfigProvider` never gives up on
+    /// its builder: it rebuilds an ended stream and retries failed builds
+    /// forever. Its task therefore stays parked in the build that never
+    /// completes rather than exiting; drop the provider to release its last
+    /// config.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Returns whether [`shutdown`](Self::shutdown) was called.
+    #[must_use]
+    pub fn is_shut_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{collections::HashMap, sync::Arc};
+
+use rustls::{
+    crypto::CryptoProvider,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use spiffe::{SpiffeId, X509Context, X509Svid};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::certified_key::{OcspResponder, certified_key, dns_names, staple};
+
+/// A [`ResolvesServerCert`] serving every SVID of a Workload API update,
+/// choosing one per handshake from the `ClientHello` SNI.
+///
+/// Server names configured explicitly take precedence over DNS SANs. When
+/// several SVIDs carry the same DNS SAN, the first one in the update wins.
+/// Handshakes without SNI, or with an unknown server name, get the default SVID.
+#[derive(Debug)]
+pub struct SniSvidResolver {
+    default: Arc<CertifiedKey>,
+    by_server_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl SniSvidResolver {
+    pub fn new(
+        x509_context: &X509Context,
+        default: &X509Svid,
+        sni_svids: &HashMap<String, SpiffeId>,
+        complete_chains: bool,
+        provider: &CryptoProvider,
+        ocsp: Option<&OcspResponder>,
+    ) -> Result<Self, rustls::Error> {
+        let bundles = complete_chains.then(|| x509_context.bundle_set());
+        let mut by_spiffe_id = HashMap::with_capacity(x509_context.svids().len());
+        let mut by_server_name = HashMap::new();
+        for svid in x509_context.svids() {
+            let key = Arc::new(staple(certified_key(svid, bundles, provider)?, svid, ocsp));
+            for name in dns_names(svid) {
+                by_server_name
+                    .entry(name.to_ascii_lowercase())
+                    .or_insert_with(|| key.clone());
+            }
+            by_spiffe_id.insert(svid.spiffe_id(), key);
+        }
+        for (server_name, spiffe_id) in sni_svids {
+            let Some(key) = by_spiffe_id.get(spiffe_id) else {
+                #[cfg(feature = "tracing")]
+                warn!(server_name, spiffe_id = %spiffe_id, "no SVID for configured server name");
+
+                continue;
+            };
+            by_server_name.insert(server_name.to_ascii_lowercase(), key.clone());
+        }
+        let default = match by_spiffe_id.get(default.spiffe_id()) {
+            Some(key) => key.clone(),
+            None => Arc::new(staple(
+                certified_key(default, bundles, provider)?,
+                default,
+                ocsp,
+            )),
+        };
+        Ok(Self {
+            default,
+            by_server_name,
+        })
+    }
+}
+
+impl ResolvesServerCert for SniSvidResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| self.by_server_name.get(&name.to_ascii_lowercase()))
+            .unwrap_or(&self.default);
+        Some(key.clone())
+    }
+}
@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! The subset of the SPIRE server `svid.v1` and `bundle.v1` API messages
+//! used to mint SVIDs.
+
+pub const MINT_X509_SVID: &str = "/spire.api.server.svid.v1.SVID/MintX509SVID";
+pub const GET_BUNDLE: &str = "/spire.api.server.bundle.v1.Bundle/GetBundle";
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct MintX509SvidRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub csr: Vec<u8>,
+    #[prost(int32, tag = "2")]
+    pub ttl: i32,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct MintX509SvidResponse {
+    #[prost(message, optional, tag = "1")]
+    pub svid: Option<X509Svid>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct X509Svid {
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub cert_chain: Vec<Vec<u8>>,
+    #[prost(int64, tag = "3")]
+    pub expires_at: i64,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct GetBundleRequest {}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Bundle {
+    #[prost(string, tag = "1")]
+    pub trust_domain: String,
+    #[prost(message, repeated, tag = "2")]
+    pub x509_authorities: Vec<X509Certificate>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct X509Certificate {
+    #[prost(bytes = "vec", tag = "1")]
+    pub asn1: Vec<u8>,
+}
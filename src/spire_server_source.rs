@@ -0,0 +1,353 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "aws-lc-rs")]
+use aws_lc_rs as crypto;
+#[cfg(not(feature = "aws-lc-rs"))]
+use ring as crypto;
+use spiffe::{SpiffeId, TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, client::Grpc, codegen::http::uri::PathAndQuery, transport::Channel};
+use tonic_prost::ProstCodec;
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+use crypto::{
+    rand::SystemRandom,
+    signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair},
+};
+
+use crate::{
+    SourceError, WorkloadIdentity,
+    der::{
+        OID_EC_PUBLIC_KEY, OID_ECDSA_WITH_SHA256, OID_PRIME256V1, OID_SUBJECT_ALT_NAME, bit_string,
+        der,
+    },
+    grpc::channel_for_endpoint,
+    spire_proto::{
+        Bundle, GET_BUNDLE, GetBundleRequest, MINT_X509_SVID, MintX509SvidRequest,
+        MintX509SvidResponse,
+    },
+    svid_source::{ConnectFuture, FetchFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] minting X509-SVIDs from the SPIRE server `svid.v1` API,
+/// for workloads running without a SPIRE agent, such as off-cluster
+/// controllers.
+///
+/// Each mint generates a new P-256 key and sends a CSR for the configured
+/// SPIFFE ID; the trust bundle is fetched from the `bundle.v1` API alongside.
+/// The SVID is minted again once half of its lifetime has passed, and every
+/// mint is yielded as a new update.
+///
+/// Minting requires admin authorization on the SPIRE server: connect through
+/// the server's local admin socket, or pass a [`Channel`] authenticated with an
+/// admin SVID to [`from_channel`](Self::from_channel). Join tokens only
+/// attest agents and cannot be used to mint.
+#[derive(Clone, Debug)]
+pub struct SpireServerSource {
+    target: Target,
+    spiffe_id: SpiffeId,
+    ttl: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Endpoint(String),
+    Channel(Channel),
+}
+
+impl SpireServerSource {
+    /// Mint SVIDs for `spiffe_id` from the SPIRE server API at `endpoint`,
+    /// either `unix:///path/to/socket` or `tcp://IP:port`, e.g.
+    /// `unix:///tmp/spire-server/private/api.sock`.
+    pub fn new(endpoint: impl Into<String>, spiffe_id: SpiffeId) -> Self {
+        Self {
+            target: Target::Endpoint(endpoint.into()),
+            spiffe_id,
+            ttl: None,
+        }
+    }
+
+    /// Mint SVIDs for `spiffe_id` over an already-configured [`Channel`].
+    #[must_use]
+    pub const fn from_channel(channel: Channel, spiffe_id: SpiffeId) -> Self {
+        Self {
+            target: Target::Channel(channel),
+            spiffe_id,
+            ttl: None,
+        }
+    }
+
+    /// Request SVIDs valid for `ttl` instead of the server default.
+    ///
+    /// The server may issue shorter-lived SVIDs.
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    async fn channel(&self) -> Result<Channel, SourceError> {
+        match &self.target {
+            Target::Channel(channel) => Ok(channel.clone()),
+            Target::Endpoint(endpoint) => channel_for_endpoint(endpoint, "SPIRE server").await,
+        }
+    }
+
+    /// Mint an SVID and fetch the bundle, returning the update and the instant
+    /// the SVID should be renewed.
+    async fn mint(&self) -> Result<(X509Context, SystemTime), SourceError> {
+        let mut grpc = Grpc::new(self.channel().await?);
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)?;
+        #[cfg(feature = "aws-lc-rs")]
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, key.as_ref())?;
+        #[cfg(not(feature = "aws-lc-rs"))]
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, key.as_ref(), &rng)?;
+        let request = MintX509SvidRequest {
+            csr: csr(&key_pair, &rng, &self.spiffe_id)?,
+            ttl: self
+                .ttl
+                .map_or(0, |ttl| i32::try_from(ttl.as_secs()).unwrap_or(i32::MAX)),
+        };
+
+        grpc.ready().await?;
+        let response: MintX509SvidResponse = grpc
+            .unary(
+                Request::new(request),
+                PathAndQuery::from_static(MINT_X509_SVID),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+        let minted = response.svid.ok_or("SPIRE server returned no SVID")?;
+        let svid = X509Svid::parse_from_der(&minted.cert_chain.concat(), key.as_ref())?;
+
+        grpc.ready().await?;
+        let bundle: Bundle = grpc
+            .unary(
+                Request::new(GetBundleRequest {}),
+                PathAndQuery::from_static(GET_BUNDLE),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+        let authorities: Vec<u8> = bundle
+            .x509_authorities
+            .into_iter()
+            .flat_map(|authority| authority.asn1)
+            .collect();
+        let mut bundles = X509BundleSet::new();
+        bundles.add_bundle(X509Bundle::parse_from_der(
+            TrustDomain::new(&bundle.trust_domain)?,
+            &authorities,
+        )?);
+
+        let renew_at = renewal(&svid, minted.expires_at, SystemTime::now());
+
+        #[cfg(feature = "tracing")]
+        debug!(name: "spire_server_source", spiffe_id = %self.spiffe_id, "minted X509-SVID");
+
+        Ok((X509Context::new(vec![svid], bundles), renew_at))
+    }
+
+    async fn renew(
+        self,
+        mut renew_at: SystemTime,
+        tx: mpsc::Sender<Result<X509Context, SourceError>>,
+    ) {
+        loop {
+            let wait = renew_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            tokio::select! {
+                () = tokio::time::sleep(wait) => {}
+                () = tx.closed() => return,
+            }
+            match self.mint().await {
+                Ok((x509_context, next)) => {
+                    renew_at = next;
+                    if tx.send(Ok(x509_context)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "spire_server_source", error = %err, "failed to renew X509-SVID");
+
+                    let _ = tx.send(Err(err)).await;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl SvidSource for SpireServerSource {
+    fn fetch(&self) -> FetchFuture<'_> {
+        Box::pin(async move { Ok(self.mint().await?.0) })
+    }
+
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let (x509_context, renew_at) = self.mint().await?;
+            let (tx, rx) = mpsc::channel(1);
+            tx.send(Ok(x509_context)).await?;
+            tokio::spawn(self.clone().renew(renew_at, tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+}
+
+const OID_EXTENSION_REQUEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+
+/// The instant to renew `svid`, minted at `now`: once half of its remaining
+/// lifetime has passed, but at least [`MIN_RENEWAL_DELAY`] after `now`.
+///
+/// The lifetime ends at the leaf's `notAfter`; the `expires_at` reported by
+/// the server, in seconds since the Unix epoch, is only used if the leaf
+/// does not parse.
+fn renewal(svid: &X509Svid, expires_at: i64, now: SystemTime) -> SystemTime {
+    let expiry = WorkloadIdentity::from_svid(svid).map_or_else(
+        || {
+            u64::try_from(expires_at).map_or(now, |secs| {
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+            })
+        },
+        |identity| identity.not_after,
+    );
+    now + (expiry.duration_since(now).unwrap_or_default() / 2).max(MIN_RENEWAL_DELAY)
+}
+
+/// The shortest wait before minting again, so an SVID reported as expired
+/// does not make the source mint in a tight loop.
+const MIN_RENEWAL_DELAY: Duration = Duration::from_secs(1);
+
+/// A DER PKCS#10 certificate signing request for `spiffe_id`, carried as
+/// the only URI subject alternative name, signed with `key_pair`.
+fn csr(
+    key_pair: &EcdsaKeyPair,
+    rng: &SystemRandom,
+    spiffe_id: &SpiffeId,
+) -> Result<Vec<u8>, SourceError> {
+    let algorithm = der(
+        0x30,
+        &[der(0x06, OID_EC_PUBLIC_KEY), der(0x06, OID_PRIME256V1)].concat(),
+    );
+    let public_key = der(
+        0x30,
+        &[algorithm, bit_string(key_pair.public_key().as_ref())].concat(),
+    );
+    let san = der(0x30, &der(0x86, spiffe_id.to_string().as_bytes()));
+    let extension = der(
+        0x30,
+        &[der(0x06, OID_SUBJECT_ALT_NAME), der(0x04, &san)].concat(),
+    );
+    let extension_request = der(
+        0x30,
+        &[
+            der(0x06, OID_EXTENSION_REQUEST),
+            der(0x31, &der(0x30, &extension)),
+        ]
+        .concat(),
+    );
+    let info = der(
+        0x30,
+        &[
+            der(0x02, &[0]),
+            der(0x30, &[]),
+            public_key,
+            der(0xa0, &extension_request),
+        ]
+        .concat(),
+    );
+    let signature = key_pair.sign(rng, &info)?;
+    Ok(der(
+        0x30,
+        &[
+            info,
+            der(0x30, &der(0x06, OID_ECDSA_WITH_SHA256)),
+            bit_string(signature.as_ref()),
+        ]
+        .concat(),
+    ))
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        signature::{
+            ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, UnparsedPublicKey,
+        },
+    };
+    use spiffe::SpiffeId;
+    use x509_parser::{
+        certification_request::X509CertificationRequest,
+        extensions::{GeneralName, ParsedExtension},
+        prelude::FromDer,
+    };
+
+    use super::{MIN_RENEWAL_DELAY, csr, renewal};
+    use crate::{
+        SourceError, WorkloadIdentity,
+        test_certs::{Ca, DAY},
+    };
+
+    #[test]
+    fn csrs_request_the_spiffe_id_and_are_signed() -> Result<(), SourceError> {
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)?;
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, key.as_ref())?;
+        let spiffe_id = SpiffeId::new("spiffe://example.org/controller")?;
+
+        let der = csr(&key_pair, &rng, &spiffe_id)?;
+        let (rest, request) = X509CertificationRequest::from_der(&der)?;
+        assert!(rest.is_empty());
+        let sans: Vec<_> = request
+            .requested_extensions()
+            .ok_or("no extension request")?
+            .filter_map(|extension| match extension {
+                ParsedExtension::SubjectAlternativeName(san) => Some(&san.general_names),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(sans, [&GeneralName::URI("spiffe://example.org/controller")]);
+
+        let info = &request.certification_request_info;
+        UnparsedPublicKey::new(
+            &ECDSA_P256_SHA256_ASN1,
+            info.subject_pki.subject_public_key.data.as_ref(),
+        )
+        .verify(info.raw, request.signature_value.data.as_ref())?;
+        Ok(())
+    }
+
+    #[test]
+    fn renewals_follow_the_leaf_expiry() -> Result<(), SourceError> {
+        let svid = Ca::root("root")?.svid("spiffe://example.org/controller", DAY)?;
+        let not_after = WorkloadIdentity::from_svid(&svid)
+            .ok_or("unparsable SVID")?
+            .not_after;
+        let now = SystemTime::now();
+
+        // an unset expires_at does not make the SVID expire at once
+        let renew_at = renewal(&svid, 0, now);
+        assert_eq!(renew_at, now + not_after.duration_since(now)? / 2);
+        assert!(renew_at.duration_since(now)? > Duration::from_hours(11));
+
+        let after_expiry = not_after + Duration::from_secs(1);
+        assert_eq!(
+            renewal(&svid, 0, after_expiry),
+            after_expiry + MIN_RENEWAL_DELAY
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,557 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    ops::ControlFlow,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    DigitallySignedStruct, SignatureScheme,
+    client::{
+        ResolvesClientCert,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use rustls_config_stream::{ClientConfigStreamError, ServerConfigStreamError};
+use tokio::{
+    sync::watch,
+    time::{Instant, Sleep},
+};
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+use crate::{SourceError, SpiffeConfigError};
+
+/// The outcome of the most recent updates of the config streams built by one
+/// builder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StreamHealth {
+    /// The number of configs built so far, incremented by one for each
+    /// yielded config, to correlate logs and caches with the config in use
+    /// and detect missed rotations.
+    pub generation: u64,
+    /// When a config was last built successfully.
+    pub last_good: Option<SystemTime>,
+    /// The error of the most recent update, with its sources, if it failed.
+    pub last_error: Option<String>,
+    /// When the most recent update failed.
+    pub last_error_at: Option<SystemTime>,
+    /// Whether the stream exceeded its maximum staleness since the last
+    /// config was built.
+    pub stale: bool,
+    /// The error, with its sources, on which the SVID source gave up since
+    /// the last config was built.
+    pub fatal: Option<String>,
+    /// The most recent errors of the streams and their builds, oldest first,
+    /// kept across successful updates.
+    pub recent_errors: VecDeque<ErrorRecord>,
+    /// The state summarizing the fields above.
+    pub state: HealthState,
+}
+
+/// The health of the config streams built by one builder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HealthState {
+    /// No config has been built yet.
+    #[default]
+    Starting,
+    /// The most recent update was built into a config.
+    Healthy,
+    /// Updates have failed, or the stream became stale, since the last
+    /// config was built, which stays in use.
+    Degraded {
+        /// When the first failure since the last config occurred.
+        since: SystemTime,
+    },
+    /// The SVID source gave up; see [`StreamHealth::fatal`].
+    Failed,
+}
+
+/// An error of a config stream or of building one, as kept in
+/// [`StreamHealth::recent_errors`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ErrorRecord {
+    /// When the error occurred.
+    pub at: SystemTime,
+    /// The error, with its sources.
+    pub error: String,
+}
+
+/// The number of errors kept in [`StreamHealth::recent_errors`].
+const ERROR_HISTORY: usize = 8;
+
+impl StreamHealth {
+    /// Returns how long ago a config was last built successfully, or `None`
+    /// if none has been.
+    #[must_use]
+    pub fn staleness(&self) -> Option<Duration> {
+        self.last_good
+            .map(|last_good| last_good.elapsed().unwrap_or_default())
+    }
+
+    /// Returns whether the most recent update failed.
+    #[must_use]
+    pub const fn is_failing(&self) -> bool {
+        self.last_error.is_some()
+    }
+
+    fn update_state(&mut self) {
+        self.state = if self.fatal.is_some() {
+            HealthState::Failed
+        } else if self.last_good.is_none() {
+            HealthState::Starting
+        } else if self.last_error.is_some() || self.stale {
+            let since = match self.state {
+                HealthState::Degraded { since } => since,
+                _ => self.last_error_at.unwrap_or_else(SystemTime::now),
+            };
+            HealthState::Degraded { since }
+        } else {
+            HealthState::Healthy
+        };
+    }
+
+    fn record_error(&mut self, error: String) {
+        let at = SystemTime::now();
+        if self.recent_errors.len() == ERROR_HISTORY {
+            self.recent_errors.pop_front();
+        }
+        self.recent_errors.push_back(ErrorRecord {
+            at,
+            error: error.clone(),
+        });
+        self.last_error = Some(error);
+        self.last_error_at = Some(at);
+    }
+}
+
+/// The channels publishing the [`StreamHealth`] of a builder's streams, its
+/// [`HealthState`] on transitions only, and the error the SVID source gave up
+/// on.
+#[derive(Clone, Debug)]
+pub struct HealthChannels {
+    health: watch::Sender<StreamHealth>,
+    state: watch::Sender<HealthState>,
+    fatal: watch::Sender<Option<Arc<SpiffeConfigError>>>,
+}
+
+impl HealthChannels {
+    pub fn new() -> Self {
+        Self {
+            health: watch::Sender::new(StreamHealth::default()),
+            state: watch::Sender::new(HealthState::default()),
+            fatal: watch::Sender::new(None),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<StreamHealth> {
+        self.health.subscribe()
+    }
+
+    pub fn subscribe_state(&self) -> watch::Receiver<HealthState> {
+        self.state.subscribe()
+    }
+
+    pub fn subscribe_fatal(&self) -> watch::Receiver<Option<Arc<SpiffeConfigError>>> {
+        self.fatal.subscribe()
+    }
+
+    fn modify(&self, modify: impl FnOnce(&mut StreamHealth)) {
+        self.health.send_modify(|health| {
+            modify(health);
+            health.update_state();
+        });
+        let state = self.health.borrow().state;
+        self.state.send_if_modified(|current| {
+            if *current == state {
+                return false;
+            }
+
+            #[cfg(feature = "tracing")]
+            debug!(from = ?*current, to = ?state, "config stream health changed");
+
+            *current = state;
+            true
+        });
+    }
+}
+
+/// The errors of the client and server config streams.
+pub trait ConfigStreamError: Error {
+    /// The error followed by its sources, including the error it wraps.
+    fn chain(&self) -> String;
+}
+
+impl ConfigStreamError for ClientConfigStreamError {
+    fn chain(&self) -> String {
+        match self {
+            Self::StreamError(err) | Self::StreamBuilderError(err) => {
+                format!("{self}: {}", error_chain(&**err))
+            }
+            err => error_chain(err),
+        }
+    }
+}
+
+impl ConfigStreamError for ServerConfigStreamError {
+    fn chain(&self) -> String {
+        match self {
+            Self::StreamError(err) | Self::StreamBuilderError(err) => {
+                format!("{self}: {}", error_chain(&**err))
+            }
+            err => error_chain(err),
+        }
+    }
+}
+
+/// What a config stream does once no config was built within its maximum
+/// staleness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StalePolicy {
+    /// Yield a [`SpiffeConfigError::Stale`] error, so the provider marks
+    /// itself unhealthy and rebuilds the stream while it keeps serving the
+    /// last config.
+    #[default]
+    Unhealthy,
+    /// First yield a config that rejects every handshake, then the error of
+    /// [`Unhealthy`](Self::Unhealthy), so the provider stops vending the stale
+    /// credentials.
+    Reject,
+}
+
+/// Publishes the outcome of each stream update as a [`StreamHealth`] and
+/// tracks the maximum staleness of the stream.
+#[derive(Debug)]
+pub struct HealthReporter {
+    health: HealthChannels,
+    keep_last_good: bool,
+    staleness: Option<Staleness>,
+}
+
+#[derive(Debug)]
+struct Staleness {
+    max_age: Duration,
+    policy: StalePolicy,
+    deadline: Pin<Box<Sleep>>,
+    rejected: bool,
+}
+
+/// The action due on a stale stream.
+pub enum Stale {
+    /// Yield a config rejecting every handshake.
+    Reject,
+    /// Yield this error.
+    Fail(SpiffeConfigError),
+}
+
+impl HealthReporter {
+    pub fn new(
+        health: HealthChannels,
+        keep_last_good: bool,
+        max_staleness: Option<(Duration, StalePolicy)>,
+    ) -> Self {
+        Self {
+            health,
+            keep_last_good,
+            staleness: max_staleness.map(|(max_age, policy)| Staleness {
+                max_age,
+                policy,
+                deadline: Box::pin(tokio::time::sleep(max_age)),
+                rejected: false,
+            }),
+        }
+    }
+
+    /// Record the outcome of `item`; continue polling instead of yielding it
+    /// if it is an error to keep from consumers.
+    pub fn observe<T, E: ConfigStreamError>(
+        &mut self,
+        item: Option<Result<T, E>>,
+    ) -> ControlFlow<Option<Result<T, E>>> {
+        match &item {
+            Some(Ok(_)) => {
+                if let Some(staleness) = &mut self.staleness {
+                    staleness.rejected = false;
+                    staleness
+                        .deadline
+                        .as_mut()
+                        .reset(Instant::now() + staleness.max_age);
+                }
+                self.health.modify(|health| {
+                    health.generation += 1;
+                    health.last_good = Some(SystemTime::now());
+                    health.last_error = None;
+                    health.last_error_at = None;
+                    health.stale = false;
+                    health.fatal = None;
+
+                    #[cfg(feature = "tracing")]
+                    debug!(generation = health.generation, "yielding config");
+                });
+                self.health
+                    .fatal
+                    .send_if_modified(|fatal| fatal.take().is_some());
+            }
+            Some(Err(err)) => {
+                let message = err.chain();
+                self.health.modify(|health| health.record_error(message));
+                if self.keep_last_good {
+                    return ControlFlow::Continue(());
+                }
+            }
+            None => {}
+        }
+        ControlFlow::Break(item)
+    }
+
+    /// Record `err` of the SVID source stream as fatal if the source gave up.
+    pub fn report_fatal(&self, err: &SourceError) {
+        report_fatal(&self.health, err);
+    }
+
+    /// Polls for the maximum staleness to pass without a config being built.
+    ///
+    /// The deadline restarts after each failure, so a stream that keeps
+    /// being polled fails once per maximum staleness.
+    pub fn poll_stale(&mut self, cx: &mut Context<'_>) -> Poll<Stale> {
+        let Some(staleness) = &mut self.staleness else {
+            return Poll::Pending;
+        };
+        if staleness.deadline.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        self.health.modify(|health| health.stale = true);
+        if staleness.policy == StalePolicy::Reject && !staleness.rejected {
+            staleness.rejected = true;
+
+            #[cfg(feature = "tracing")]
+            warn!("config stream is stale, rejecting handshakes");
+
+            return Poll::Ready(Stale::Reject);
+        }
+        let age = self
+            .health
+            .health
+            .borrow()
+            .staleness()
+            .unwrap_or(staleness.max_age);
+        staleness
+            .deadline
+            .as_mut()
+            .reset(Instant::now() + staleness.max_age);
+
+        #[cfg(feature = "tracing")]
+        warn!(age_secs = age.as_secs(), "config stream is stale");
+
+        Poll::Ready(Stale::Fail(SpiffeConfigError::Stale(age)))
+    }
+}
+
+/// Record `err` as fatal on `health` if the SVID source gave up on it, i.e.
+/// it is [`SpiffeConfigError::RetriesExhausted`].
+///
+/// The typed error keeps the last error of the source as its chain of
+/// messages, since the source's error cannot be cloned.
+fn report_fatal(health: &HealthChannels, err: &SourceError) {
+    if let Some(SpiffeConfigError::RetriesExhausted(retries, last)) = err.downcast_ref() {
+        let message = error_chain(&**err);
+
+        #[cfg(feature = "tracing")]
+        warn!(error = message, "SVID source gave up");
+
+        health.modify(|health| health.fatal = Some(message));
+        let fatal = SpiffeConfigError::RetriesExhausted(*retries, error_chain(&**last).into());
+        health.fatal.send_replace(Some(Arc::new(fatal)));
+    }
+}
+
+/// Record `err` of building a stream on `health`, as fatal if the SVID
+/// source gave up on it.
+pub fn report_build_error(health: &HealthChannels, err: &SourceError) {
+    let message = format!("could not build stream: {}", error_chain(&**err));
+    health.modify(|health| health.record_error(message));
+    report_fatal(health, err);
+}
+
+/// `err` followed by its sources.
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+/// A certificate resolver and server certificate verifier that fail every
+/// handshake, vended by stale streams under [`StalePolicy::Reject`].
+///
+/// Holds the crypto provider of the stream, whose signature schemes the
+/// verifier advertises.
+#[derive(Debug)]
+pub struct Rejecting(pub Arc<CryptoProvider>);
+
+impl Rejecting {
+    fn error() -> rustls::Error {
+        rustls::Error::General("SPIFFE config is stale".into())
+    }
+}
+
+impl ResolvesServerCert for Rejecting {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        None
+    }
+}
+
+impl ResolvesClientCert for Rejecting {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        None
+    }
+
+    fn has_certs(&self) -> bool {
+        false
+    }
+}
+
+impl ServerCertVerifier for Rejecting {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Err(Self::error())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Err(Self::error())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Err(Self::error())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls_config_stream::ServerConfigStreamError;
+
+    use super::{HealthChannels, HealthReporter, HealthState};
+    use crate::{SourceError, SpiffeConfigError};
+
+    fn observe(reporter: &mut HealthReporter, item: Result<(), &str>) {
+        let item = item.map_err(|err| ServerConfigStreamError::StreamError(err.into()));
+        let _ = reporter.observe(Some(item));
+    }
+
+    #[test]
+    fn degrades_on_failures_and_recovers() {
+        let channels = HealthChannels::new();
+        let health = channels.subscribe();
+        let mut reporter = HealthReporter::new(channels, false, None);
+        assert_eq!(health.borrow().state, HealthState::Starting);
+
+        observe(&mut reporter, Ok(()));
+        assert_eq!(health.borrow().state, HealthState::Healthy);
+        assert_eq!(health.borrow().generation, 1);
+
+        observe(&mut reporter, Err("agent unavailable"));
+        let state = health.borrow().state;
+        let since = match state {
+            HealthState::Degraded { since } => since,
+            state => panic!("expected a degraded stream, got {state:?}"),
+        };
+        observe(&mut reporter, Err("agent still unavailable"));
+        assert_eq!(health.borrow().state, HealthState::Degraded { since });
+        assert_eq!(health.borrow().recent_errors.len(), 2);
+
+        observe(&mut reporter, Ok(()));
+        assert_eq!(health.borrow().state, HealthState::Healthy);
+        assert_eq!(health.borrow().last_error, None);
+        assert_eq!(health.borrow().generation, 2);
+    }
+
+    #[test]
+    fn fails_when_retries_are_exhausted() {
+        let channels = HealthChannels::new();
+        let (health, fatal) = (channels.subscribe(), channels.subscribe_fatal());
+        let mut reporter = HealthReporter::new(channels, false, None);
+        observe(&mut reporter, Ok(()));
+
+        let err: SourceError =
+            SpiffeConfigError::RetriesExhausted(3, "agent unavailable".into()).into();
+        reporter.report_fatal(&err);
+        assert_eq!(health.borrow().state, HealthState::Failed);
+        assert!(matches!(
+            fatal.borrow().as_deref(),
+            Some(SpiffeConfigError::RetriesExhausted(3, _))
+        ));
+
+        // other errors are not fatal
+        reporter.report_fatal(&"agent unavailable".into());
+        observe(&mut reporter, Ok(()));
+        assert_eq!(health.borrow().state, HealthState::Healthy);
+        assert!(fatal.borrow().is_none());
+    }
+
+    #[test]
+    fn the_state_watch_fires_on_transitions_only() -> Result<(), SourceError> {
+        let channels = HealthChannels::new();
+        let mut state = channels.subscribe_state();
+        let mut reporter = HealthReporter::new(channels, false, None);
+
+        observe(&mut reporter, Ok(()));
+        assert!(state.has_changed()?);
+        assert_eq!(*state.borrow_and_update(), HealthState::Healthy);
+        observe(&mut reporter, Ok(()));
+        assert!(!state.has_changed()?);
+
+        observe(&mut reporter, Err("agent unavailable"));
+        assert!(state.has_changed()?);
+        assert!(matches!(
+            *state.borrow_and_update(),
+            HealthState::Degraded { .. }
+        ));
+        observe(&mut reporter, Err("agent still unavailable"));
+        assert!(!state.has_changed()?);
+
+        observe(&mut reporter, Ok(()));
+        assert_eq!(*state.borrow_and_update(), HealthState::Healthy);
+        Ok(())
+    }
+}
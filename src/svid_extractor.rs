@@ -1,6 +1,8 @@
+use std::time::{Duration, SystemTime};
+
 use rustls::pki_types::CertificateDer;
 use spiffe::SpiffeId;
-use tokio::net::TcpStream;
+use tokio::{net::TcpStream, time::Sleep};
 use tokio_rustls::server::TlsStream;
 use x509_parser::prelude::GeneralName;
 
@@ -27,3 +29,80 @@ pub fn extract_spiffe_id(leaf: Option<&CertificateDer<'_>>) -> Option<SpiffeId>
     })?;
     SpiffeId::try_from(uri).ok()
 }
+
+/// The outcome of client authentication on a [`TlsStream`], e.g. of a server
+/// built with `with_anonymous_clients`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PeerIdentity {
+    /// The peer presented an X509-SVID with this SPIFFE ID.
+    Authenticated(SpiffeId),
+    /// The peer presented a trusted certificate carrying no SPIFFE ID.
+    Unidentified,
+    /// The peer presented no certificate.
+    Anonymous,
+}
+
+/// Extract the [`PeerIdentity`] of the peer of a [`TlsStream`]
+#[inline]
+#[must_use]
+pub fn peer_identity(stream: &TlsStream<TcpStream>) -> PeerIdentity {
+    let leaf = extract_leaf_cert(stream);
+    if leaf.is_none() {
+        return PeerIdentity::Anonymous;
+    }
+    extract_spiffe_id(leaf).map_or(PeerIdentity::Unidentified, PeerIdentity::Authenticated)
+}
+
+/// Extract the `notAfter` of a [`CertificateDer`]
+#[inline]
+#[must_use]
+pub fn extract_not_after(leaf: Option<&CertificateDer<'_>>) -> Option<SystemTime> {
+    let (_, cert) = x509_parser::parse_x509_certificate(leaf?).ok()?;
+    let secs = u64::try_from(cert.validity().not_after.timestamp()).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// A timer elapsing when the peer's X509-SVID presented on a [`TlsStream`]
+/// expires, or `None` if the peer presented no certificate with a parseable
+/// `notAfter`.
+///
+/// Use it to close long-lived connections whose peer certificate is no
+/// longer valid, as mTLS only checks validity during the handshake. The
+/// timer does not borrow the stream, so it can be raced against serving it:
+///
+/// ```rust,no_run
+/// # async fn example(mut tls: tokio_rustls::server::TlsStream<tokio::net::TcpStream>) {
+/// use tokio::io::AsyncWriteExt;
+///
+/// let expiry = rustls_spiffe::peer_svid_expiry_timer(&tls);
+/// let expired = async {
+///     match expiry {
+///         Some(timer) => timer.await,
+///         // anonymous clients have no SVID to expire
+///         None => std::future::pending().await,
+///     }
+/// };
+/// tokio::select! {
+///     () = async { /* serve the connection */ } => {}
+///     () = expired => {
+///         // sends close_notify
+///         let _ = tls.shutdown().await;
+///     }
+/// }
+/// # }
+/// ```
+#[must_use]
+pub fn peer_svid_expiry_timer(stream: &TlsStream<TcpStream>) -> Option<Sleep> {
+    expiry_timer(stream.get_ref().1.peer_certificates())
+}
+
+/// A timer elapsing when the leaf of `certs` expires, or `None` if there is
+/// no leaf with a parseable `notAfter`.
+pub fn expiry_timer(certs: Option<&[CertificateDer<'_>]>) -> Option<Sleep> {
+    let not_after = extract_not_after(certs?.first())?;
+    let remaining = not_after
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    Some(tokio::time::sleep(remaining))
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use spiffe::{SpiffeId, X509Context, X509Svid};
+
+use crate::SpiffeConfigError;
+
+/// Selects which X509-SVID from a Workload API update is used for the
+/// workload's certificate.
+#[derive(Clone, Debug, Default)]
+pub enum SvidSelector {
+    /// The default SVID, i.e. the first one returned by the Workload API.
+    #[default]
+    Default,
+    /// The SVID with this SPIFFE ID.
+    SpiffeId(SpiffeId),
+}
+
+impl SvidSelector {
+    pub fn select<'a>(&self, x509_context: &'a X509Context) -> Option<&'a X509Svid> {
+        match self {
+            Self::Default => x509_context.default_svid(),
+            Self::SpiffeId(id) => x509_context
+                .svids()
+                .iter()
+                .find(|svid| svid.spiffe_id() == id),
+        }
+    }
+
+    /// The error to report when [`select`](Self::select) finds no SVID, or
+    /// `None` if the generic missing-certified-key error applies.
+    pub fn not_found(&self) -> Option<SpiffeConfigError> {
+        match self {
+            Self::Default => None,
+            Self::SpiffeId(id) => Some(SpiffeConfigError::SvidNotFound(id.clone())),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use rustls_config_stream::{ServerConfigStreamBuilder, ServerConfigStreamError};
+    use spiffe::{SpiffeId, TrustDomain};
+    use tokio_stream::StreamExt;
+
+    use super::SvidSelector;
+    use crate::{
+        SourceError, SpiffeConfigError, SpiffeServerConfigStream,
+        test_certs::{Ca, ChannelSource, DAY, context},
+    };
+
+    #[test]
+    fn selects_by_spiffe_id() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let update = context(
+            vec![
+                ca.svid("spiffe://example.org/default", DAY)?,
+                ca.svid("spiffe://example.org/other", DAY)?,
+            ],
+            vec![],
+        );
+        let other = SpiffeId::new("spiffe://example.org/other")?;
+
+        let selected = SvidSelector::SpiffeId(other.clone())
+            .select(&update)
+            .ok_or("no SVID selected")?;
+        assert_eq!(selected.spiffe_id(), &other);
+        let selected = SvidSelector::Default
+            .select(&update)
+            .ok_or("no SVID selected")?;
+        assert_eq!(selected.spiffe_id().path(), "/default");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_fail_when_the_selected_svid_disappears() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let bundle = ca.bundle("example.org", false)?;
+        let both = context(
+            vec![
+                ca.svid("spiffe://example.org/default", DAY)?,
+                ca.svid("spiffe://example.org/other", DAY)?,
+            ],
+            vec![bundle.clone()],
+        );
+        let default_only = context(
+            vec![ca.svid("spiffe://example.org/default", DAY)?],
+            vec![bundle],
+        );
+        let other = SpiffeId::new("spiffe://example.org/other")?;
+        let (source, tx) = ChannelSource::new(both.clone());
+        let mut builder = SpiffeServerConfigStream::builder(vec![TrustDomain::new("example.org")?])
+            .with_svid_source(source)
+            .with_svid_id(other.clone());
+        let mut stream = builder.build().await?;
+
+        tx.send(Ok(both)).await?;
+        stream.next().await.ok_or("stream ended")??;
+        tx.send(Ok(default_only)).await?;
+        let Some(Err(ServerConfigStreamError::StreamError(err))) = stream.next().await else {
+            return Err("built a config without the selected SVID".into());
+        };
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(SpiffeConfigError::SvidNotFound(id)) if *id == other
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use spiffe::{
+    WorkloadApiClient, X509Context,
+    endpoint::{get_default_socket_path, validate_socket_path},
+    error::GrpcClientError,
+};
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+use tonic::transport::Endpoint;
+#[cfg(feature = "tracing")]
+use tracing::{debug, info, warn};
+
+use crate::{SourceError, SpiffeConfigError};
+
+/// A stream of X509-SVID and bundle updates from an [`SvidSource`].
+pub type X509ContextStream =
+    Pin<Box<dyn Stream<Item = Result<X509Context, SourceError>> + Send + Sync + 'static>>;
+
+/// Future returned by [`SvidSource::connect`].
+pub type ConnectFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<X509ContextStream, SourceError>> + Send + 'a>>;
+
+/// Future returned by [`SvidSource::fetch`].
+pub type FetchFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<X509Context, SourceError>> + Send + 'a>>;
+
+/// A backend supplying X509-SVIDs and trust bundles to the config streams.
+///
+/// The streams call [`connect`](Self::connect) each time they are built, so
+/// after a stream fails the provider reconnects by building again. All
+/// config-building logic is shared between sources; the default is
+/// [`WorkloadApiSource`].
+pub trait SvidSource: Send + Sync + 'static {
+    /// Open a new stream of updates.
+    fn connect(&self) -> ConnectFuture<'_>;
+
+    /// Fetch the current SVIDs and bundles once.
+    ///
+    /// The default implementation takes the first update from a new stream.
+    fn fetch(&self) -> FetchFuture<'_> {
+        Box::pin(async move {
+            self.connect()
+                .await?
+                .next()
+                .await
+                .ok_or("SVID source stream ended without an update")?
+        })
+    }
+
+    /// Describes where the source reads updates from, named in errors such
+    /// as [`SpiffeConfigError::StartupTimeout`], or `None` if it has nowhere
+    /// to read from.
+    ///
+    /// Defaults to naming a custom source; sources wrapping another should
+    /// return the description of the wrapped one.
+    fn describe(&self) -> Option<String> {
+        Some("a custom SVID source".into())
+    }
+}
+
+/// An [`SvidSource`] streaming from the SPIFFE Workload API.
+#[derive(Clone, Debug, Default)]
+pub struct WorkloadApiSource {
+    client: Option<WorkloadApiClient>,
+    endpoints: Vec<String>,
+}
+
+impl WorkloadApiSource {
+    /// Connect to the Workload API socket named by `SPIFFE_ENDPOINT_SOCKET`,
+    /// or else to the first socket found by [`detect_socket_path`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stream from an already-configured [`WorkloadApiClient`].
+    #[must_use]
+    pub const fn from_client(client: WorkloadApiClient) -> Self {
+        Self {
+            client: Some(client),
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Connect to the Workload API at `endpoint`, e.g.
+    /// `unix:///run/spire/sockets/agent.sock` or `tcp://127.0.0.1:8081`,
+    /// instead of the socket named by `SPIFFE_ENDPOINT_SOCKET`.
+    ///
+    /// Endpoints with other schemes fail to connect with an error naming the
+    /// endpoint.
+    pub fn from_endpoint(endpoint: impl Into<String>) -> Self {
+        Self::from_endpoints([endpoint])
+    }
+
+    /// Connect to the first reachable Workload API of `endpoints`, tried in
+    /// order.
+    ///
+    /// Every connect starts again from the first endpoint, so when the stream
+    /// from a standby agent fails, the providers' rebuild returns to the
+    /// primary once it is back. With no endpoints this behaves like
+    /// [`new`](Self::new).
+    pub fn from_endpoints(endpoints: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            client: None,
+            endpoints: endpoints.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl WorkloadApiSource {
+    fn endpoints(&self) -> Result<Vec<String>, SourceError> {
+        if self.endpoints.is_empty() {
+            let endpoint = get_default_socket_path()
+                .or_else(detect_socket_path)
+                .ok_or(GrpcClientError::MissingEndpointSocketPath)?;
+            return Ok(vec![endpoint]);
+        }
+        Ok(self.endpoints.clone())
+    }
+
+    async fn fetch_from(&self, endpoint: Option<&str>) -> Result<X509Context, SourceError> {
+        let mut client = match endpoint {
+            Some(endpoint) => client_for_endpoint(endpoint).await?,
+            None => self.client.clone().ok_or("no Workload API client")?,
+        };
+        Ok(client.fetch_x509_context().await?)
+    }
+
+    async fn connect_to(&self, endpoint: Option<&str>) -> Result<X509ContextStream, SourceError> {
+        let mut client = match endpoint {
+            Some(endpoint) => client_for_endpoint(endpoint).await?,
+            None => self.client.clone().ok_or("no Workload API client")?,
+        };
+        let stream = client.stream_x509_contexts().await?;
+        Ok(Box::pin(stream.map(|update| update.map_err(SourceError::from))) as X509ContextStream)
+    }
+}
+
+/// Workload API socket locations used by SPIRE and common Kubernetes
+/// deployments, in the order [`detect_socket_path`] probes them.
+pub const WELL_KNOWN_SOCKET_PATHS: &[&str] = &[
+    "/run/spire/sockets/agent.sock",
+    "/run/spire/agent-sockets/spire-agent.sock",
+    "/spiffe-workload-api/spire-agent.sock",
+    "/run/secrets/workload-spiffe-uds/socket",
+    "/var/run/secrets/workload-spiffe-uds/socket",
+    "/tmp/spire-agent/public/api.sock",
+];
+
+/// Returns the first of [`WELL_KNOWN_SOCKET_PATHS`] that is a Unix domain
+/// socket, as a `unix://` endpoint.
+///
+/// [`WorkloadApiSource`] falls back to this when no endpoint is configured
+/// and `SPIFFE_ENDPOINT_SOCKET` is unset.
+#[must_use]
+pub fn detect_socket_path() -> Option<String> {
+    for path in WELL_KNOWN_SOCKET_PATHS {
+        let is_socket = std::fs::metadata(path).is_ok_and(|metadata| is_socket(&metadata));
+
+        #[cfg(feature = "tracing")]
+        debug!(name: "workload_api_source", path, is_socket, "probed workload api socket");
+
+        if is_socket {
+            #[cfg(feature = "tracing")]
+            info!(name: "workload_api_source", path, "detected workload api socket");
+
+            return Some(format!("unix://{path}"));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    warn!(name: "workload_api_source", "no workload api socket found at well-known paths");
+
+    None
+}
+
+#[cfg(unix)]
+fn is_socket(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    metadata.file_type().is_socket()
+}
+
+#[cfg(not(unix))]
+const fn is_socket(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Connect a [`WorkloadApiClient`] to `endpoint`.
+///
+/// `unix:` endpoints use the Unix domain socket at their path. `tcp://IP:port`
+/// endpoints are dialed over plaintext HTTP/2: the workload has no SVID to
+/// authenticate with yet, so the Workload API is never served over TLS.
+async fn client_for_endpoint(endpoint: &str) -> Result<WorkloadApiClient, SourceError> {
+    validate_socket_path(endpoint)
+        .map_err(|e| format!("unsupported Workload API endpoint {endpoint:?}: {e}"))?;
+    if let Some(address) = endpoint.strip_prefix("tcp://") {
+        let channel = Endpoint::from_shared(format!("http://{}", address.trim_end_matches('/')))?
+            .connect()
+            .await?;
+        return Ok(WorkloadApiClient::new(channel)?);
+    }
+    Ok(WorkloadApiClient::new_from_path(endpoint).await?)
+}
+
+impl SvidSource for WorkloadApiSource {
+    fn fetch(&self) -> FetchFuture<'_> {
+        Box::pin(async move {
+            if self.client.is_some() {
+                return self.fetch_from(None).await;
+            }
+            let mut last_err = None;
+            for endpoint in self.endpoints()? {
+                match self.fetch_from(Some(&endpoint)).await {
+                    Ok(ctx) => return Ok(ctx),
+                    Err(err) => {
+                        #[cfg(feature = "tracing")]
+                        warn!(name: "workload_api_source", endpoint, error = %err, "failed to fetch from workload api endpoint");
+
+                        last_err = Some(err);
+                    }
+                }
+            }
+            Err(last_err.unwrap_or_else(|| "no Workload API endpoints".into()))
+        })
+    }
+
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            if self.client.is_some() {
+                return self.connect_to(None).await;
+            }
+            let mut last_err = None;
+            for endpoint in self.endpoints()? {
+                match self.connect_to(Some(&endpoint)).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => {
+                        #[cfg(feature = "tracing")]
+                        warn!(name: "workload_api_source", endpoint, error = %err, "failed to connect to workload api endpoint");
+
+                        last_err = Some(err);
+                    }
+                }
+            }
+            Err(last_err.unwrap_or_else(|| "no Workload API endpoints".into()))
+        })
+    }
+
+    fn describe(&self) -> Option<String> {
+        if self.client.is_some() {
+            return Some("the injected Workload API client".into());
+        }
+        let endpoints = self.endpoints().ok()?;
+        Some(format!(
+            "the SPIFFE Workload API at {}",
+            endpoints.join(", ")
+        ))
+    }
+}
+
+/// Wraps an [`SvidSource`] in another, e.g. one reconnecting it.
+pub type SourceLayer = Box<dyn Fn(Arc<dyn SvidSource>) -> Arc<dyn SvidSource> + Send + Sync>;
+
+/// The SVID source of a config stream builder: a base source and the layers
+/// wrapping it, in the order they were added.
+///
+/// Replacing the base keeps the layers, and the layered source is composed
+/// once when first used, so every stream shares its wrappers.
+pub struct LayeredSource {
+    base: Arc<dyn SvidSource>,
+    layers: Vec<SourceLayer>,
+    composed: OnceLock<Arc<dyn SvidSource>>,
+}
+
+impl LayeredSource {
+    pub fn new(base: impl SvidSource) -> Self {
+        Self {
+            base: Arc::new(base),
+            layers: Vec::new(),
+            composed: OnceLock::new(),
+        }
+    }
+
+    /// Replace the base source, keeping the layers.
+    pub fn set_base(&mut self, base: impl SvidSource) {
+        self.base = Arc::new(base);
+        self.composed = OnceLock::new();
+    }
+
+    /// Wrap the layers added so far in `layer`.
+    pub fn push(&mut self, layer: SourceLayer) {
+        self.layers.push(layer);
+        self.composed = OnceLock::new();
+    }
+
+    /// The base source wrapped in every layer.
+    pub fn get(&self) -> &Arc<dyn SvidSource> {
+        self.composed.get_or_init(|| {
+            self.layers
+                .iter()
+                .fold(self.base.clone(), |source, layer| layer(source))
+        })
+    }
+}
+
+/// Wait up to `timeout` for `connect` to open a stream and for its first
+/// update, returning a stream yielding that update and then the later ones.
+///
+/// Times out with [`SpiffeConfigError::StartupTimeout`].
+pub async fn connect_within(
+    connect: impl Future<Output = Result<X509ContextStream, SourceError>> + Send,
+    timeout: Duration,
+    svid_source: Option<String>,
+) -> Result<X509ContextStream, SourceError> {
+    let first_update = async {
+        let mut stream = connect.await?;
+        let first = stream
+            .next()
+            .await
+            .ok_or("SVID source stream ended without an update")?;
+        Ok(Box::pin(tokio_stream::once(first).chain(stream)) as X509ContextStream)
+    };
+    tokio::time::timeout(timeout, first_update)
+        .await
+        .unwrap_or_else(|_| {
+            #[cfg(feature = "tracing")]
+            warn!(name: "svid_source", ?timeout, ?svid_source, "no SVID update within the startup timeout");
+
+            Err(SpiffeConfigError::StartupTimeout {
+                timeout,
+                svid_source,
+            }.into())
+        })
+}
+
+/// Fetch the current update from `source` once, then stream later updates.
+///
+/// The returned stream yields the fetched update immediately while the
+/// stream is opened in the background; its first update is dropped if it
+/// matches the fetched one. A failure to open the stream is yielded as an
+/// error.
+pub async fn connect_with_initial_fetch(
+    source: Arc<dyn SvidSource>,
+) -> Result<X509ContextStream, SourceError> {
+    let initial = source.fetch().await?;
+    let (tx, rx) = mpsc::channel(1);
+    tx.send(Ok(initial.clone())).await?;
+    tokio::spawn(async move {
+        let mut stream = match source.connect().await {
+            Ok(stream) => stream,
+            Err(err) => {
+                let _ = tx.send(Err(err)).await;
+                return;
+            }
+        };
+        let mut initial = Some(initial);
+        loop {
+            let update = tokio::select! {
+                update = stream.next() => update,
+                () = tx.closed() => return,
+            };
+            let Some(update) = update else {
+                return;
+            };
+            let duplicate =
+                matches!((&update, initial.take()), (Ok(ctx), Some(initial)) if *ctx == initial);
+            if !duplicate && tx.send(update).await.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(Box::pin(ReceiverStream::new(rx)))
+}
@@ -0,0 +1,342 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+//! Certificates for unit tests: a CA issuing intermediates and X509-SVIDs,
+//! signed with P-256 keys generated per test, and a source streaming them.
+
+#[cfg(feature = "config-stream")]
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use aws_lc_rs::{
+    encoding::AsDer,
+    rand::{SecureRandom, SystemRandom},
+    signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair},
+};
+use spiffe::{TrustDomain, X509Bundle, X509BundleSet, X509Context, X509Svid};
+
+#[cfg(feature = "config-stream")]
+use tokio::sync::mpsc;
+#[cfg(feature = "config-stream")]
+use tokio_stream::wrappers::ReceiverStream;
+
+#[cfg(feature = "config-stream")]
+use crate::svid_source::{ConnectFuture, FetchFuture, SvidSource, X509ContextStream};
+use crate::{
+    SourceError,
+    der::{
+        OID_EC_PUBLIC_KEY, OID_ECDSA_WITH_SHA256, OID_PRIME256V1, OID_SUBJECT_ALT_NAME, bit_string,
+        der,
+    },
+};
+
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+
+/// A certificate authority: a self-signed root, or an intermediate below one.
+pub struct Ca {
+    key: EcdsaKeyPair,
+    name: String,
+    /// The certificates of this CA up to, but excluding, the root.
+    chain: Vec<Vec<u8>>,
+    root: Vec<u8>,
+}
+
+impl Ca {
+    /// A self-signed root CA named `name`.
+    pub fn root(name: &str) -> Result<Self, SourceError> {
+        Self::root_valid(name, valid_for(DAY))
+    }
+
+    /// A self-signed root CA named `name` that expired a day ago.
+    pub fn expired_root(name: &str) -> Result<Self, SourceError> {
+        let now = SystemTime::now();
+        Self::root_valid(name, (now - 2 * DAY, now - DAY))
+    }
+
+    fn root_valid(name: &str, validity: (SystemTime, SystemTime)) -> Result<Self, SourceError> {
+        let (key, _) = generate()?;
+        let cert = sign(&key, name, &key, name, &ca_extensions(), validity)?;
+        Ok(Self {
+            key,
+            name: name.into(),
+            chain: Vec::new(),
+            root: cert,
+        })
+    }
+
+    /// An intermediate CA named `name` issued by this CA.
+    pub fn intermediate(&self, name: &str) -> Result<Self, SourceError> {
+        let (key, _) = generate()?;
+        let cert = sign(
+            &key,
+            name,
+            &self.key,
+            &self.name,
+            &ca_extensions(),
+            valid_for(DAY),
+        )?;
+        Ok(Self {
+            key,
+            name: name.into(),
+            chain: [vec![cert], self.chain.clone()].concat(),
+            root: self.root.clone(),
+        })
+    }
+
+    /// An X509-SVID for `spiffe_id` issued by this CA, expiring after
+    /// `lifetime`, with the intermediates of this CA in its chain.
+    pub fn svid(&self, spiffe_id: &str, lifetime: Duration) -> Result<X509Svid, SourceError> {
+        self.svid_with_dns_names(spiffe_id, &[], lifetime)
+    }
+
+    /// An X509-SVID like [`svid`](Self::svid) that expired a day ago.
+    pub fn expired_svid(&self, spiffe_id: &str) -> Result<X509Svid, SourceError> {
+        let now = SystemTime::now();
+        self.svid_valid(spiffe_id, &[], (now - 2 * DAY, now - DAY))
+    }
+
+    /// An X509-SVID like [`svid`](Self::svid), also carrying `dns_names`.
+    pub fn svid_with_dns_names(
+        &self,
+        spiffe_id: &str,
+        dns_names: &[&str],
+        lifetime: Duration,
+    ) -> Result<X509Svid, SourceError> {
+        self.svid_valid(spiffe_id, dns_names, valid_for(lifetime))
+    }
+
+    fn svid_valid(
+        &self,
+        spiffe_id: &str,
+        dns_names: &[&str],
+        validity: (SystemTime, SystemTime),
+    ) -> Result<X509Svid, SourceError> {
+        let (key, pkcs8) = generate()?;
+        let mut san = der(0x86, spiffe_id.as_bytes());
+        for name in dns_names {
+            san.extend(der(0x82, name.as_bytes()));
+        }
+        let san = der(0x30, &san);
+        let extensions = [
+            extension(OID_BASIC_CONSTRAINTS, &der(0x30, &[])),
+            // digitalSignature
+            extension(OID_KEY_USAGE, &der(0x03, &[7, 0x80])),
+            extension(OID_SUBJECT_ALT_NAME, &san),
+        ];
+        let leaf = sign(&key, "svid", &self.key, &self.name, &extensions, validity)?;
+        let chain = [vec![leaf], self.chain.clone()].concat().concat();
+        Ok(X509Svid::parse_from_der(&chain, &pkcs8)?)
+    }
+
+    /// A bundle for `trust_domain` holding the root, and `intermediates`, of
+    /// this CA.
+    pub fn bundle(
+        &self,
+        trust_domain: &str,
+        intermediates: bool,
+    ) -> Result<X509Bundle, SourceError> {
+        let mut authorities = self.root.clone();
+        if intermediates {
+            authorities.extend(self.chain.concat());
+        }
+        Ok(X509Bundle::parse_from_der(
+            TrustDomain::new(trust_domain)?,
+            &authorities,
+        )?)
+    }
+}
+
+/// The private key of `svid` as a SEC1 `ECPrivateKey`, as OpenSSL writes
+/// `EC PRIVATE KEY` files.
+pub fn sec1_private_key(svid: &X509Svid) -> Result<Vec<u8>, SourceError> {
+    let key = EcdsaKeyPair::from_pkcs8(
+        &ECDSA_P256_SHA256_ASN1_SIGNING,
+        svid.private_key().content(),
+    )?;
+    Ok(key.private_key().as_der()?.as_ref().to_vec())
+}
+
+/// An update with `svids` and `bundles`.
+pub fn context(svids: Vec<X509Svid>, bundles: Vec<X509Bundle>) -> X509Context {
+    X509Context::new(svids, bundle_set(bundles))
+}
+
+/// A bundle set holding `bundles`.
+pub fn bundle_set(bundles: Vec<X509Bundle>) -> X509BundleSet {
+    let mut bundle_set = X509BundleSet::new();
+    for bundle in bundles {
+        bundle_set.add_bundle(bundle);
+    }
+    bundle_set
+}
+
+/// A source streaming the updates sent on a channel, whose fetch returns
+/// `fetched`. It can be connected once.
+#[cfg(feature = "config-stream")]
+pub struct ChannelSource {
+    updates: Mutex<Option<mpsc::Receiver<Result<X509Context, SourceError>>>>,
+    fetched: X509Context,
+}
+
+#[cfg(feature = "config-stream")]
+impl ChannelSource {
+    /// A source and the sender of its updates.
+    pub fn new(fetched: X509Context) -> (Self, mpsc::Sender<Result<X509Context, SourceError>>) {
+        let (tx, rx) = mpsc::channel(8);
+        let source = Self {
+            updates: Mutex::new(Some(rx)),
+            fetched,
+        };
+        (source, tx)
+    }
+}
+
+#[cfg(feature = "config-stream")]
+impl SvidSource for ChannelSource {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let updates = self
+                .updates
+                .lock()
+                .map_err(|_| "poisoned")?
+                .take()
+                .ok_or("connected twice")?;
+            Ok(Box::pin(ReceiverStream::new(updates)) as X509ContextStream)
+        })
+    }
+
+    fn fetch(&self) -> FetchFuture<'_> {
+        Box::pin(async move { Ok(self.fetched.clone()) })
+    }
+}
+
+pub const DAY: Duration = Duration::from_hours(24);
+
+fn valid_for(lifetime: Duration) -> (SystemTime, SystemTime) {
+    let now = SystemTime::now();
+    (now - Duration::from_mins(1), now + lifetime)
+}
+
+fn generate() -> Result<(EcdsaKeyPair, Vec<u8>), SourceError> {
+    let pkcs8 =
+        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())?;
+    Ok((key, pkcs8.as_ref().to_vec()))
+}
+
+fn ca_extensions() -> [Vec<u8>; 2] {
+    [
+        extension(OID_BASIC_CONSTRAINTS, &der(0x30, &der(0x01, &[0xff]))),
+        // keyCertSign and cRLSign
+        extension(OID_KEY_USAGE, &der(0x03, &[1, 0x06])),
+    ]
+}
+
+fn extension(oid: &[u8], value: &[u8]) -> Vec<u8> {
+    der(
+        0x30,
+        &[der(0x06, oid), der(0x01, &[0xff]), der(0x04, value)].concat(),
+    )
+}
+
+/// A DER certificate for the key of `subject`, signed by `issuer`.
+fn sign(
+    subject: &EcdsaKeyPair,
+    subject_name: &str,
+    issuer: &EcdsaKeyPair,
+    issuer_name: &str,
+    extensions: &[Vec<u8>],
+    (not_before, not_after): (SystemTime, SystemTime),
+) -> Result<Vec<u8>, SourceError> {
+    let algorithm = der(0x30, &der(0x06, OID_ECDSA_WITH_SHA256));
+    let public_key = der(
+        0x30,
+        &[
+            der(
+                0x30,
+                &[der(0x06, OID_EC_PUBLIC_KEY), der(0x06, OID_PRIME256V1)].concat(),
+            ),
+            bit_string(subject.public_key().as_ref()),
+        ]
+        .concat(),
+    );
+    let mut serial = [0; 8];
+    SystemRandom::new().fill(&mut serial)?;
+    // positive, and without a leading zero byte
+    serial[0] = (serial[0] & 0x7f) | 0x01;
+    let tbs = der(
+        0x30,
+        &[
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &serial),
+            algorithm.clone(),
+            name(issuer_name),
+            der(0x30, &[time(not_before)?, time(not_after)?].concat()),
+            name(subject_name),
+            public_key,
+            der(0xa3, &der(0x30, &extensions.concat())),
+        ]
+        .concat(),
+    );
+    let signature = issuer.sign(&SystemRandom::new(), &tbs)?;
+    Ok(der(
+        0x30,
+        &[tbs, algorithm, bit_string(signature.as_ref())].concat(),
+    ))
+}
+
+fn name(common_name: &str) -> Vec<u8> {
+    der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[
+                    der(0x06, OID_COMMON_NAME),
+                    der(0x0c, common_name.as_bytes()),
+                ]
+                .concat(),
+            ),
+        ),
+    )
+}
+
+/// The DER `GeneralizedTime` of `at`, to the second.
+fn time(at: SystemTime) -> Result<Vec<u8>, SourceError> {
+    let secs = at.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let days = i64::try_from(secs / 86_400)?;
+    let rem = secs % 86_400;
+    // civil_from_days of Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let formatted = format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+    Ok(der(0x18, formatted.as_bytes()))
+}
+
+#[test]
+fn issues_parseable_svids() -> Result<(), SourceError> {
+    let root = Ca::root("root")?;
+    let intermediate = root.intermediate("intermediate")?;
+    let svid = intermediate.svid("spiffe://example.org/workload", DAY)?;
+    assert_eq!(
+        svid.spiffe_id().to_string(),
+        "spiffe://example.org/workload"
+    );
+    assert_eq!(svid.cert_chain().len(), 2);
+    root.bundle("example.org", false)?;
+    Ok(())
+}
@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+#[cfg(feature = "axum")]
+use std::time::Duration;
+use std::{
+    future::poll_fn,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use rustls::pki_types::CertificateDer;
+use spiffe::SpiffeId;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tokio_stream::Stream;
+#[cfg(all(feature = "axum", feature = "tracing"))]
+use tracing::{debug, warn};
+
+use crate::{
+    ExpiringStream, HandshakeOptions, PeerIdentity, ServerConfigProvider, extract_spiffe_id,
+};
+
+/// How many handshakes may run at once unless configured otherwise.
+const DEFAULT_MAX_HANDSHAKES: usize = 1024;
+
+/// A stream of TLS connections accepted from a [`TcpListener`], each
+/// handshaking with the current config of a [`ServerConfigProvider`].
+///
+/// Handshakes run concurrently on spawned tasks, so a slow client does not
+/// hold up the others, and every connection picks up the SVID current when
+/// it was accepted. The peer of each connection is captured as a
+/// [`PeerInfo`].
+///
+/// Handshakes are aborted after 10 seconds, and at most 1024 run at once;
+/// while that many are in progress no further connections are taken from
+/// the listener, leaving them in its backlog. Both limits are configurable.
+///
+/// With the `tonic` feature, serve a gRPC server with
+/// `serve_with_incoming(incoming)`; failed handshakes are yielded as errors,
+/// which tonic logs and skips. With the `axum` feature, serve a router with
+/// `axum::serve(incoming, router)`. For `hyper_util`'s server builder,
+/// [`accept`](Self::accept) connections in a loop and serve each with
+/// `serve_connection(TokioIo::new(tls), service)`.
+///
+/// ```rust
+/// # async fn example(provider: std::sync::Arc<rustls_spiffe::ServerConfigProvider>) -> std::io::Result<()> {
+/// use std::time::Duration;
+///
+/// use rustls_spiffe::{HandshakeOptions, TlsIncoming};
+///
+/// let listener = tokio::net::TcpListener::bind("[::]:8443").await?;
+/// let incoming = TlsIncoming::new(listener, provider)
+///     .with_handshake_options(HandshakeOptions::new().with_timeout(Duration::from_secs(5)))
+///     .with_max_concurrent_handshakes(256);
+/// // tonic::transport::Server::builder()
+/// //     .add_service(GreeterServer::new(greeter))
+/// //     .serve_with_incoming(incoming)
+/// //     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TlsIncoming {
+    listener: TcpListener,
+    provider: Arc<ServerConfigProvider>,
+    handshake: HandshakeOptions,
+    max_handshakes: usize,
+    handshakes: JoinSet<io::Result<TlsConnection>>,
+}
+
+impl TlsIncoming {
+    /// Accept connections from `listener` with the configs of `provider`.
+    #[must_use]
+    pub fn new(listener: TcpListener, provider: Arc<ServerConfigProvider>) -> Self {
+        Self {
+            listener,
+            provider,
+            handshake: HandshakeOptions::new(),
+            max_handshakes: DEFAULT_MAX_HANDSHAKES,
+            handshakes: JoinSet::new(),
+        }
+    }
+
+    /// Run handshakes and treat accepted connections according to `options`.
+    #[must_use]
+    pub const fn with_handshake_options(mut self, options: HandshakeOptions) -> Self {
+        self.handshake = options;
+        self
+    }
+
+    /// Run at most `max` handshakes at once, 1024 by default, and stop
+    /// accepting from the listener while that many are in progress.
+    ///
+    /// A `max` of zero is treated as one.
+    #[must_use]
+    pub const fn with_max_concurrent_handshakes(mut self, max: usize) -> Self {
+        self.max_handshakes = if max == 0 { 1 } else { max };
+        self
+    }
+
+    /// Returns the local address of the listener.
+    ///
+    /// # Errors
+    /// The address of the listener cannot be read.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns the next connection to complete its handshake.
+    ///
+    /// # Errors
+    /// Accepting from the listener or a handshake fails; later connections
+    /// can still be accepted.
+    pub async fn accept(&mut self) -> io::Result<TlsConnection> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TlsConnection>> {
+        if let Poll::Ready(err) = self.poll_listener(cx) {
+            return Poll::Ready(Err(err));
+        }
+        self.poll_handshakes(cx)
+    }
+
+    /// Spawns a handshake for every connection accepted from the listener,
+    /// until it has none ready, fails or the maximum of concurrent
+    /// handshakes is reached.
+    ///
+    /// At the maximum the listener is not polled; the next handshake to
+    /// complete wakes the task instead.
+    fn poll_listener(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        while self.handshakes.len() < self.max_handshakes {
+            let Poll::Ready(accepted) = self.listener.poll_accept(cx) else {
+                break;
+            };
+            let (tcp, remote_addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => return Poll::Ready(err),
+            };
+            let acceptor = TlsAcceptor::from(self.provider.get_config());
+            let enforce_peer_expiry = self.handshake.enforces_peer_expiry();
+            self.handshakes.spawn(self.handshake.run(async move {
+                let tls = acceptor.accept(tcp).await?;
+                Ok(TlsConnection::new(tls, remote_addr, enforce_peer_expiry))
+            }));
+        }
+        Poll::Pending
+    }
+
+    fn poll_handshakes(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TlsConnection>> {
+        match self.handshakes.poll_join_next(cx) {
+            Poll::Ready(Some(Ok(handshake))) => Poll::Ready(handshake),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Err(io::Error::other(err))),
+            // the listener wakes the stream on the next connection
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for TlsIncoming {
+    type Item = io::Result<TlsConnection>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx).map(Some)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum::serve::Listener for TlsIncoming {
+    type Io = TlsConnection;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (TlsConnection, SocketAddr) {
+        loop {
+            let accepted = poll_fn(|cx| {
+                if let Poll::Ready(err) = self.poll_listener(cx) {
+                    return Poll::Ready(Err(err));
+                }
+                self.poll_handshakes(cx).map(Ok)
+            })
+            .await;
+            match accepted {
+                Ok(Ok(tls)) => {
+                    let remote_addr = tls.info.remote_addr;
+                    return (tls, remote_addr);
+                }
+                Ok(Err(err)) => {
+                    #[cfg(feature = "tracing")]
+                    debug!(name: "tls_incoming", error = %err, "TLS handshake failed");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = err;
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name: "tls_incoming", error = %err, "failed to accept connection");
+
+                    // back off, as axum does for its own listeners, so errors
+                    // such as too many open files do not spin
+                    if !matches!(
+                        err.kind(),
+                        io::ErrorKind::ConnectionRefused
+                            | io::ErrorKind::ConnectionAborted
+                            | io::ErrorKind::ConnectionReset
+                    ) {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Self::local_addr(self)
+    }
+}
+
+/// A TLS connection accepted by [`TlsIncoming`].
+pub struct TlsConnection {
+    tls: ExpiringStream<TlsStream<TcpStream>>,
+    info: PeerInfo,
+}
+
+/// The peer of a [`TlsConnection`], captured when it was accepted.
+///
+/// tonic inserts it into the extensions of every request received over the
+/// connection, as does axum when serving a router with
+/// `into_make_service_with_connect_info::<PeerInfo>()`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct PeerInfo {
+    /// The address of the peer.
+    pub remote_addr: SocketAddr,
+    /// The certificate chain presented by the peer, leaf first.
+    pub certs: Option<Arc<[CertificateDer<'static>]>>,
+    /// The SPIFFE ID of the peer, if it presented an X509-SVID.
+    pub spiffe_id: Option<SpiffeId>,
+}
+
+impl PeerInfo {
+    /// Returns the outcome of client authentication, e.g. for a
+    /// [`PeerIdentityLayer`](crate::PeerIdentityLayer).
+    #[must_use]
+    pub fn identity(&self) -> PeerIdentity {
+        match (&self.certs, &self.spiffe_id) {
+            (_, Some(spiffe_id)) => PeerIdentity::Authenticated(spiffe_id.clone()),
+            (Some(_), None) => PeerIdentity::Unidentified,
+            (None, None) => PeerIdentity::Anonymous,
+        }
+    }
+}
+
+impl TlsConnection {
+    fn new(tls: TlsStream<TcpStream>, remote_addr: SocketAddr, enforce_peer_expiry: bool) -> Self {
+        let certs: Option<Arc<[CertificateDer<'static>]>> = tls
+            .get_ref()
+            .1
+            .peer_certificates()
+            .map(|certs| certs.iter().map(|cert| cert.clone().into_owned()).collect());
+        let spiffe_id = extract_spiffe_id(certs.as_deref().and_then(<[_]>::first));
+        Self {
+            tls: ExpiringStream::accepted(tls, enforce_peer_expiry),
+            info: PeerInfo {
+                remote_addr,
+                certs,
+                spiffe_id,
+            },
+        }
+    }
+
+    /// Returns the TLS stream of the connection.
+    #[must_use]
+    pub const fn get_ref(&self) -> &TlsStream<TcpStream> {
+        self.tls.get_ref()
+    }
+
+    /// Returns the peer of the connection.
+    #[must_use]
+    pub const fn peer_info(&self) -> &PeerInfo {
+        &self.info
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.tls).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.tls).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.tls).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.tls).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use spiffe::SpiffeId;
+use tonic::{Request, Status, transport::server::Connected};
+
+use crate::{PeerInfo, TlsConnection};
+
+impl Connected for TlsConnection {
+    type ConnectInfo = PeerInfo;
+
+    fn connect_info(&self) -> PeerInfo {
+        self.peer_info().clone()
+    }
+}
+
+/// Returns the SPIFFE ID of the peer that sent `request` over a connection
+/// accepted by [`TlsIncoming`](crate::TlsIncoming), if it presented an
+/// X509-SVID.
+#[must_use]
+pub fn peer_spiffe_id<T>(request: &Request<T>) -> Option<SpiffeId> {
+    request.extensions().get::<PeerInfo>()?.spiffe_id.clone()
+}
+
+/// A tonic interceptor inserting the [`SpiffeId`] of the peer into the
+/// request extensions.
+///
+/// Handlers can then authorize by identity with
+/// `request.extensions().get::<SpiffeId>()`. Requests from peers without an
+/// SVID pass through without one; see [`peer_spiffe_id`].
+///
+/// # Errors
+/// Never; the `Result` is required of tonic interceptors.
+pub fn peer_spiffe_id_interceptor(mut request: Request<()>) -> Result<Request<()>, Status> {
+    if let Some(spiffe_id) = peer_spiffe_id(&request) {
+        request.extensions_mut().insert(spiffe_id);
+    }
+    Ok(request)
+}
@@ -1,27 +1,297 @@
 // SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
 
-use rustls::{RootCertStore, pki_types::CertificateDer};
-use spiffe::{TrustDomain, X509Bundle, X509BundleSet};
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, TrustAnchor},
+};
+use spiffe::{TrustDomain, X509BundleSet};
 use std::sync::Arc;
+use tokio::sync::watch;
 #[cfg(feature = "tracing")]
-use tracing::debug;
+use tracing::{debug, warn};
+use x509_parser::time::ASN1Time;
+
+use crate::SpiffeConfigError;
+
+/// Handle for changing the trust domains of running config streams.
+///
+/// Obtained from `SpiffeClientConfigStreamBuilder::trust_domain_handle` or
+/// `SpiffeServerConfigStreamBuilder::trust_domain_handle`. Changes apply to every
+/// stream built by that builder: each stream immediately yields a new config
+/// rebuilt from its most recent Workload API update, so federated trust domains
+/// can be added or removed without restarting the provider.
+#[derive(Clone, Debug)]
+pub struct TrustDomainHandle {
+    trust_domains: watch::Sender<Vec<TrustDomain>>,
+}
+
+impl TrustDomainHandle {
+    pub(crate) fn new(trust_domains: Vec<TrustDomain>) -> Self {
+        Self {
+            trust_domains: watch::Sender::new(trust_domains),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Vec<TrustDomain>> {
+        self.trust_domains.subscribe()
+    }
+
+    /// Returns the currently configured trust domains.
+    #[must_use]
+    pub fn trust_domains(&self) -> Vec<TrustDomain> {
+        self.trust_domains.borrow().clone()
+    }
+
+    /// Add a trust domain.
+    ///
+    /// Returns `false` if the trust domain was already configured.
+    #[allow(clippy::must_use_candidate)]
+    pub fn add(&self, trust_domain: TrustDomain) -> bool {
+        self.trust_domains.send_if_modified(|trust_domains| {
+            if trust_domains.contains(&trust_domain) {
+                return false;
+            }
+            trust_domains.push(trust_domain);
+            true
+        })
+    }
+
+    /// Remove a trust domain.
+    ///
+    /// Returns `false` if the trust domain was not configured.
+    #[allow(clippy::must_use_candidate)]
+    pub fn remove(&self, trust_domain: &TrustDomain) -> bool {
+        self.trust_domains.send_if_modified(|trust_domains| {
+            let len = trust_domains.len();
+            trust_domains.retain(|td| td != trust_domain);
+            trust_domains.len() != len
+        })
+    }
+
+    /// Replace the configured trust domains.
+    pub fn set(&self, trust_domains: Vec<TrustDomain>) {
+        self.trust_domains.send_if_modified(|current| {
+            if *current == trust_domains {
+                return false;
+            }
+            *current = trust_domains;
+            true
+        });
+    }
+}
+
+/// Options controlling which trust domains and bundle authorities end up in
+/// the root store built for each config.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RootStoreOptions {
+    /// Fail when a configured trust domain has no bundle in the bundle set.
+    pub strict: bool,
+    /// Also trust the trust domain of the workload's own SVID.
+    pub trust_local_domain: bool,
+    /// Filters applied to each bundle authority.
+    pub authorities: AuthorityFilter,
+}
+
+/// Checks a bundle authority must pass to be added to the root store.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuthorityFilter {
+    /// Skip authorities whose `notAfter` is in the past.
+    pub skip_expired: bool,
+    /// Skip authorities without a `CA=true` basicConstraints extension.
+    pub require_ca: bool,
+}
 
 pub trait TrustDomainStore {
-    fn get_trust_domains(&self) -> &Vec<TrustDomain>;
-    fn build_root_store(&self, bundles: &X509BundleSet) -> Arc<RootCertStore> {
-        let mut root_store = RootCertStore::empty();
-        let root_certs = self
-            .get_trust_domains()
-            .iter()
-            .filter_map(|domain| bundles.get_bundle(domain))
-            .flat_map(X509Bundle::authorities)
-            .map(|authority| CertificateDer::from_slice(authority.content()));
+    fn get_trust_domains(&self) -> Vec<TrustDomain>;
+
+    fn root_store_options(&self) -> RootStoreOptions;
+
+    /// Static trust anchors added to every root store next to the bundles.
+    fn extra_roots(&self) -> &[CertificateDer<'static>] {
+        &[]
+    }
+
+    /// Static, pre-parsed trust anchors added to every root store.
+    fn extra_trust_anchors(&self) -> &[TrustAnchor<'static>] {
+        &[]
+    }
+
+    fn build_root_store(
+        &self,
+        bundles: &X509BundleSet,
+        local: Option<&TrustDomain>,
+    ) -> Result<Arc<RootCertStore>, SpiffeConfigError> {
+        let options = self.root_store_options();
+        let configured = self.get_trust_domains();
+        let local = local.filter(|local| options.trust_local_domain && !configured.contains(local));
+        let mut root_store = root_store_for(bundles, configured.iter().chain(local), options)?;
+        if !self.extra_roots().is_empty() {
+            root_store.add_parsable_certificates(self.extra_roots().iter().cloned());
+        }
+        root_store.extend(self.extra_trust_anchors().iter().cloned());
+        Ok(Arc::new(root_store))
+    }
+}
+
+/// Build a [`RootCertStore`] from the bundles of the given trust domains.
+///
+/// Trust domains without a bundle are skipped, or fail with
+/// [`SpiffeConfigError::MissingBundle`] when [`RootStoreOptions::strict`] is set.
+pub fn root_store_for<'a>(
+    bundles: &X509BundleSet,
+    trust_domains: impl IntoIterator<Item = &'a TrustDomain>,
+    options: RootStoreOptions,
+) -> Result<RootCertStore, SpiffeConfigError> {
+    let now = ASN1Time::now();
+    #[cfg(feature = "tracing")]
+    let (mut expired, mut not_ca) = (0, 0);
+    let mut root_store = RootCertStore::empty();
+    let mut root_certs = Vec::new();
+    for domain in trust_domains {
+        let Some(bundle) = bundles.get_bundle(domain) else {
+            if options.strict {
+                return Err(SpiffeConfigError::MissingBundle(domain.clone()));
+            }
+
+            #[cfg(feature = "tracing")]
+            warn!(trust_domain = %domain, "no bundle for configured trust domain");
+
+            continue;
+        };
+        for authority in bundle.authorities() {
+            if options.authorities.skip_expired && is_expired(authority.content(), now) {
+                #[cfg(feature = "tracing")]
+                {
+                    expired += 1;
+                }
+                continue;
+            }
+            if options.authorities.require_ca && !is_ca(authority.content()) {
+                #[cfg(feature = "tracing")]
+                warn!(trust_domain = %domain, "skipping bundle authority without CA basicConstraints");
+
+                #[cfg(feature = "tracing")]
+                {
+                    not_ca += 1;
+                }
+                continue;
+            }
+            root_certs.push(CertificateDer::from_slice(authority.content()));
+        }
+    }
 
+    #[cfg(feature = "tracing")]
+    {
         let (added, ignored) = root_store.add_parsable_certificates(root_certs);
+        debug!(added, ignored, expired, not_ca);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    root_store.add_parsable_certificates(root_certs);
+
+    Ok(root_store)
+}
+
+/// Returns `true` if the DER-encoded certificate parses and its `notAfter` is
+/// before `now`. Unparsable certificates are left for
+/// [`RootCertStore::add_parsable_certificates`] to reject.
+fn is_expired(der: &[u8], now: ASN1Time) -> bool {
+    x509_parser::parse_x509_certificate(der).is_ok_and(|(_, cert)| cert.validity().not_after < now)
+}
+
+/// Returns `true` if the DER-encoded certificate parses and carries a
+/// basicConstraints extension with `CA=true`.
+fn is_ca(der: &[u8]) -> bool {
+    x509_parser::parse_x509_certificate(der).is_ok_and(|(_, cert)| {
+        cert.basic_constraints()
+            .is_ok_and(|bc| bc.is_some_and(|bc| bc.value.ca))
+    })
+}
+
+#[cfg(all(test, feature = "aws-lc-rs"))]
+mod tests {
+    use spiffe::TrustDomain;
+
+    use super::{AuthorityFilter, RootStoreOptions, root_store_for};
+    use crate::{
+        SourceError, SpiffeConfigError,
+        test_certs::{Ca, DAY, bundle_set},
+    };
+
+    #[test]
+    fn skips_trust_domains_without_a_bundle() -> Result<(), SourceError> {
+        let bundles = bundle_set(vec![Ca::root("root")?.bundle("example.org", false)?]);
+        let trust_domains = [
+            TrustDomain::new("example.org")?,
+            TrustDomain::new("other.org")?,
+        ];
+
+        let root_store = root_store_for(&bundles, &trust_domains, RootStoreOptions::default())?;
+        assert_eq!(root_store.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn strict_mode_fails_on_a_missing_bundle() -> Result<(), SourceError> {
+        let bundles = bundle_set(vec![Ca::root("root")?.bundle("example.org", false)?]);
+        let missing = TrustDomain::new("other.org")?;
+        let options = RootStoreOptions {
+            strict: true,
+            ..RootStoreOptions::default()
+        };
+
+        let result = root_store_for(&bundles, [&missing], options);
+        assert!(matches!(
+            result,
+            Err(SpiffeConfigError::MissingBundle(trust_domain)) if trust_domain == missing
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn skips_expired_authorities_when_asked() -> Result<(), SourceError> {
+        let (valid, expired) = (Ca::root("valid")?, Ca::expired_root("expired")?);
+        let mut bundle = valid.bundle("example.org", false)?;
+        for authority in expired.bundle("example.org", false)?.authorities() {
+            bundle.add_authority(authority.content())?;
+        }
+        let bundles = bundle_set(vec![bundle]);
+        let trust_domains = [TrustDomain::new("example.org")?];
+        let options = RootStoreOptions {
+            authorities: AuthorityFilter {
+                skip_expired: true,
+                ..AuthorityFilter::default()
+            },
+            ..RootStoreOptions::default()
+        };
+
+        let root_store = root_store_for(&bundles, &trust_domains, RootStoreOptions::default())?;
+        assert_eq!(root_store.len(), 2);
+        let root_store = root_store_for(&bundles, &trust_domains, options)?;
+        assert_eq!(root_store.len(), 1);
+        Ok(())
+    }
 
-        #[cfg(feature = "tracing")]
-        debug!(added, ignored);
+    #[test]
+    fn skips_authorities_that_are_not_cas_when_asked() -> Result<(), SourceError> {
+        let ca = Ca::root("root")?;
+        let mut bundle = ca.bundle("example.org", false)?;
+        let svid = ca.svid("spiffe://example.org/workload", DAY)?;
+        bundle.add_authority(svid.leaf().content())?;
+        let bundles = bundle_set(vec![bundle]);
+        let trust_domains = [TrustDomain::new("example.org")?];
+        let options = RootStoreOptions {
+            authorities: AuthorityFilter {
+                require_ca: true,
+                ..AuthorityFilter::default()
+            },
+            ..RootStoreOptions::default()
+        };
 
-        Arc::new(root_store)
+        let root_store = root_store_for(&bundles, &trust_domains, RootStoreOptions::default())?;
+        assert_eq!(root_store.len(), 2);
+        let root_store = root_store_for(&bundles, &trust_domains, options)?;
+        assert_eq!(root_store.len(), 1);
+        Ok(())
     }
 }
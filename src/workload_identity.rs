@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use spiffe::{SpiffeId, X509Svid};
+
+/// The identity presented by a config stream, taken from the SVID of its most
+/// recent config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WorkloadIdentity {
+    /// The SPIFFE ID of the SVID.
+    pub spiffe_id: SpiffeId,
+    /// The serial number of the leaf certificate, big-endian.
+    pub serial: Vec<u8>,
+    /// The `notBefore` of the leaf certificate.
+    pub not_before: SystemTime,
+    /// The `notAfter` of the leaf certificate.
+    pub not_after: SystemTime,
+}
+
+impl WorkloadIdentity {
+    /// Read the identity of `svid`, or `None` if its leaf certificate does not
+    /// parse.
+    pub(crate) fn from_svid(svid: &X509Svid) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(svid.leaf().content()).ok()?;
+        let validity = cert.validity();
+        Some(Self {
+            spiffe_id: svid.spiffe_id().clone(),
+            serial: cert.raw_serial().to_vec(),
+            not_before: system_time(validity.not_before.timestamp()),
+            not_after: system_time(validity.not_after.timestamp()),
+        })
+    }
+
+    /// When the SVID reaches half of its lifetime, at which the SPIRE agent
+    /// rotates it.
+    ///
+    /// A new SVID is expected from the Workload API shortly after; schedule
+    /// pre-rotation work, such as recycling connection pools, for this time
+    /// rather than reacting to the rotation.
+    #[must_use]
+    pub fn renew_at(&self) -> SystemTime {
+        let lifetime = self
+            .not_after
+            .duration_since(self.not_before)
+            .unwrap_or_default();
+        self.not_before + lifetime / 2
+    }
+
+    /// The time left until [`renew_at`](Self::renew_at), zero once it has
+    /// passed.
+    #[must_use]
+    pub fn until_renewal(&self) -> Duration {
+        self.renew_at()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+
+    /// The time left until [`not_after`](Self::not_after), zero once the SVID
+    /// has expired.
+    #[must_use]
+    pub fn until_expiry(&self) -> Duration {
+        self.not_after
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+}
+
+fn system_time(timestamp: i64) -> SystemTime {
+    u64::try_from(timestamp).map_or(SystemTime::UNIX_EPOCH, |secs| {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    })
+}
+
+/// The identity adopted by a config stream on rotation, passed to the hooks
+/// registered with `on_rotation` on the config stream builders.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RotationInfo {
+    /// The identity of the new config.
+    pub identity: WorkloadIdentity,
+    /// The identity of the previous config, or `None` for the first config.
+    pub previous: Option<WorkloadIdentity>,
+}
+
+/// A hook registered with `on_rotation`.
+pub type RotationHook = Arc<dyn Fn(&RotationInfo) + Send + Sync>;
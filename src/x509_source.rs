@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+
+use std::sync::Arc;
+
+use spiffe::{BundleSource, SvidSource as _, TrustDomain, X509BundleSet, X509Context, X509Source};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    SourceError,
+    svid_source::{ConnectFuture, SvidSource, X509ContextStream},
+};
+
+/// An [`SvidSource`] reading from a shared [`X509Source`] from the `spiffe`
+/// crate, so applications that already use one don't open a second Workload
+/// API stream.
+///
+/// An [`X509Source`] only hands out bundles by trust domain, so each update
+/// holds the bundles of the trust domains watched through `trust_domains`,
+/// the fixed `extra` trust domains and the trust domain of the SVID picked by
+/// the source. A new update is yielded whenever the source updates or the
+/// watched trust domains change.
+#[derive(Clone, Debug)]
+pub struct SharedX509Source {
+    source: Arc<X509Source>,
+    trust_domains: watch::Receiver<Vec<TrustDomain>>,
+    extra: Vec<TrustDomain>,
+}
+
+impl SharedX509Source {
+    /// Read the SVID and bundles of `trust_domains` and `extra` from `source`.
+    #[must_use]
+    pub const fn new(
+        source: Arc<X509Source>,
+        trust_domains: watch::Receiver<Vec<TrustDomain>>,
+        extra: Vec<TrustDomain>,
+    ) -> Self {
+        Self {
+            source,
+            trust_domains,
+            extra,
+        }
+    }
+
+    fn x509_context(&self) -> Result<X509Context, SourceError> {
+        let svid = self.source.get_svid()?.ok_or("X509Source has no SVID")?;
+        let mut trust_domains = self.trust_domains.borrow().clone();
+        trust_domains.extend(self.extra.iter().cloned());
+        trust_domains.push(svid.spiffe_id().trust_domain().clone());
+        let mut bundles = X509BundleSet::new();
+        for trust_domain in &trust_domains {
+            if let Some(bundle) = self.source.get_bundle_for_trust_domain(trust_domain)? {
+                bundles.add_bundle(bundle);
+            }
+        }
+        Ok(X509Context::new(vec![svid], bundles))
+    }
+
+    async fn run(mut self, tx: mpsc::Sender<Result<X509Context, SourceError>>) {
+        let mut updated = self.source.updated();
+        loop {
+            let changed = tokio::select! {
+                changed = updated.changed() => changed.is_ok(),
+                changed = self.trust_domains.changed() => changed.is_ok(),
+                () = tx.closed() => return,
+            };
+            if !changed {
+                return;
+            }
+            let update = self.x509_context();
+            let failed = update.is_err();
+            if tx.send(update).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+impl SvidSource for SharedX509Source {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let initial = self.x509_context()?;
+            let (tx, rx) = mpsc::channel(1);
+            tx.send(Ok(initial)).await?;
+            let mut this = self.clone();
+            this.trust_domains.mark_unchanged();
+            tokio::spawn(this.run(tx));
+            Ok(Box::pin(ReceiverStream::new(rx)) as X509ContextStream)
+        })
+    }
+}